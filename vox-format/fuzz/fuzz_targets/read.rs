@@ -0,0 +1,50 @@
+//! Reads arbitrary data as VOX file and as scene graph nodes. This must never
+//! panic, and never allocate more memory than the limits allow.
+//!
+//! Run with:
+//!
+//! ```sh
+//! cargo +nightly fuzz run read -- -rss_limit_mb=256 -malloc_limit_mb=128
+//! ```
+
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+use vox_format::{
+    reader::{
+        from_reader_with_options,
+        Limits,
+        ReaderOptions,
+    },
+    types::{
+        Attributes,
+        Group,
+        Layer,
+        Shape,
+        Transform,
+    },
+};
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits::untrusted();
+
+    let options = ReaderOptions {
+        limits: limits.clone(),
+        ..ReaderOptions::lenient()
+    };
+    let _ = from_reader_with_options(Cursor::new(data), &options);
+
+    let options = ReaderOptions {
+        limits: limits.clone(),
+        ..ReaderOptions::strict()
+    };
+    let _ = from_reader_with_options(Cursor::new(data), &options);
+
+    let _ = Attributes::read_with_limits(data, &limits);
+    let _ = Transform::read_with_limits(data, &limits);
+    let _ = Group::read_with_limits(data, &limits);
+    let _ = Shape::read_with_limits(data, &limits);
+    let _ = Layer::read_with_limits(data, &limits);
+});
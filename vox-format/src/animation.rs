@@ -0,0 +1,424 @@
+//! Keyframe animations of transform and shape nodes.
+//!
+//! MagicaVoxel stores animations in the frames of [`Transform`] nodes and in
+//! the models of [`Shape`] nodes. Each of them is a keyframe, whose frame
+//! index is stored in the `_f` attribute. Keyframes are sparse, so a node
+//! might only have keyframes at frames 0 and 10.
+//!
+//! When evaluating a node at some frame, the translation is interpolated
+//! linearly between the surrounding keyframes. Rotations and models can't be
+//! interpolated, so the last keyframe at or before the frame is held. Before
+//! the first keyframe, the first keyframe is held, and after the last one, the
+//! last one is held. If the node's `_loop` attribute is set, the animation
+//! instead repeats after its last keyframe.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{animation::TransformKeyframe, types::{Attributes, Rotation, Transform, Vector}};
+//! let mut transform = Transform {
+//!     node_id: 0,
+//!     attributes: Attributes::new(),
+//!     child_node_id: 1,
+//!     reserved_id: None,
+//!     layer_id: None,
+//!     frames: vec![],
+//! };
+//! transform.set_keyframes(vec![
+//!     TransformKeyframe {
+//!         frame_index: 0,
+//!         translation: Vector::new(0, 0, 0),
+//!         rotation: Rotation::IDENTITY,
+//!     },
+//!     TransformKeyframe {
+//!         frame_index: 10,
+//!         translation: Vector::new(10, 0, 0),
+//!         rotation: Rotation::IDENTITY,
+//!     },
+//! ]);
+//!
+//! let pose = transform.pose_at(5).unwrap();
+//! assert_eq!(pose.translation, Vector::new(5, 0, 0));
+//! ```
+
+use thiserror::Error;
+
+use crate::types::{
+    Attributes,
+    FrameIndexParseError,
+    LoopParseError,
+    Rotation,
+    RotationParseError,
+    Shape,
+    ShapeModel,
+    Transform,
+    TranslationParseError,
+    Vector,
+};
+
+/// Error returned when the animation attributes of a node are invalid.
+#[derive(Debug, Error)]
+pub enum AnimationError {
+    #[error("{0}")]
+    Translation(#[from] TranslationParseError),
+
+    #[error("{0}")]
+    Rotation(#[from] RotationParseError),
+
+    #[error("{0}")]
+    FrameIndex(#[from] FrameIndexParseError),
+
+    #[error("{0}")]
+    Loop(#[from] LoopParseError),
+}
+
+/// A keyframe of a [`Transform`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TransformKeyframe {
+    pub frame_index: u32,
+    pub translation: Vector<i32>,
+    pub rotation: Rotation,
+}
+
+/// A keyframe of a [`Shape`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ModelKeyframe {
+    pub frame_index: u32,
+
+    /// The index of the model that is displayed from this frame on.
+    pub model_id: u32,
+}
+
+/// The translation and rotation of a [`Transform`] at some frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pose {
+    pub translation: Vector<i32>,
+    pub rotation: Rotation,
+}
+
+impl Transform {
+    /// Returns the keyframes sorted by frame index.
+    pub fn keyframes(&self) -> Result<Vec<TransformKeyframe>, AnimationError> {
+        let mut keyframes = self
+            .frames
+            .iter()
+            .map(|frame| {
+                Ok(TransformKeyframe {
+                    frame_index: frame.frame_index()?.unwrap_or_default(),
+                    translation: frame.translation()?.unwrap_or_default(),
+                    rotation: frame.rotation()?.unwrap_or_default(),
+                })
+            })
+            .collect::<Result<Vec<_>, AnimationError>>()?;
+        keyframes.sort_by_key(|keyframe| keyframe.frame_index);
+        Ok(keyframes)
+    }
+
+    /// Replaces the frames with `keyframes`. Other attributes of the frames
+    /// are removed.
+    ///
+    /// Like MagicaVoxel, this leaves out zero translations, identity rotations
+    /// and the frame index of a single keyframe at frame 0.
+    pub fn set_keyframes(&mut self, keyframes: impl IntoIterator<Item = TransformKeyframe>) {
+        let mut keyframes = keyframes.into_iter().collect::<Vec<_>>();
+        keyframes.sort_by_key(|keyframe| keyframe.frame_index);
+        let animated = is_animated(keyframes.iter().map(|keyframe| keyframe.frame_index));
+
+        self.frames = keyframes
+            .into_iter()
+            .map(|keyframe| {
+                let mut frame = Attributes::new();
+                if keyframe.rotation != Rotation::IDENTITY {
+                    frame.set_rotation(keyframe.rotation);
+                }
+                if keyframe.translation != Vector::default() {
+                    frame.set_translation(keyframe.translation);
+                }
+                if animated {
+                    frame.set_frame_index(keyframe.frame_index);
+                }
+                frame
+            })
+            .collect();
+    }
+
+    /// Returns whether the animation repeats after its last keyframe.
+    pub fn is_looping(&self) -> Result<bool, AnimationError> {
+        Ok(self.attributes.looping()?.unwrap_or_default())
+    }
+
+    /// Sets whether the animation repeats after its last keyframe.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.attributes.set_looping(looping);
+    }
+
+    /// Evaluates the animation at `frame`. A transform without frames has
+    /// the identity pose.
+    pub fn pose_at(&self, frame: u32) -> Result<Pose, AnimationError> {
+        let keyframes = self.keyframes()?;
+        let frame_indices = keyframes
+            .iter()
+            .map(|keyframe| keyframe.frame_index)
+            .collect::<Vec<_>>();
+
+        let pose = match sample(&frame_indices, frame, self.is_looping()?) {
+            None => Pose::default(),
+            Some(Sample::Hold(i)) => {
+                Pose {
+                    translation: keyframes[i].translation,
+                    rotation: keyframes[i].rotation,
+                }
+            }
+            Some(Sample::Between(i, t)) => {
+                let (a, b) = (keyframes[i].translation, keyframes[i + 1].translation);
+                let lerp = |a: i32, b: i32| {
+                    (f64::from(a) + (f64::from(b) - f64::from(a)) * t).round() as i32
+                };
+                Pose {
+                    translation: Vector::new(lerp(a.x, b.x), lerp(a.y, b.y), lerp(a.z, b.z)),
+                    rotation: keyframes[i].rotation,
+                }
+            }
+        };
+
+        Ok(pose)
+    }
+}
+
+impl Shape {
+    /// Returns the model keyframes sorted by frame index.
+    pub fn model_keyframes(&self) -> Result<Vec<ModelKeyframe>, AnimationError> {
+        let mut keyframes = self
+            .models
+            .iter()
+            .map(|model| {
+                Ok(ModelKeyframe {
+                    frame_index: model.attributes.frame_index()?.unwrap_or_default(),
+                    model_id: model.model_id,
+                })
+            })
+            .collect::<Result<Vec<_>, AnimationError>>()?;
+        keyframes.sort_by_key(|keyframe| keyframe.frame_index);
+        Ok(keyframes)
+    }
+
+    /// Replaces the models with `keyframes`. Other attributes of the models
+    /// are removed. The frame index of a single keyframe at frame 0 is left
+    /// out.
+    pub fn set_model_keyframes(&mut self, keyframes: impl IntoIterator<Item = ModelKeyframe>) {
+        let mut keyframes = keyframes.into_iter().collect::<Vec<_>>();
+        keyframes.sort_by_key(|keyframe| keyframe.frame_index);
+        let animated = is_animated(keyframes.iter().map(|keyframe| keyframe.frame_index));
+
+        self.models = keyframes
+            .into_iter()
+            .map(|keyframe| {
+                let mut attributes = Attributes::new();
+                if animated {
+                    attributes.set_frame_index(keyframe.frame_index);
+                }
+                ShapeModel {
+                    model_id: keyframe.model_id,
+                    attributes,
+                }
+            })
+            .collect();
+    }
+
+    /// Returns whether the animation repeats after its last keyframe.
+    pub fn is_looping(&self) -> Result<bool, AnimationError> {
+        Ok(self.attributes.looping()?.unwrap_or_default())
+    }
+
+    /// Sets whether the animation repeats after its last keyframe.
+    pub fn set_looping(&mut self, looping: bool) {
+        self.attributes.set_looping(looping);
+    }
+
+    /// Returns the model that is displayed at `frame`, or `None`, if the shape
+    /// has no models.
+    pub fn model_at(&self, frame: u32) -> Result<Option<u32>, AnimationError> {
+        let keyframes = self.model_keyframes()?;
+        let frame_indices = keyframes
+            .iter()
+            .map(|keyframe| keyframe.frame_index)
+            .collect::<Vec<_>>();
+
+        Ok(
+            sample(&frame_indices, frame, self.is_looping()?).map(|sample| {
+                match sample {
+                    Sample::Hold(i) | Sample::Between(i, _) => keyframes[i].model_id,
+                }
+            }),
+        )
+    }
+}
+
+/// Returns whether keyframes with these frame indices need to store them.
+fn is_animated(mut frame_indices: impl ExactSizeIterator<Item = u32>) -> bool {
+    frame_indices.len() > 1 || frame_indices.any(|frame_index| frame_index != 0)
+}
+
+/// Where a frame lies relative to the keyframes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Sample {
+    /// The value of the keyframe with this index is held.
+    Hold(usize),
+
+    /// The frame lies between the keyframe with this index and the next one.
+    /// The `f64` is the relative position between them.
+    Between(usize, f64),
+}
+
+/// Finds the keyframes for `frame`. `frame_indices` must be sorted. Returns
+/// `None`, if there are no keyframes.
+fn sample(frame_indices: &[u32], frame: u32, looping: bool) -> Option<Sample> {
+    let last = *frame_indices.last()?;
+    let frame = if looping {
+        frame % last.saturating_add(1)
+    }
+    else {
+        frame
+    };
+
+    // The number of keyframes at or before `frame`.
+    let num_before = frame_indices.partition_point(|frame_index| *frame_index <= frame);
+    if num_before == 0 {
+        return Some(Sample::Hold(0));
+    }
+
+    let i = num_before - 1;
+    match frame_indices.get(i + 1) {
+        Some(next) if frame_indices[i] < frame => {
+            let t = f64::from(frame - frame_indices[i]) / f64::from(next - frame_indices[i]);
+            Some(Sample::Between(i, t))
+        }
+        _ => Some(Sample::Hold(i)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ModelKeyframe,
+        TransformKeyframe,
+    };
+    use crate::{
+        from_slice,
+        to_vec,
+        types::{
+            Attributes,
+            Model,
+            Node,
+            Rotation,
+            Shape,
+            Transform,
+            Vector,
+        },
+        writer::{
+            to_vec_with_options,
+            WriterOptions,
+        },
+        VoxData,
+    };
+
+    fn transform() -> Transform {
+        let mut transform = Transform {
+            node_id: 0,
+            attributes: Attributes::new(),
+            child_node_id: 1,
+            reserved_id: None,
+            layer_id: None,
+            frames: vec![],
+        };
+        let rotation = Rotation::from_matrix([[0, -1, 0], [1, 0, 0], [0, 0, 1]]).unwrap();
+        transform.set_keyframes(vec![
+            TransformKeyframe {
+                frame_index: 12,
+                translation: Vector::new(10, -10, 4),
+                rotation,
+            },
+            TransformKeyframe {
+                frame_index: 2,
+                translation: Vector::new(0, 0, 4),
+                rotation: Rotation::IDENTITY,
+            },
+        ]);
+        transform
+    }
+
+    #[test]
+    fn it_interpolates_translations_and_holds_rotations() {
+        let transform = transform();
+        assert_eq!(transform.frames[0].get("_f"), Some("2"));
+        assert_eq!(transform.frames[0].get("_r"), None);
+
+        let pose = transform.pose_at(0).unwrap();
+        assert_eq!(pose.translation, Vector::new(0, 0, 4));
+        assert_eq!(pose.rotation, Rotation::IDENTITY);
+
+        let pose = transform.pose_at(7).unwrap();
+        assert_eq!(pose.translation, Vector::new(5, -5, 4));
+        assert_eq!(pose.rotation, Rotation::IDENTITY);
+
+        let pose = transform.pose_at(100).unwrap();
+        assert_eq!(pose.translation, Vector::new(10, -10, 4));
+        assert_ne!(pose.rotation, Rotation::IDENTITY);
+    }
+
+    #[test]
+    fn it_loops() {
+        let mut transform = transform();
+        transform.set_looping(true);
+        assert_eq!(
+            transform.pose_at(13 + 7).unwrap(),
+            transform.pose_at(7).unwrap()
+        );
+    }
+
+    #[test]
+    fn it_writes_animated_scenes() {
+        let mut vox = VoxData::default();
+        for _ in 0..2 {
+            vox.models.push(Model {
+                size: Vector::new(1, 1, 1),
+                voxels: vec![],
+            });
+        }
+
+        let mut shape = Shape {
+            node_id: 1,
+            attributes: Attributes::new(),
+            models: vec![],
+        };
+        shape.set_model_keyframes(vec![
+            ModelKeyframe {
+                frame_index: 0,
+                model_id: 0,
+            },
+            ModelKeyframe {
+                frame_index: 5,
+                model_id: 1,
+            },
+        ]);
+        vox.nodes.push(Node::Transform(transform()));
+        vox.nodes.push(Node::Shape(shape));
+
+        for data in [
+            to_vec(&vox).unwrap(),
+            to_vec_with_options(&vox, &WriterOptions::canonical()).unwrap(),
+        ] {
+            let vox = from_slice(&data).unwrap();
+            let (transform, shape) = match &vox.nodes[..] {
+                [Node::Transform(transform), Node::Shape(shape)] => (transform, shape),
+                nodes => panic!("Unexpected nodes: {:?}", nodes),
+            };
+            assert_eq!(
+                transform.pose_at(7).unwrap().translation,
+                Vector::new(5, -5, 4)
+            );
+            assert_eq!(shape.model_at(4).unwrap(), Some(0));
+            assert_eq!(shape.model_at(5).unwrap(), Some(1));
+        }
+    }
+}
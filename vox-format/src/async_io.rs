@@ -0,0 +1,266 @@
+//! Reads and writes VOX files with [`tokio`]'s asynchronous I/O traits.
+//!
+//! The chunk parser works on synchronous readers, so this doesn't stream the
+//! file chunk by chunk. Instead the whole file is first read asynchronously
+//! into memory and then parsed on tokio's blocking thread pool, which needs a
+//! tokio runtime. This trades memory for simplicity: A file needs as much
+//! memory as it is large, in addition to the decoded data. Since the file is
+//! buffered, the reader doesn't need to implement `AsyncSeek`.
+//!
+//! The length of the `MAIN` chunk is read from its header first, so that it
+//! can be checked against [`crate::reader::Limits::max_chunk_size`] before
+//! anything is buffered. [`from_async_reader`] buffers at most
+//! [`DEFAULT_MAX_BUFFER_LEN`] bytes. Writing works the other way around: The
+//! file is encoded into a buffer, which is then written asynchronously.
+//!
+//! # Example
+//!
+//! ```
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() {
+//! # let mut vox = vox_format::VoxData::default();
+//! # vox.models.push(vox_format::types::Model {
+//! #     size: vox_format::types::Vector::new(1, 1, 1),
+//! #     voxels: vec![vox_format::types::Voxel::new([0, 0, 0], 1)],
+//! # });
+//! let mut data = vec![];
+//! vox_format::to_async_writer(&mut data, &vox).await.unwrap();
+//!
+//! let vox = vox_format::from_async_reader(&data[..]).await.unwrap();
+//! assert_eq!(vox.models.len(), 1);
+//! # }
+//! ```
+//!
+//! [`tokio`]: https://docs.rs/tokio
+
+use std::io::Cursor;
+
+use tokio::{
+    io::{
+        AsyncRead,
+        AsyncReadExt,
+        AsyncWrite,
+        AsyncWriteExt,
+    },
+    task::spawn_blocking,
+};
+
+use crate::{
+    chunk::Chunk,
+    data::VoxData,
+    reader::{
+        from_reader_with_options,
+        Error as ReadError,
+        Limits,
+        Problem,
+        ReaderOptions,
+    },
+    writer::{
+        to_vec,
+        Error as WriteError,
+    },
+};
+
+/// Length of the file header (magic and version) and the `MAIN` chunk's
+/// header.
+const HEADER_LEN: usize = 8 + 12;
+
+/// The maximum length of the `MAIN` chunk that [`from_async_reader`] reads
+/// into memory. Larger files can be read with
+/// [`from_async_reader_with_options`] and a larger
+/// [`crate::reader::Limits::max_chunk_size`].
+pub const DEFAULT_MAX_BUFFER_LEN: u32 = 256 * 1024 * 1024;
+
+/// Reads a VOX file from an asynchronous reader into
+/// [`crate::data::VoxData`].
+///
+/// The whole file is buffered in memory, so files whose `MAIN` chunk is
+/// larger than [`DEFAULT_MAX_BUFFER_LEN`] are rejected.
+pub async fn from_async_reader<R: AsyncRead + Unpin>(reader: R) -> Result<VoxData, ReadError> {
+    let options = ReaderOptions {
+        limits: Limits {
+            max_chunk_size: DEFAULT_MAX_BUFFER_LEN,
+            ..Limits::default()
+        },
+        ..ReaderOptions::default()
+    };
+    let (vox, _) = from_async_reader_with_options(reader, &options).await?;
+    Ok(vox)
+}
+
+/// Reads a VOX file from an asynchronous reader into
+/// [`crate::data::VoxData`], validating it as specified by `options`. See
+/// [`crate::reader::from_reader_with_options`].
+///
+/// The whole file is buffered in memory, up to the `MAIN` chunk's length,
+/// which is limited by [`crate::reader::Limits::max_chunk_size`]. With the
+/// default limits that's up to 4 GiB, so set a lower limit, if you don't
+/// trust the file. Compressed files are read until the end of the stream,
+/// up to the same limit.
+pub async fn from_async_reader_with_options<R: AsyncRead + Unpin>(
+    reader: R,
+    options: &ReaderOptions,
+) -> Result<(VoxData, Vec<Problem>), ReadError> {
+    let data = read_file(reader, options).await?;
+
+    // Parsing is CPU-bound, so we don't do it on the executor.
+    let options = options.clone();
+    let result =
+        spawn_blocking(move || from_reader_with_options(Cursor::new(data), &options)).await;
+    match result {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        Err(error) => Err(std::io::Error::from(error).into()),
+    }
+}
+
+/// Reads the file header and the `MAIN` chunk, but nothing after it.
+async fn read_file<R: AsyncRead + Unpin>(
+    mut reader: R,
+    options: &ReaderOptions,
+) -> Result<Vec<u8>, ReadError> {
+    let mut data = Vec::with_capacity(HEADER_LEN);
+    (&mut reader)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut data)
+        .await?;
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if crate::compression::Compression::detect(&data).is_some() {
+        // The length of the decompressed data is unknown, so we read the whole
+        // file. It's decompressed and checked against the limits by the
+        // parser.
+        let max_len = u64::from(options.limits.max_chunk_size).saturating_add(8);
+        reader.take(max_len).read_to_end(&mut data).await?;
+        return Ok(data);
+    }
+
+    if data.len() < HEADER_LEN {
+        // Let the parser report the truncated header.
+        return Ok(data);
+    }
+
+    let main_chunk = Chunk::read(Cursor::new(&data[8..]))?;
+    options.limits.check_chunk(&main_chunk, 1)?;
+
+    // We don't reserve the length from the header, since the file might be much
+    // shorter. A truncated file is read as far as possible, so that the parser
+    // can recover from it, if `options` allow it.
+    let remaining = main_chunk.len() - 12;
+    reader.take(remaining).read_to_end(&mut data).await?;
+
+    Ok(data)
+}
+
+/// Writes VOX data to an asynchronous writer.
+pub async fn to_async_writer<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    vox: &VoxData,
+) -> Result<(), WriteError> {
+    let data = to_vec(vox)?;
+    writer.write_all(&data).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_async_reader,
+        from_async_reader_with_options,
+        to_async_writer,
+    };
+    use crate::{
+        reader::{
+            Error,
+            LimitKind,
+            Limits,
+            ReaderOptions,
+        },
+        to_vec,
+        types::{
+            Model,
+            Vector,
+            Voxel,
+        },
+        VoxData,
+    };
+
+    fn vox() -> VoxData {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 2, 2),
+            voxels: vec![Voxel::new([0, 1, 0], 1), Voxel::new([1, 1, 1], 2)],
+        });
+        vox
+    }
+
+    #[tokio::test]
+    async fn it_round_trips() {
+        let mut data = vec![];
+        to_async_writer(&mut data, &vox()).await.unwrap();
+        assert_eq!(data, to_vec(&vox()).unwrap());
+
+        // Trailing data after the `MAIN` chunk is not read.
+        data.extend_from_slice(b"trailing");
+        let mut reader = &data[..];
+        let read = from_async_reader(&mut reader).await.unwrap();
+        assert_eq!(read.models[0].voxels, vox().models[0].voxels);
+        assert_eq!(reader, b"trailing");
+    }
+
+    #[tokio::test]
+    async fn it_checks_the_limits_before_buffering() {
+        let data = to_vec(&vox()).unwrap();
+        let options = ReaderOptions {
+            limits: Limits {
+                max_chunk_size: 16,
+                ..Limits::default()
+            },
+            ..ReaderOptions::default()
+        };
+        let error = from_async_reader_with_options(&data[..], &options)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::LimitExceeded {
+                kind: LimitKind::ChunkSize,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_reports_files_shorter_than_their_main_chunk() {
+        // A `MAIN` chunk that claims to be almost 4 GiB long, but has no data.
+        let mut data = b"VOX ".to_vec();
+        data.extend_from_slice(&150u32.to_le_bytes());
+        data.extend_from_slice(b"MAIN");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&(u32::MAX - 12).to_le_bytes());
+
+        // By default we don't buffer that much.
+        let error = from_async_reader(&data[..]).await.unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::LimitExceeded {
+                kind: LimitKind::ChunkSize,
+                ..
+            }
+        ));
+
+        // Without limits, the missing data is reported.
+        let error = from_async_reader_with_options(&data[..], &ReaderOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error.root(), Error::Io(_)));
+    }
+
+    #[tokio::test]
+    async fn it_reports_truncated_files() {
+        let data = to_vec(&vox()).unwrap();
+        assert!(from_async_reader(&data[..data.len() - 4]).await.is_err());
+        assert!(from_async_reader(&data[..10]).await.is_err());
+    }
+}
@@ -0,0 +1,274 @@
+//! Baking a scene into a single model.
+//!
+//! [`bake`] places the voxels of every shape as MagicaVoxel shows them, i.e.
+//! with the translations and rotations of all transforms applied, and collects
+//! them into one model. The model covers the bounding box of all voxels, which
+//! must fit into 256³ voxels.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{bake::{bake, OverlapPolicy}, types::{Model, Vector, Voxel}, VoxData};
+//! let mut vox = VoxData::default();
+//! vox.models.push(Model {
+//!     size: Vector::new(2, 1, 1),
+//!     voxels: vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 0, 0], 2)],
+//! });
+//!
+//! let mut scene = vox.scene().unwrap();
+//! let copy = scene.add_shape(scene.root(), 0).unwrap();
+//! scene.get_mut(copy).unwrap().transform.frames[0].set_translation(Vector::new(1, 0, 0));
+//! vox.set_scene(&scene).unwrap();
+//!
+//! let model = bake(&vox, &OverlapPolicy::LastWins).unwrap();
+//! assert_eq!(model.size, Vector::new(3, 1, 1));
+//! assert_eq!(model.voxels.len(), 3);
+//! ```
+
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+use crate::{
+    animation::AnimationError,
+    data::VoxData,
+    scene::{
+        for_each_voxel,
+        ForEachVoxelError,
+        SceneError,
+    },
+    tiling::MAX_MODEL_SIZE,
+    types::{
+        Model,
+        Vector,
+        Voxel,
+    },
+};
+
+/// Error type for [`bake`].
+#[derive(Debug, Error)]
+pub enum BakeError {
+    /// The voxels don't fit into a single model.
+    #[error("The baked model would be {size:?} voxels large, but models can be at most {} voxels large.", MAX_MODEL_SIZE)]
+    TooLarge { size: Vector<u64> },
+
+    #[error("Shape refers to model {model_id}, which doesn't exist.")]
+    InvalidModelId { model_id: u32 },
+
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+}
+
+impl From<ForEachVoxelError> for BakeError {
+    fn from(e: ForEachVoxelError) -> Self {
+        match e {
+            ForEachVoxelError::InvalidModelId { model_id } => Self::InvalidModelId { model_id },
+            ForEachVoxelError::Scene(e) => e.into(),
+            ForEachVoxelError::Animation(e) => e.into(),
+        }
+    }
+}
+
+/// Decides which voxel is kept, if the voxels of several shapes end up at the
+/// same position.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverlapPolicy {
+    /// Keep the voxel of the shape that comes last in the scene graph.
+    LastWins,
+
+    /// Keep the voxel of the shape that comes first in the scene graph.
+    FirstWins,
+
+    /// Keep the voxel of the shape whose layer comes first in this list of
+    /// layer IDs. Layers that aren't listed have the lowest priority. Between
+    /// shapes with the same priority, the last one wins.
+    LayerPriority(Vec<u32>),
+}
+
+/// Bakes all shapes into a single model. See the [module
+/// documentation](self).
+///
+/// The voxel with the smallest coordinates in the scene is at `(0, 0, 0)` in
+/// the model. If there are no voxels, the model is empty and has size
+/// `(1, 1, 1)`.
+pub fn bake(vox: &VoxData, policy: &OverlapPolicy) -> Result<Model, BakeError> {
+    // For each position the priority and color of the kept voxel. Lower values
+    // have a higher priority.
+    let mut voxels = HashMap::new();
+
+    for_each_voxel(vox, 0, |object, point, color_index| {
+        let priority = match policy {
+            OverlapPolicy::LastWins | OverlapPolicy::FirstWins => 0,
+            OverlapPolicy::LayerPriority(layers) => {
+                object
+                    .layer_id()
+                    .and_then(|layer_id| layers.iter().position(|id| *id == layer_id))
+                    .unwrap_or(layers.len())
+            }
+        };
+
+        let replace = match voxels.get(&point) {
+            None => true,
+            Some((_, _)) if *policy == OverlapPolicy::FirstWins => false,
+            Some((existing, _)) => priority <= *existing,
+        };
+        if replace {
+            voxels.insert(point, (priority, color_index));
+        }
+    })?;
+
+    let mut points = voxels.keys();
+    let (min, max) = match points.next() {
+        Some(first) => {
+            points.fold((*first, *first), |(min, max), p| {
+                (
+                    Vector::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                    Vector::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+                )
+            })
+        }
+        None => {
+            return Ok(Model {
+                size: Vector::new(1, 1, 1),
+                voxels: vec![],
+            })
+        }
+    };
+
+    let extent = |min: i32, max: i32| (i64::from(max) - i64::from(min) + 1) as u64;
+    let size = Vector::new(
+        extent(min.x, max.x),
+        extent(min.y, max.y),
+        extent(min.z, max.z),
+    );
+    let max_size = u64::from(MAX_MODEL_SIZE);
+    if size.x > max_size || size.y > max_size || size.z > max_size {
+        return Err(BakeError::TooLarge { size });
+    }
+
+    let mut voxels = voxels
+        .into_iter()
+        .map(|(point, (_, color_index))| {
+            Voxel {
+                point: Vector::new(
+                    (point.x - min.x) as u8 as i8,
+                    (point.y - min.y) as u8 as i8,
+                    (point.z - min.z) as u8 as i8,
+                ),
+                color_index,
+            }
+        })
+        .collect::<Vec<_>>();
+    voxels.sort_by_key(|voxel| {
+        let point = voxel.point;
+        (point.z as u8, point.y as u8, point.x as u8)
+    });
+
+    Ok(Model {
+        size: Vector::new(size.x as u32, size.y as u32, size.z as u32),
+        voxels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bake,
+        BakeError,
+        OverlapPolicy,
+    };
+    use crate::{
+        types::{
+            ColorIndex,
+            Model,
+            Rotation,
+            Vector,
+            Voxel,
+        },
+        VoxData,
+    };
+
+    /// Creates a file that shows a model with one voxel on layers 0 and 1, at
+    /// the same position.
+    fn overlapping() -> VoxData {
+        let mut vox = VoxData::default();
+        vox.create_layer("first").unwrap();
+        vox.create_layer("second").unwrap();
+        for color_index in 1..=2 {
+            vox.models.push(Model {
+                size: Vector::new(1, 1, 1),
+                voxels: vec![Voxel::new([0, 0, 0], color_index)],
+            });
+        }
+
+        let mut scene = vox.scene().unwrap();
+        let second = scene.objects().nth(2).unwrap().0;
+        scene.get_mut(second).unwrap().set_layer_id(Some(1));
+        vox.set_scene(&scene).unwrap();
+
+        vox
+    }
+
+    #[test]
+    fn it_resolves_overlaps() {
+        let vox = overlapping();
+        let color = |policy| bake(&vox, &policy).unwrap().voxels[0].color_index;
+
+        assert_eq!(color(OverlapPolicy::LastWins), ColorIndex(2));
+        assert_eq!(color(OverlapPolicy::FirstWins), ColorIndex(1));
+        assert_eq!(
+            color(OverlapPolicy::LayerPriority(vec![0, 1])),
+            ColorIndex(1)
+        );
+        assert_eq!(color(OverlapPolicy::LayerPriority(vec![1])), ColorIndex(2));
+    }
+
+    #[test]
+    fn it_applies_transforms() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 1),
+            voxels: vec![Voxel::new([0, 0, 0], 1), Voxel::new([2, 0, 0], 2)],
+        });
+
+        let mut scene = vox.scene().unwrap();
+        let copy = scene.add_shape(scene.root(), 0).unwrap();
+        // Rotate by 90° around the z axis.
+        let rotation = Rotation::from_matrix([[0, -1, 0], [1, 0, 0], [0, 0, 1]]).unwrap();
+        let frame = &mut scene.get_mut(copy).unwrap().transform.frames[0];
+        frame.set_rotation(rotation);
+        frame.set_translation(Vector::new(0, 0, 5));
+        vox.set_scene(&scene).unwrap();
+
+        let model = bake(&vox, &OverlapPolicy::LastWins).unwrap();
+        assert_eq!(model.size, Vector::new(3, 3, 6));
+        let voxel = |point: [i8; 3]| model.get_voxel(point.into()).map(|voxel| voxel.color_index);
+        assert_eq!(voxel([0, 1, 0]), Some(ColorIndex(1)));
+        assert_eq!(voxel([2, 1, 0]), Some(ColorIndex(2)));
+        assert_eq!(voxel([1, 0, 5]), Some(ColorIndex(1)));
+        assert_eq!(voxel([1, 2, 5]), Some(ColorIndex(2)));
+
+        scene.get_mut(copy).unwrap().transform.frames[0].set_translation(Vector::new(0, 0, 300));
+        vox.set_scene(&scene).unwrap();
+        assert!(matches!(
+            bake(&vox, &OverlapPolicy::LastWins),
+            Err(BakeError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn it_rejects_invalid_model_ids() {
+        let mut vox = overlapping();
+        let mut scene = vox.scene().unwrap();
+        scene.add_shape(scene.root(), 2).unwrap();
+        vox.set_scene(&scene).unwrap();
+
+        assert!(matches!(
+            bake(&vox, &OverlapPolicy::LastWins),
+            Err(BakeError::InvalidModelId { model_id: 2 })
+        ));
+    }
+}
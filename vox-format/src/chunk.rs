@@ -10,8 +10,16 @@
 //! that contain the voxel data. The format is specified [here](https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt), but not all chunk IDs are described.
 
 use std::{
-    convert::TryInto,
+    collections::HashMap,
+    convert::{
+        TryFrom,
+        TryInto,
+    },
+    fmt,
+    fs::File,
     io::{
+        BufReader,
+        Cursor,
         Error as IoError,
         ErrorKind,
         Read,
@@ -19,8 +27,8 @@ use std::{
         SeekFrom,
         Write,
     },
+    path::Path,
     str::FromStr,
-    u64,
 };
 
 use byteorder::{
@@ -31,7 +39,11 @@ use byteorder::{
 use thiserror::Error;
 
 use crate::{
-    reader::Error as ReadError,
+    reader::{
+        Error as ReadError,
+        Limits,
+        ResultExt,
+    },
     types::Version,
     writer::Error as WriteError,
 };
@@ -40,6 +52,10 @@ use crate::{
 #[error("Failed to parse chunk ID: {0}")]
 pub struct ChunkIdParseError(String);
 
+#[derive(Debug, Error)]
+#[error("Failed to parse chunk path: {0}")]
+pub struct ChunkPathParseError(String);
+
 /// A chunk ID. It's either a pre-defined ID (that is used for VOX), or
 /// `Unsupported`.
 ///
@@ -75,7 +91,7 @@ pub enum ChunkId {
 impl ChunkId {
     pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
         let mut id = [0u8; 4];
-        reader.read_exact(&mut id)?;
+        reader.read_exact(&mut id).field("chunk ID")?;
         Ok(ChunkId::from(id))
     }
 
@@ -136,11 +152,18 @@ impl From<ChunkId> for [u8; 4] {
     }
 }
 
+impl fmt::Display for ChunkId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let id: [u8; 4] = (*self).into();
+        String::from_utf8_lossy(&id).fmt(f)
+    }
+}
+
 impl FromStr for ChunkId {
     type Err = ChunkIdParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.as_bytes().len() != 4 {
+        if s.len() != 4 {
             return Err(ChunkIdParseError(s.to_owned()));
         }
 
@@ -150,12 +173,91 @@ impl FromStr for ChunkId {
     }
 }
 
+/// The path to a chunk from the root of the chunk tree. Each segment consists
+/// of the chunk ID and the index of the chunk among its siblings with the same
+/// ID.
+///
+/// The path is displayed like `MAIN/nTRN#12`, which is the 13th `nTRN` chunk in
+/// the `MAIN` chunk.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ChunkPath {
+    segments: Vec<(ChunkId, usize)>,
+}
+
+impl ChunkPath {
+    /// Creates a path that only contains the root chunk.
+    pub fn root(chunk_id: ChunkId) -> Self {
+        Self {
+            segments: vec![(chunk_id, 0)],
+        }
+    }
+
+    /// Returns the path to a child of this chunk.
+    pub fn child(&self, chunk_id: ChunkId, index: usize) -> Self {
+        let mut path = self.clone();
+        path.segments.push((chunk_id, index));
+        path
+    }
+
+    /// Returns the segments of the path.
+    pub fn segments(&self) -> &[(ChunkId, usize)] {
+        &self.segments
+    }
+
+    /// Returns `true`, if the path has no segments.
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Inserts a segment at the start of the path.
+    pub(crate) fn prepend(&mut self, chunk_id: ChunkId, index: usize) {
+        self.segments.insert(0, (chunk_id, index));
+    }
+}
+
+impl fmt::Display for ChunkPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (chunk_id, index)) in self.segments.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{}", chunk_id)?;
+            }
+            else {
+                write!(f, "/{}#{}", chunk_id, index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChunkPath {
+    type Err = ChunkPathParseError;
+
+    /// Parses a path like `MAIN/nTRN#12`. If a segment has no index, it
+    /// refers to the first chunk with that ID.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ChunkPathParseError(s.to_owned());
+
+        let segments = s
+            .split('/')
+            .map(|segment| {
+                let (chunk_id, index) = match segment.split_once('#') {
+                    Some((chunk_id, index)) => (chunk_id, index.parse().map_err(|_| error())?),
+                    None => (segment, 0),
+                };
+                Ok((chunk_id.parse().map_err(|_| error())?, index))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { segments })
+    }
+}
+
 /// Chunk meta-data. This doesn't contain contents or children, but information
 /// needed to read the chunk from a file. You will still need a reader to read
 /// the contents though.
 #[derive(Clone, Debug)]
 pub struct Chunk {
-    offset: u32,
+    offset: u64,
     id: ChunkId,
     content_len: u32,
     children_len: u32,
@@ -165,37 +267,61 @@ impl Chunk {
     /// Reads the chunk header and returns the information needed to read its
     /// contents or children.
     pub fn read<R: Read + Seek>(mut reader: R) -> Result<Self, ReadError> {
-        let offset = reader.stream_position()? as u32;
+        let offset = reader.stream_position()?;
 
         let id = ChunkId::read(&mut reader)?;
         log::trace!("read chunk at {}: {:?}", offset, id);
 
-        let content_len = reader.read_u32::<LE>()?;
-        let children_len = reader.read_u32::<LE>()?;
+        let content_len = reader.read_u32::<LE>().field("chunk content length")?;
+        let children_len = reader.read_u32::<LE>().field("chunk children length")?;
         log::trace!(
             "content_len = {}, children_len = {}",
             content_len,
             children_len
         );
 
-        Ok(Chunk {
+        let chunk = Chunk {
             offset,
             id,
             content_len,
             children_len,
-        })
+        };
+
+        // Make sure that all offsets we compute from this chunk fit into a `u64`.
+        if offset.checked_add(chunk.len()).is_none() {
+            return Err(ReadError::InvalidChunkLength { chunk });
+        }
+
+        Ok(chunk)
     }
 
     /// Creates a reader for its contents.
     pub fn content<R: Read + Seek>(&self, mut reader: R) -> Result<ContentReader<R>, ReadError> {
         let offset = self.content_offset();
         log::trace!("content reader: id={:?}, offset={}", self.id, offset);
-        reader.seek(SeekFrom::Start(offset as u64))?;
+        reader.seek(SeekFrom::Start(offset))?;
         Ok(ContentReader {
             reader,
             start: offset,
             offset,
-            end: offset + self.content_len,
+            end: offset + u64::from(self.content_len),
+        })
+    }
+
+    /// Creates a reader for its children data. This reads the children as a
+    /// blob, which is useful to copy them without decoding them.
+    pub fn children_data<R: Read + Seek>(
+        &self,
+        mut reader: R,
+    ) -> Result<ContentReader<R>, ReadError> {
+        let offset = self.children_offset();
+        log::trace!("children data reader: id={:?}, offset={}", self.id, offset);
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(ContentReader {
+            reader,
+            start: offset,
+            offset,
+            end: offset + u64::from(self.children_len),
         })
     }
 
@@ -212,16 +338,14 @@ impl Chunk {
     pub fn children<R: Read + Seek>(&self, reader: R) -> ChildrenReader<R> {
         let offset = self.children_offset();
 
-        log::trace!(
-            "children reader: offset={}, end={}",
-            offset,
-            offset + self.children_len
-        );
+        let end = offset + u64::from(self.children_len);
+
+        log::trace!("children reader: offset={}, end={}", offset, end);
 
         ChildrenReader {
             reader,
             offset,
-            end: offset + self.children_len,
+            end,
         }
     }
 
@@ -229,7 +353,7 @@ impl Chunk {
     /// start of the reader. Note that for children chunks, this is relative
     /// to the start of the child data, since they basically use a
     /// `ContentReader` to read children chunks.
-    pub fn offset(&self) -> u32 {
+    pub fn offset(&self) -> u64 {
         self.offset
     }
 
@@ -240,7 +364,7 @@ impl Chunk {
 
     /// Returns the offset to the content data. See [`Self::offset`] for further
     /// information.
-    pub fn content_offset(&self) -> u32 {
+    pub fn content_offset(&self) -> u64 {
         self.offset + 12
     }
 
@@ -251,8 +375,8 @@ impl Chunk {
 
     /// Returns the offset to the children data. See [`Self::offset`] for
     /// further information.
-    pub fn children_offset(&self) -> u32 {
-        self.offset + 12 + self.content_len
+    pub fn children_offset(&self) -> u64 {
+        self.offset + 12 + u64::from(self.content_len)
     }
 
     /// Returns the length of the children data.
@@ -262,8 +386,8 @@ impl Chunk {
 
     /// Returns the length of this chunk. That is the length of its contents,
     /// children and header.
-    pub fn len(&self) -> u32 {
-        self.content_len + self.children_len + 12
+    pub fn len(&self) -> u64 {
+        u64::from(self.content_len) + u64::from(self.children_len) + 12
     }
 
     /// Returns `true` if the chunks has neither content nor children, `false`
@@ -276,9 +400,9 @@ impl Chunk {
 /// A reader for a chunk's contents.
 pub struct ContentReader<R> {
     reader: R,
-    offset: u32,
-    start: u32,
-    end: u32,
+    offset: u64,
+    start: u64,
+    end: u64,
 }
 
 impl<R: Read> Read for ContentReader<R> {
@@ -290,7 +414,9 @@ impl<R: Read> Read for ContentReader<R> {
             self.end
         );
         if self.offset < self.end {
-            let n_at_most = ((self.end - self.offset) as usize).min(buf.len());
+            let n_at_most = usize::try_from(self.end - self.offset)
+                .unwrap_or(usize::MAX)
+                .min(buf.len());
             log::trace!(
                 "read: offset={}, end={}, n_at_most={}",
                 self.offset,
@@ -299,7 +425,7 @@ impl<R: Read> Read for ContentReader<R> {
             );
             let n_read = self.reader.read(&mut buf[..n_at_most])?;
             log::trace!("read: n_read={}", n_read);
-            self.offset += n_read as u32;
+            self.offset += n_read as u64;
             Ok(n_read)
         }
         else {
@@ -324,18 +450,31 @@ impl<R: Seek> Seek for ContentReader<R> {
 
             self.offset = new_offset;
 
-            self.reader.seek(SeekFrom::Start(self.offset.into()))?;
+            self.reader.seek(SeekFrom::Start(self.offset))?;
         }
 
-        Ok((self.offset - self.start).into())
+        Ok(self.offset - self.start)
     }
 }
 
 /// An iterator over a chunk's children.
 pub struct ChildrenReader<R> {
     reader: R,
-    offset: u32,
-    end: u32,
+    offset: u64,
+    end: u64,
+}
+
+impl<R> ChildrenReader<R> {
+    /// Returns the offset at which the next child is read.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the underlying reader. Each child is read at [`Self::offset`],
+    /// so the reader's position can be changed in between.
+    pub(crate) fn reader_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
 }
 
 impl<R: Read + Seek> Iterator for ChildrenReader<R> {
@@ -344,7 +483,22 @@ impl<R: Read + Seek> Iterator for ChildrenReader<R> {
     fn next(&mut self) -> Option<Self::Item> {
         log::trace!("read next child: offset={}, end={}", self.offset, self.end);
         if self.offset < self.end {
-            Some(read_chunk_at(&mut self.reader, &mut self.offset))
+            let result = read_chunk_at(&mut self.reader, &mut self.offset).and_then(|chunk| {
+                if self.offset > self.end {
+                    Err(ReadError::InvalidChunkLength { chunk })
+                }
+                else {
+                    Ok(chunk)
+                }
+            });
+
+            // Don't continue after an error, since we don't know where the next chunk
+            // starts.
+            if result.is_err() {
+                self.offset = self.end;
+            }
+
+            Some(result)
         }
         else {
             None
@@ -353,28 +507,36 @@ impl<R: Read + Seek> Iterator for ChildrenReader<R> {
 }
 
 /// Reads a chunk from `reader` at the specified offset.
-pub fn read_chunk_at<R: Read + Seek>(mut reader: R, offset: &mut u32) -> Result<Chunk, ReadError> {
+pub fn read_chunk_at<R: Read + Seek>(mut reader: R, offset: &mut u64) -> Result<Chunk, ReadError> {
     log::trace!("reading chunk at {}", offset);
-    reader.seek(SeekFrom::Start((*offset).into()))?;
+    reader.seek(SeekFrom::Start(*offset))?;
     let chunk = Chunk::read(reader)?;
     *offset += chunk.len();
     Ok(chunk)
 }
 
 /// Reads the VOX file's header, verifies it, and then reads the MAIN chunk.
-pub fn read_main_chunk<R: Read + Seek>(mut reader: R) -> Result<(Chunk, Version), ReadError> {
+pub fn read_main_chunk<R: Read + Seek>(reader: R) -> Result<(Chunk, Version), ReadError> {
+    let (main_chunk, version) = read_main_chunk_any_version(reader)?;
+    if !version.is_supported() {
+        return Err(ReadError::UnsupportedFileVersion { version });
+    }
+    Ok((main_chunk, version))
+}
+
+/// Like [`read_main_chunk`], but leaves checking the version to the caller.
+pub(crate) fn read_main_chunk_any_version<R: Read + Seek>(
+    mut reader: R,
+) -> Result<(Chunk, Version), ReadError> {
     let mut buf = [0u8; 4];
-    reader.read_exact(&mut buf)?;
+    reader.read_exact(&mut buf).field("file magic")?;
     log::trace!("magic = {:?}", buf);
     if &buf != b"VOX " {
         return Err(ReadError::InvalidMagic { got: buf });
     }
 
-    let version = Version::read(&mut reader)?;
+    let version = Version::read(&mut reader).field("file version")?;
     log::trace!("version = {:?}", version);
-    if !version.is_supported() {
-        return Err(ReadError::UnsupportedFileVersion { version });
-    }
 
     let main_chunk = Chunk::read(reader)?;
 
@@ -398,6 +560,9 @@ pub struct ChunkWriter<W> {
     content_len: u32,
 
     children_len: u32,
+
+    /// Number of children written for each chunk ID. Used to report errors.
+    num_children: HashMap<ChunkId, usize>,
 }
 
 impl<W: Write + Seek> ChunkWriter<W> {
@@ -414,6 +579,7 @@ impl<W: Write + Seek> ChunkWriter<W> {
             offset,
             content_len: 0,
             children_len: 0,
+            num_children: HashMap::new(),
         })
     }
 
@@ -482,7 +648,7 @@ impl<W: Write + Seek> ChunkWriter<W> {
 
         f(&mut content_writer)?;
 
-        self.content_len += content_writer.len();
+        self.content_len = u32::try_from(u64::from(self.content_len) + content_writer.len())?;
 
         Ok(())
     }
@@ -516,13 +682,18 @@ impl<W: Write + Seek> ChunkWriter<W> {
         chunk_id: ChunkId,
         mut f: F,
     ) -> Result<(), WriteError> {
-        let mut child_writer = ChildWriter::new(ContentWriter::new(&mut self.writer)?, chunk_id)?;
+        let index = self.num_children.entry(chunk_id).or_default();
+        let path_index = *index;
+        *index += 1;
 
-        f(&mut child_writer)?;
+        let mut child_writer = ChildWriter::new(ContentWriter::new(&mut self.writer)?, chunk_id)?;
 
-        self.children_len += child_writer.writer.len();
+        f(&mut child_writer)
+            .and_then(|()| child_writer.write_header())
+            .map_err(|e| e.in_chunk(chunk_id, path_index))?;
 
-        child_writer.write_header()?;
+        self.children_len =
+            u32::try_from(u64::from(self.children_len) + child_writer.writer.len())?;
 
         Ok(())
     }
@@ -542,6 +713,55 @@ impl<W: Write + Seek> ChunkWriter<W> {
         })
     }
 
+    /// Copies `chunk` including its contents and children from `reader` to a
+    /// new child chunk. The data is copied without being decoded.
+    pub fn copy_child<R: Read + Seek>(
+        &mut self,
+        mut reader: R,
+        chunk: &Chunk,
+    ) -> Result<(), WriteError> {
+        log::trace!("Copying chunk: {:?}", chunk.id());
+
+        self.child_writer(chunk.id(), |child_writer| {
+            child_writer.content_writer(|writer| {
+                std::io::copy(&mut chunk.content(&mut reader)?, writer)?;
+                Ok(())
+            })?;
+            child_writer.children_data_writer(|writer| {
+                std::io::copy(&mut chunk.children_data(&mut reader)?, writer)?;
+                Ok(())
+            })
+        })
+    }
+
+    /// Writes a [`ChunkNode`] including its children as a child chunk.
+    pub fn write_node(&mut self, node: &ChunkNode) -> Result<(), WriteError> {
+        let index = self.num_children.entry(node.id).or_default();
+        let path_index = *index;
+        *index += 1;
+
+        self.children_data_writer(|writer| node.write_recursive(writer))
+            .map_err(|e| e.in_chunk(node.id, path_index))
+    }
+
+    /// Writes raw data to the children data. The data must consist of complete
+    /// chunks.
+    fn children_data_writer<
+        'w,
+        F: FnMut(&mut ContentWriter<&'w mut W>) -> Result<(), WriteError>,
+    >(
+        &'w mut self,
+        mut f: F,
+    ) -> Result<(), WriteError> {
+        let mut children_writer = ContentWriter::new(&mut self.writer)?;
+
+        f(&mut children_writer)?;
+
+        self.children_len = u32::try_from(u64::from(self.children_len) + children_writer.len())?;
+
+        Ok(())
+    }
+
     fn write_header(&mut self) -> Result<(), WriteError> {
         log::trace!(
             "Write header for chunk {:?} to offset {}: content_len = {}, children_len = {}",
@@ -551,7 +771,7 @@ impl<W: Write + Seek> ChunkWriter<W> {
             self.children_len
         );
 
-        let old_pos = self.writer.seek(SeekFrom::Current(0))?;
+        let old_pos = self.writer.stream_position()?;
         self.writer.seek(SeekFrom::Start(self.offset + 4))?;
 
         self.writer.write_u32::<LE>(self.content_len)?;
@@ -568,14 +788,14 @@ impl<W: Write + Seek> ChunkWriter<W> {
 #[derive(Debug)]
 pub struct ContentWriter<W> {
     writer: W,
-    offset: u32,
-    start: u32,
-    end: u32,
+    offset: u64,
+    start: u64,
+    end: u64,
 }
 
 impl<W: Seek> ContentWriter<W> {
     fn new(mut writer: W) -> Result<Self, WriteError> {
-        let offset = writer.stream_position()?.try_into()?;
+        let offset = writer.stream_position()?;
         Ok(Self {
             writer,
             offset,
@@ -584,7 +804,7 @@ impl<W: Seek> ContentWriter<W> {
         })
     }
 
-    fn len(&self) -> u32 {
+    fn len(&self) -> u64 {
         self.end - self.start
     }
 }
@@ -630,11 +850,11 @@ impl<W: Seek> Seek for ContentWriter<W> {
                 pos,
                 new_offset
             );
-            self.writer.seek(SeekFrom::Start(new_offset.into()))?;
+            self.writer.seek(SeekFrom::Start(new_offset))?;
             self.offset = new_offset;
         }
 
-        Ok((self.offset - self.start).into())
+        Ok(self.offset - self.start)
     }
 }
 
@@ -651,45 +871,742 @@ pub fn chunk_writer<W: Write + Seek, F: FnMut(&mut ChunkWriter<W>) -> Result<(),
     chunk_id: ChunkId,
     mut f: F,
 ) -> Result<(), WriteError> {
-    let mut chunk_writer = ChunkWriter::new(writer, chunk_id)?;
+    let mut chunk_writer =
+        ChunkWriter::new(writer, chunk_id).map_err(|e| e.in_chunk(chunk_id, 0))?;
 
-    f(&mut chunk_writer)?;
+    f(&mut chunk_writer)
+        .and_then(|()| chunk_writer.write_header())
+        .map_err(|e| e.in_chunk(chunk_id, 0))?;
 
-    chunk_writer.write_header()?;
+    Ok(())
+}
+
+/// An owned chunk, including its contents and children. Unlike [`Chunk`],
+/// this can be modified and written back. See [`ChunkTree`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkNode {
+    pub id: ChunkId,
+    pub content: Vec<u8>,
+    pub children: Vec<ChunkNode>,
+}
+
+impl ChunkNode {
+    /// Creates a chunk node without children.
+    pub fn new(id: ChunkId, content: Vec<u8>) -> Self {
+        Self {
+            id,
+            content,
+            children: vec![],
+        }
+    }
+
+    /// Reads `chunk` including its children from `reader`.
+    pub fn read<R: Read + Seek>(reader: R, chunk: &Chunk) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, chunk, &Limits::default(), 1)
+    }
+
+    /// Reads `chunk` including its children from `reader`, enforcing `limits`.
+    /// `depth` is the depth at which `chunk` is nested. The `MAIN` chunk has
+    /// depth 1.
+    pub fn read_with_limits<R: Read + Seek>(
+        mut reader: R,
+        chunk: &Chunk,
+        limits: &Limits,
+        depth: usize,
+    ) -> Result<Self, ReadError> {
+        let path = ChunkPath::root(chunk.id());
+        Self::read_recursive(&mut reader, chunk, &path, limits, depth)
+    }
+
+    fn read_recursive<R: Read + Seek>(
+        reader: &mut R,
+        chunk: &Chunk,
+        path: &ChunkPath,
+        limits: &Limits,
+        depth: usize,
+    ) -> Result<Self, ReadError> {
+        let result = (|| {
+            limits.check_chunk(chunk, depth)?;
+
+            let content = chunk.read_content_to_vec(&mut *reader)?;
+
+            let mut children = vec![];
+            let mut num_children = HashMap::new();
+            for child in chunk
+                .children(&mut *reader)
+                .collect::<Result<Vec<_>, _>>()?
+            {
+                let index = num_children.entry(child.id()).or_insert(0);
+                let child_path = path.child(child.id(), *index);
+                *index += 1;
+
+                children.push(Self::read_recursive(
+                    reader,
+                    &child,
+                    &child_path,
+                    limits,
+                    depth + 1,
+                )?);
+            }
+
+            Ok(Self {
+                id: chunk.id(),
+                content,
+                children,
+            })
+        })();
+
+        // Errors from children already have their path, so this only adds the path
+        // to errors in this chunk.
+        result.map_err(|e: ReadError| e.with_chunk(path, chunk.offset()))
+    }
+
+    /// Writes the chunk including its header and children.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        self.write_recursive(&mut writer)
+            .map_err(|e| e.in_chunk(self.id, 0))
+    }
+
+    fn write_recursive<W: Write>(&self, writer: &mut W) -> Result<(), WriteError> {
+        let children_len = self.children.iter().map(ChunkNode::len).sum::<u64>();
+
+        self.id.write(&mut *writer)?;
+        writer.write_u32::<LE>(self.content.len().try_into()?)?;
+        writer.write_u32::<LE>(children_len.try_into()?)?;
+        writer.write_all(&self.content)?;
+
+        let mut num_children = HashMap::new();
+        for child in &self.children {
+            let index = num_children.entry(child.id).or_insert(0);
+            let path_index = *index;
+            *index += 1;
+
+            child
+                .write_recursive(writer)
+                .map_err(|e| e.in_chunk(child.id, path_index))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the length of this chunk including its header and children.
+    pub fn len(&self) -> u64 {
+        12 + self.content.len() as u64 + self.children.iter().map(ChunkNode::len).sum::<u64>()
+    }
+
+    /// Returns `true` if the chunks has neither content nor children, `false`
+    /// otherwise.
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty() && self.children.is_empty()
+    }
+
+    /// Returns the `index`-th child with the specified ID.
+    pub fn child(&self, id: ChunkId, index: usize) -> Option<&ChunkNode> {
+        self.children.iter().filter(|c| c.id == id).nth(index)
+    }
+
+    /// Returns the `index`-th child with the specified ID.
+    pub fn child_mut(&mut self, id: ChunkId, index: usize) -> Option<&mut ChunkNode> {
+        self.children.iter_mut().filter(|c| c.id == id).nth(index)
+    }
+
+    /// Returns all children with the specified ID.
+    pub fn children_with_id(&self, id: ChunkId) -> impl Iterator<Item = &ChunkNode> {
+        self.children.iter().filter(move |c| c.id == id)
+    }
+
+    /// Looks up a descendant by its path relative to this chunk, e.g.
+    /// `nTRN#12` for a child, or `nTRN#12/nGRP` for a grandchild. An empty
+    /// path returns this chunk.
+    pub fn get(&self, segments: &[(ChunkId, usize)]) -> Option<&ChunkNode> {
+        segments
+            .iter()
+            .try_fold(self, |node, (id, index)| node.child(*id, *index))
+    }
+
+    /// Looks up a descendant by its path relative to this chunk. See
+    /// [`Self::get`].
+    pub fn get_mut(&mut self, segments: &[(ChunkId, usize)]) -> Option<&mut ChunkNode> {
+        segments
+            .iter()
+            .try_fold(self, |node, (id, index)| node.child_mut(*id, *index))
+    }
+}
 
+/// A VOX file as a tree of owned chunks. This can be used to insert, reorder,
+/// remove or replace chunks anywhere and write the file back, without having
+/// to decode the chunks.
+///
+/// # Example
+///
+/// ```
+/// # use vox_format::chunk::*;
+/// let mut tree = ChunkTree::default();
+/// tree.root
+///     .children
+///     .push(ChunkNode::new(ChunkId::Size, vec![0; 12]));
+/// tree.root
+///     .children
+///     .push(ChunkNode::new(ChunkId::NTrn, vec![]));
+/// tree.root
+///     .children
+///     .push(ChunkNode::new(ChunkId::Size, vec![1; 12]));
+///
+/// let data = tree.to_vec().unwrap();
+/// let mut tree = ChunkTree::from_slice(&data).unwrap();
+///
+/// // Remove all scene graph nodes.
+/// tree.root
+///     .children
+///     .retain(|chunk| !matches!(chunk.id, ChunkId::NTrn | ChunkId::NGrp | ChunkId::NShp));
+///
+/// let size = tree.get(&"MAIN/SIZE#1".parse().unwrap()).unwrap();
+/// assert_eq!(size.content, vec![1; 12]);
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChunkTree {
+    pub version: Version,
+
+    /// The `MAIN` chunk.
+    pub root: ChunkNode,
+}
+
+impl Default for ChunkTree {
+    fn default() -> Self {
+        Self {
+            version: Version::default(),
+            root: ChunkNode::new(ChunkId::Main, vec![]),
+        }
+    }
+}
+
+impl ChunkTree {
+    /// Parses a VOX file into a chunk tree.
+    pub fn read<R: Read + Seek>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Parses a VOX file into a chunk tree, enforcing `limits`.
+    pub fn read_with_limits<R: Read + Seek>(
+        mut reader: R,
+        limits: &Limits,
+    ) -> Result<Self, ReadError> {
+        let (main_chunk, version) = read_main_chunk(&mut reader)?;
+        let root = ChunkNode::read_with_limits(reader, &main_chunk, limits, 1)?;
+        Ok(Self { version, root })
+    }
+
+    /// Parses a VOX file from a slice into a chunk tree.
+    pub fn from_slice(slice: &[u8]) -> Result<Self, ReadError> {
+        Self::read(Cursor::new(slice))
+    }
+
+    /// Parses a VOX file from the specified path into a chunk tree.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ReadError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Serializes the chunk tree as VOX file.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> Result<(), WriteError> {
+        crate::writer::main_chunk_writer(writer, self.version, |chunk_writer| {
+            chunk_writer.write_content(&self.root.content)?;
+            for child in &self.root.children {
+                chunk_writer.write_node(child)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Serializes the chunk tree as VOX file and returns the bytes.
+    pub fn to_vec(&self) -> Result<Vec<u8>, WriteError> {
+        let mut buf = Vec::with_capacity(self.root.len() as usize + 8);
+        self.write(Cursor::new(&mut buf))?;
+        Ok(buf)
+    }
+
+    /// Serializes the chunk tree as VOX file to the specified path.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), WriteError> {
+        self.write(File::create(path)?)
+    }
+
+    /// Looks up a chunk by its path, which starts with `MAIN`, e.g.
+    /// `MAIN/nTRN#12`.
+    pub fn get(&self, path: &ChunkPath) -> Option<&ChunkNode> {
+        let (root, segments) = path.segments().split_first()?;
+        (*root == (self.root.id, 0))
+            .then(|| self.root.get(segments))
+            .flatten()
+    }
+
+    /// Looks up a chunk by its path. See [`Self::get`].
+    pub fn get_mut(&mut self, path: &ChunkPath) -> Option<&mut ChunkNode> {
+        let (root, segments) = path.segments().split_first()?;
+        if *root == (self.root.id, 0) {
+            self.root.get_mut(segments)
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// What to do with a chunk, when transforming chunks with
+/// [`transform_chunks`].
+#[derive(Clone, Debug)]
+pub enum ChunkAction {
+    /// Keep the chunk. Its children are transformed as well.
+    Keep,
+
+    /// Remove the chunk including its children.
+    Remove,
+
+    /// Replace the chunk's contents. Its children are transformed as well.
+    ReplaceContent(Vec<u8>),
+
+    /// Replace the chunk including its children with the given data. The data
+    /// must consist of complete chunks, and can contain any number of them,
+    /// e.g. as written by [`chunk_writer`].
+    Replace(Vec<u8>),
+}
+
+/// What we found to do with a chunk, after calling the closure for it and its
+/// children.
+enum Plan {
+    /// Neither the chunk nor its children changed, so we copy it as a blob.
+    Copy,
+    Remove,
+    Replace(Vec<u8>),
+    Rewrite {
+        content: Option<Vec<u8>>,
+        children: Vec<(Chunk, Plan)>,
+    },
+}
+
+/// Reads a VOX file from `reader`, transforms its chunks and writes the result
+/// to `writer`.
+///
+/// The closure is called for every chunk in the `MAIN` chunk, including nested
+/// children, and decides what to do with it (see [`ChunkAction`]). It also
+/// gets the reader, so that it can read the chunk's contents. Chunks whose
+/// children don't change are copied as blobs.
+///
+/// # Example
+///
+/// This removes all `nTRN` chunks from a file:
+///
+/// ```
+/// # use std::{fs::File, io::Cursor};
+/// # use vox_format::chunk::*;
+/// # let input = File::open("../test_files/test_multiple_models.vox").unwrap();
+/// # let output = Cursor::new(vec![]);
+/// transform_chunks(input, output, |_reader, chunk| {
+///     Ok(if chunk.id() == ChunkId::NTrn {
+///         ChunkAction::Remove
+///     }
+///     else {
+///         ChunkAction::Keep
+///     })
+/// })
+/// .unwrap();
+/// ```
+pub fn transform_chunks<
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnMut(&mut R, &Chunk) -> Result<ChunkAction, WriteError>,
+>(
+    reader: R,
+    writer: W,
+    f: F,
+) -> Result<(), WriteError> {
+    transform_chunks_with_limits(reader, writer, &Limits::default(), f)
+}
+
+/// Like [`transform_chunks`], but enforces `limits` on the chunks that are
+/// read.
+pub fn transform_chunks_with_limits<
+    R: Read + Seek,
+    W: Write + Seek,
+    F: FnMut(&mut R, &Chunk) -> Result<ChunkAction, WriteError>,
+>(
+    mut reader: R,
+    writer: W,
+    limits: &Limits,
+    mut f: F,
+) -> Result<(), WriteError> {
+    let (main_chunk, version) = read_main_chunk(&mut reader)?;
+    limits.check_chunk(&main_chunk, 1)?;
+
+    let children = plan_children(&mut reader, &main_chunk, limits, 1, &mut f)?;
+
+    crate::writer::main_chunk_writer(writer, version, |chunk_writer| {
+        chunk_writer.content_writer(|writer| {
+            std::io::copy(&mut main_chunk.content(&mut reader)?, writer)?;
+            Ok(())
+        })?;
+        write_children(&mut reader, &children, chunk_writer)
+    })
+}
+
+/// Plans the children of `chunk`, which is nested at `depth`.
+fn plan_children<R: Read + Seek, F: FnMut(&mut R, &Chunk) -> Result<ChunkAction, WriteError>>(
+    reader: &mut R,
+    chunk: &Chunk,
+    limits: &Limits,
+    depth: usize,
+    f: &mut F,
+) -> Result<Vec<(Chunk, Plan)>, WriteError> {
+    let children = chunk
+        .children(&mut *reader)
+        .collect::<Result<Vec<Chunk>, ReadError>>()?;
+
+    children
+        .into_iter()
+        .map(|child| {
+            let plan = plan_chunk(reader, &child, limits, depth + 1, f)?;
+            Ok((child, plan))
+        })
+        .collect()
+}
+
+fn plan_chunk<R: Read + Seek, F: FnMut(&mut R, &Chunk) -> Result<ChunkAction, WriteError>>(
+    reader: &mut R,
+    chunk: &Chunk,
+    limits: &Limits,
+    depth: usize,
+    f: &mut F,
+) -> Result<Plan, WriteError> {
+    // This recurses for nested children, so it must stop at the nesting limit.
+    limits.check_chunk(chunk, depth)?;
+
+    let content = match f(reader, chunk)? {
+        ChunkAction::Keep => None,
+        ChunkAction::Remove => return Ok(Plan::Remove),
+        ChunkAction::ReplaceContent(content) => Some(content),
+        ChunkAction::Replace(data) => return Ok(Plan::Replace(data)),
+    };
+
+    let children = plan_children(reader, chunk, limits, depth, f)?;
+
+    if content.is_none() && children.iter().all(|(_, plan)| matches!(plan, Plan::Copy)) {
+        Ok(Plan::Copy)
+    }
+    else {
+        Ok(Plan::Rewrite { content, children })
+    }
+}
+
+fn write_children<R: Read + Seek, W: Write + Seek>(
+    reader: &mut R,
+    children: &[(Chunk, Plan)],
+    chunk_writer: &mut ChunkWriter<W>,
+) -> Result<(), WriteError> {
+    for (chunk, plan) in children {
+        match plan {
+            Plan::Copy => chunk_writer.copy_child(&mut *reader, chunk)?,
+            Plan::Remove => log::trace!("Removing chunk: {:?}", chunk.id()),
+            Plan::Replace(data) => {
+                log::trace!("Replacing chunk: {:?}", chunk.id());
+                chunk_writer.children_data_writer(|writer| {
+                    writer.write_all(data)?;
+                    Ok(())
+                })?;
+            }
+            Plan::Rewrite { content, children } => {
+                // The writer types nest for each level of children, so we write rewritten
+                // chunks to a buffer first.
+                let mut buf = vec![];
+                rewrite_chunk(reader, chunk, content.as_deref(), children, &mut buf)?;
+                chunk_writer.children_data_writer(|writer| {
+                    writer.write_all(&buf)?;
+                    Ok(())
+                })?;
+            }
+        }
+    }
     Ok(())
 }
 
+fn rewrite_chunk<R: Read + Seek>(
+    reader: &mut R,
+    chunk: &Chunk,
+    content: Option<&[u8]>,
+    children: &[(Chunk, Plan)],
+    buf: &mut Vec<u8>,
+) -> Result<(), WriteError> {
+    log::trace!("Rewriting chunk: {:?}", chunk.id());
+
+    chunk_writer(Cursor::new(buf), chunk.id(), |chunk_writer| {
+        if let Some(content) = content {
+            chunk_writer.write_content(content)?;
+        }
+        else {
+            chunk_writer.content_writer(|writer| {
+                std::io::copy(&mut chunk.content(&mut *reader)?, writer)?;
+                Ok(())
+            })?;
+        }
+        write_children(reader, children, chunk_writer)
+    })
+}
+
 #[derive(Debug, Error)]
 #[error("The argument {pos:?} to seek is invalid.")]
 struct InvalidSeek {
-    current: u32,
-    start: u32,
-    end: u32,
+    current: u64,
+    start: u64,
+    end: u64,
     pos: SeekFrom,
 }
 
-fn seek_to(current: u32, start: u32, end: u32, pos: SeekFrom) -> Result<u32, IoError> {
-    let (offset, delta) = match pos {
-        SeekFrom::Current(pos) => (current, Some(pos)),
-        SeekFrom::Start(pos) => (start, pos.try_into().ok()),
-        SeekFrom::End(pos) => (end, Some(pos)),
+fn seek_to(current: u64, start: u64, end: u64, pos: SeekFrom) -> Result<u64, IoError> {
+    let new_pos = match pos {
+        SeekFrom::Current(delta) => current.checked_add_signed(delta),
+        SeekFrom::Start(delta) => start.checked_add(delta),
+        SeekFrom::End(delta) => end.checked_add_signed(delta),
     };
 
-    let offset = i64::from(offset);
-    let new_pos: Option<u32> = delta
-        .and_then(|d| offset.checked_add(d))
-        .and_then(|p| p.try_into().ok());
-
     new_pos.ok_or_else(|| {
-        IoError::new(
-            ErrorKind::Other,
-            InvalidSeek {
-                current,
-                start,
-                end,
-                pos,
-            },
-        )
+        IoError::other(InvalidSeek {
+            current,
+            start,
+            end,
+            pos,
+        })
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{
+        Cursor,
+        Write,
+    };
+
+    use super::{
+        read_main_chunk,
+        transform_chunks,
+        transform_chunks_with_limits,
+        ChunkAction,
+        ChunkId,
+        ChunkNode,
+        ChunkTree,
+    };
+    use crate::{
+        reader::{
+            from_slice,
+            Error as ReadError,
+            LimitKind,
+            Limits,
+        },
+        types::Version,
+        writer::{
+            main_chunk_writer,
+            Error as WriteError,
+        },
+    };
+
+    /// `MAIN` with children `nTRN` (with children `nGRP` and `RGBA`) and
+    /// `SIZE`.
+    fn nested_chunks() -> Vec<u8> {
+        let mut buf = vec![];
+        main_chunk_writer(Cursor::new(&mut buf), Version::default(), |chunk_writer| {
+            chunk_writer.child_writer(ChunkId::NTrn, |child_writer| {
+                child_writer.write_content(b"transform")?;
+                child_writer
+                    .child_writer(ChunkId::NGrp, |writer| writer.write_content(b"group"))?;
+                child_writer.child_writer(ChunkId::Rgba, |writer| writer.write_content(b"rgba"))
+            })?;
+            chunk_writer.child_writer(ChunkId::Size, |writer| writer.write_content(b"size"))
+        })
+        .unwrap();
+        buf
+    }
+
+    /// `MAIN` with a chain of `depth` empty `nGRP` chunks, each nested in the
+    /// previous one.
+    fn deeply_nested_chunks(depth: usize) -> Vec<u8> {
+        let mut children = vec![];
+        for i in 0..depth {
+            let children_len = 12 * (depth - i - 1) as u32;
+            children.extend_from_slice(b"nGRP");
+            children.extend_from_slice(&0u32.to_le_bytes());
+            children.extend_from_slice(&children_len.to_le_bytes());
+        }
+
+        let mut buf = vec![];
+        main_chunk_writer(Cursor::new(&mut buf), Version::default(), |chunk_writer| {
+            chunk_writer.children_data_writer(|writer| {
+                writer.write_all(&children)?;
+                Ok(())
+            })
+        })
+        .unwrap();
+        buf
+    }
+
+    fn is_nesting_error(error: &ReadError) -> bool {
+        matches!(
+            error.root(),
+            ReadError::LimitExceeded {
+                kind: LimitKind::Nesting,
+                ..
+            }
+        )
+    }
+
+    fn transform<F: FnMut(ChunkId) -> ChunkAction>(data: &[u8], mut f: F) -> Vec<u8> {
+        let mut output = vec![];
+        transform_chunks(Cursor::new(data), Cursor::new(&mut output), |_, chunk| {
+            Ok(f(chunk.id()))
+        })
+        .unwrap();
+        output
+    }
+
+    type Tree = Vec<(ChunkId, Vec<u8>, Vec<(ChunkId, Vec<u8>)>)>;
+
+    /// Returns the IDs and contents of the children of `MAIN`, and of their
+    /// children.
+    fn read_tree(data: &[u8]) -> Tree {
+        let mut reader = Cursor::new(data);
+        let (main_chunk, _) = read_main_chunk(&mut reader).unwrap();
+        main_chunk
+            .children(reader.clone())
+            .map(|chunk| {
+                let chunk = chunk.unwrap();
+                let children = chunk
+                    .children(reader.clone())
+                    .map(|child| {
+                        let child = child.unwrap();
+                        (
+                            child.id(),
+                            child.read_content_to_vec(reader.clone()).unwrap(),
+                        )
+                    })
+                    .collect();
+                (
+                    chunk.id(),
+                    chunk.read_content_to_vec(reader.clone()).unwrap(),
+                    children,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunk_trees_round_trip() {
+        let data = nested_chunks();
+        let tree = ChunkTree::from_slice(&data).unwrap();
+
+        let rgba = tree.get(&"MAIN/nTRN/RGBA".parse().unwrap()).unwrap();
+        assert_eq!(rgba.content, b"rgba");
+        assert!(tree.get(&"MAIN/nTRN#1".parse().unwrap()).is_none());
+
+        assert_eq!(tree.to_vec().unwrap(), data);
+    }
+
+    #[test]
+    fn chunk_trees_can_be_modified() {
+        let mut tree = ChunkTree::from_slice(&nested_chunks()).unwrap();
+
+        let transform = tree.get_mut(&"MAIN/nTRN".parse().unwrap()).unwrap();
+        transform.children.remove(0);
+        transform
+            .children
+            .push(ChunkNode::new(ChunkId::Layr, b"layer".to_vec()));
+        tree.root.children.swap(0, 1);
+
+        assert_eq!(
+            read_tree(&tree.to_vec().unwrap()),
+            vec![
+                (ChunkId::Size, b"size".to_vec(), vec![]),
+                (
+                    ChunkId::NTrn,
+                    b"transform".to_vec(),
+                    vec![
+                        (ChunkId::Rgba, b"rgba".to_vec()),
+                        (ChunkId::Layr, b"layer".to_vec())
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_copies_unchanged_chunks_with_children() {
+        let data = nested_chunks();
+        assert_eq!(transform(&data, |_| ChunkAction::Keep), data);
+    }
+
+    #[test]
+    fn it_transforms_nested_chunks() {
+        let data = nested_chunks();
+        let output = transform(&data, |id| {
+            match id {
+                ChunkId::Rgba => ChunkAction::Remove,
+                ChunkId::NGrp => ChunkAction::ReplaceContent(b"new group".to_vec()),
+                _ => ChunkAction::Keep,
+            }
+        });
+
+        assert_eq!(
+            read_tree(&output),
+            vec![
+                (
+                    ChunkId::NTrn,
+                    b"transform".to_vec(),
+                    vec![(ChunkId::NGrp, b"new group".to_vec())]
+                ),
+                (ChunkId::Size, b"size".to_vec(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_enforces_the_nesting_limit() {
+        // Deep enough to overflow the stack, if we didn't stop at the default limit.
+        let data = deeply_nested_chunks(100_000);
+
+        let error = ChunkTree::from_slice(&data).unwrap_err();
+        assert!(is_nesting_error(&error));
+
+        let error = transform_chunks(Cursor::new(&data), Cursor::new(vec![]), |_, _| {
+            Ok(ChunkAction::Keep)
+        })
+        .unwrap_err();
+        assert!(matches!(&error, WriteError::Reader(error) if is_nesting_error(error)));
+
+        // Reading a file skips the children of `MAIN`'s children, but checks them.
+        let error = from_slice(&data).unwrap_err();
+        assert!(is_nesting_error(&error));
+        assert_eq!(
+            error
+                .context()
+                .unwrap()
+                .chunk_path
+                .as_ref()
+                .unwrap()
+                .to_string(),
+            "MAIN/nGRP#0"
+        );
+
+        // `MAIN` and 4 levels of children are within a limit of 5.
+        let data = deeply_nested_chunks(4);
+        let limits = Limits {
+            max_nesting: 5,
+            ..Limits::default()
+        };
+        ChunkTree::read_with_limits(Cursor::new(&data), &limits).unwrap();
+        transform_chunks_with_limits(Cursor::new(&data), Cursor::new(vec![]), &limits, |_, _| {
+            Ok(ChunkAction::Keep)
+        })
+        .unwrap();
+
+        let limits = Limits {
+            max_nesting: 4,
+            ..Limits::default()
+        };
+        let error = ChunkTree::read_with_limits(Cursor::new(&data), &limits).unwrap_err();
+        assert!(is_nesting_error(&error));
+    }
+}
@@ -0,0 +1,251 @@
+//! Transparent decompression of VOX files that were compressed with gzip or
+//! zstd.
+//!
+//! [`crate::from_reader`] and [`crate::from_file`] detect compressed files by
+//! their magic number and decompress them, if the corresponding feature is
+//! enabled. Use [`crate::writer::to_file_compressed`] to write compressed
+//! files.
+
+use std::{
+    fmt,
+    io::{
+        Read,
+        Seek,
+        SeekFrom,
+        Write,
+    },
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::reader::{
+    Error as ReadError,
+    LimitKind,
+    Limits,
+};
+
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// A compression format.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Compression {
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Detects the compression format from the first bytes of a file. Returns
+    /// `None`, if the data isn't compressed with a supported format.
+    pub fn detect(header: &[u8]) -> Option<Self> {
+        #[cfg(feature = "gzip")]
+        if header.starts_with(&GZIP_MAGIC) {
+            return Some(Compression::Gzip);
+        }
+
+        #[cfg(feature = "zstd")]
+        if header.starts_with(&ZSTD_MAGIC) {
+            return Some(Compression::Zstd);
+        }
+
+        let _ = header;
+        None
+    }
+
+    /// Compresses `data` and writes it to `writer`.
+    pub fn compress<W: Write>(&self, data: &[u8], writer: W) -> Result<(), std::io::Error> {
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::copy_encode(data, writer, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decompresses all data from `reader`. Fails, if the decompressed data is
+    /// longer than `max_len`.
+    pub fn decompress<R: Read>(&self, reader: R, max_len: u64) -> Result<Vec<u8>, ReadError> {
+        let mut buf = vec![];
+
+        // Read one byte more than allowed, so we can detect if the limit is exceeded.
+        let limit = max_len.saturating_add(1);
+        match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                flate2::read::GzDecoder::new(reader)
+                    .take(limit)
+                    .read_to_end(&mut buf)?;
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                zstd::stream::read::Decoder::new(reader)?
+                    .take(limit)
+                    .read_to_end(&mut buf)?;
+            }
+        }
+
+        Limits::check(LimitKind::ChunkSize, buf.len() as u64, max_len)?;
+
+        Ok(buf)
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => "gzip",
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => "zstd",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown compression format: {0}")]
+pub struct CompressionParseError(String);
+
+impl FromStr for Compression {
+    type Err = CompressionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(feature = "gzip")]
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            #[cfg(feature = "zstd")]
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(CompressionParseError(s.to_owned())),
+        }
+    }
+}
+
+/// If the data in `reader` is compressed, it's decompressed and returned.
+/// Otherwise `None` is returned and `reader` is left at the position where it
+/// started.
+///
+/// The decompressed data is limited by the chunk size limit in `limits`, since
+/// it must only contain the file header and the `MAIN` chunk.
+pub fn decompress_if_compressed<R: Read + Seek>(
+    mut reader: R,
+    limits: &Limits,
+) -> Result<Option<Vec<u8>>, ReadError> {
+    let start = reader.stream_position()?;
+
+    let mut header = [0u8; 4];
+    let mut num_read = 0;
+    while num_read < header.len() {
+        match reader.read(&mut header[num_read..])? {
+            0 => break,
+            n => num_read += n,
+        }
+    }
+    reader.seek(SeekFrom::Start(start))?;
+
+    if let Some(compression) = Compression::detect(&header[..num_read]) {
+        log::debug!("Decompressing {} data", compression);
+        // The file header is 8 bytes.
+        let max_len = u64::from(limits.max_chunk_size).saturating_add(8);
+        let data = compression.decompress(reader, max_len)?;
+        Ok(Some(data))
+    }
+    else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::Compression;
+    use crate::{
+        from_reader,
+        reader::{
+            from_reader_with_options,
+            Error,
+            LimitKind,
+            Limits,
+            ReaderOptions,
+        },
+        to_vec,
+        types::{
+            Model,
+            Vector,
+            Voxel,
+        },
+        VoxData,
+    };
+
+    fn compressed_file(compression: Compression) -> Vec<u8> {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 2, 2),
+            voxels: vec![Voxel::new([0, 1, 0], 1), Voxel::new([1, 1, 1], 2)],
+        });
+
+        let mut data = vec![];
+        compression
+            .compress(&to_vec(&vox).unwrap(), &mut data)
+            .unwrap();
+        data
+    }
+
+    fn all() -> Vec<Compression> {
+        vec![
+            #[cfg(feature = "gzip")]
+            Compression::Gzip,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd,
+        ]
+    }
+
+    #[test]
+    fn it_reads_compressed_files() {
+        for compression in all() {
+            let data = compressed_file(compression);
+            assert_eq!(Compression::detect(&data), Some(compression));
+
+            let vox = from_reader(Cursor::new(data)).unwrap();
+            assert_eq!(vox.models[0].voxels.len(), 2);
+        }
+    }
+
+    #[test]
+    fn it_limits_the_decompressed_size() {
+        for compression in all() {
+            let options = ReaderOptions {
+                limits: Limits {
+                    max_chunk_size: 16,
+                    ..Limits::default()
+                },
+                ..ReaderOptions::default()
+            };
+            let error =
+                from_reader_with_options(Cursor::new(compressed_file(compression)), &options)
+                    .unwrap_err();
+            assert!(matches!(
+                error.root(),
+                Error::LimitExceeded {
+                    kind: LimitKind::ChunkSize,
+                    ..
+                }
+            ));
+        }
+    }
+}
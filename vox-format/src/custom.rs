@@ -0,0 +1,273 @@
+//! Codecs for custom chunks.
+//!
+//! Applications can embed their own chunks in VOX files, e.g. to store spawn
+//! points or collision tags. By default these chunks are skipped by the reader.
+//! If you register a [`ChunkCodec`] for a chunk ID in
+//! [`crate::reader::ReaderOptions::codecs`], chunks with that ID are decoded
+//! and passed to [`crate::data::VoxBuffer::set_custom_chunk`]. [`VoxData`]
+//! collects them into [`VoxData::custom_chunks`], and the writer writes them
+//! back.
+//!
+//! # Example
+//!
+//! ```
+//! # use std::io::{Cursor, Write};
+//! # use vox_format::{chunk::ChunkId, custom::ChunkCodec, reader::{from_reader_with_options, ReaderOptions}, to_vec, VoxData};
+//! // A chunk that contains a list of spawn point names.
+//! let codec = ChunkCodec::new(
+//!     ChunkId::from(*b"SPWN"),
+//!     |data: &[u8]| Ok(String::from_utf8(data.to_vec())?),
+//!     |value: &String, writer| Ok(writer.write_all(value.as_bytes())?),
+//! );
+//!
+//! let mut vox = VoxData::default();
+//! vox.custom_chunks.push(codec.chunk("player-1".to_owned()));
+//! let data = to_vec(&vox).unwrap();
+//!
+//! let mut options = ReaderOptions::default();
+//! options.codecs.register(codec);
+//! let (vox, _) = from_reader_with_options(Cursor::new(data), &options).unwrap();
+//! assert_eq!(vox.custom_chunks[0].get::<String>().unwrap(), "player-1");
+//! ```
+//!
+//! [`VoxData`]: crate::data::VoxData
+//! [`VoxData::custom_chunks`]: crate::data::VoxModels::custom_chunks
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+};
+
+use crate::{
+    chunk::ChunkId,
+    reader::Error as ReadError,
+    writer::Error as WriteError,
+};
+
+/// A decoded value of a custom chunk.
+pub type AnyValue = dyn Any + Send + Sync;
+
+type ReadFn = dyn Fn(&[u8]) -> Result<Box<AnyValue>, ReadError> + Send + Sync;
+
+type WriteFn = dyn Fn(&AnyValue, &mut Vec<u8>) -> Result<(), WriteError> + Send + Sync;
+
+/// Decodes and encodes the contents of chunks with a specific ID.
+#[derive(Clone)]
+pub struct ChunkCodec {
+    id: ChunkId,
+    read: Arc<ReadFn>,
+    write: Arc<WriteFn>,
+}
+
+impl ChunkCodec {
+    /// Creates a codec for values of type `T`.
+    ///
+    /// `read` decodes the chunk's contents, and `write` encodes a value as the
+    /// chunk's contents.
+    pub fn new<T, R, W>(id: ChunkId, read: R, write: W) -> Self
+    where
+        T: Any + Send + Sync,
+        R: Fn(&[u8]) -> Result<T, ReadError> + Send + Sync + 'static,
+        W: Fn(&T, &mut Vec<u8>) -> Result<(), WriteError> + Send + Sync + 'static,
+    {
+        Self::from_any(
+            id,
+            move |data| Ok(Box::new(read(data)?)),
+            move |value, writer| {
+                let value = value
+                    .downcast_ref::<T>()
+                    .ok_or(WriteError::InvalidCustomChunk { chunk_id: id })?;
+                write(value, writer)
+            },
+        )
+    }
+
+    /// Creates a codec that works with type-erased values. `write` is called
+    /// with any value that is stored in a [`CustomChunk`] with this codec, so
+    /// it must check the type itself.
+    pub fn from_any<R, W>(id: ChunkId, read: R, write: W) -> Self
+    where
+        R: Fn(&[u8]) -> Result<Box<AnyValue>, ReadError> + Send + Sync + 'static,
+        W: Fn(&AnyValue, &mut Vec<u8>) -> Result<(), WriteError> + Send + Sync + 'static,
+    {
+        Self {
+            id,
+            read: Arc::new(read),
+            write: Arc::new(write),
+        }
+    }
+
+    /// Returns the ID of the chunks this codec decodes.
+    pub fn id(&self) -> ChunkId {
+        self.id
+    }
+
+    /// Decodes the contents of a chunk.
+    pub fn decode(&self, data: &[u8]) -> Result<CustomChunk, ReadError> {
+        Ok(CustomChunk {
+            codec: self.clone(),
+            value: (self.read)(data)?.into(),
+        })
+    }
+
+    /// Creates a custom chunk with the given value, e.g. to add it to
+    /// [`crate::data::VoxModels::custom_chunks`].
+    pub fn chunk<T: Any + Send + Sync>(&self, value: T) -> CustomChunk {
+        CustomChunk {
+            codec: self.clone(),
+            value: Arc::new(value),
+        }
+    }
+}
+
+impl fmt::Debug for ChunkCodec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkCodec").field("id", &self.id).finish()
+    }
+}
+
+/// A set of codecs for custom chunks, keyed by chunk ID.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkCodecs {
+    codecs: HashMap<ChunkId, ChunkCodec>,
+}
+
+impl ChunkCodecs {
+    /// Registers a codec. A codec that was previously registered for the same
+    /// chunk ID is replaced.
+    pub fn register(&mut self, codec: ChunkCodec) {
+        self.codecs.insert(codec.id(), codec);
+    }
+
+    /// Returns the codec for the chunk ID, if one was registered.
+    pub fn get(&self, id: ChunkId) -> Option<&ChunkCodec> {
+        self.codecs.get(&id)
+    }
+
+    /// Returns `true`, if no codecs were registered.
+    pub fn is_empty(&self) -> bool {
+        self.codecs.is_empty()
+    }
+}
+
+/// A decoded custom chunk. It keeps a reference to its codec, so that it can
+/// be written back.
+#[derive(Clone)]
+pub struct CustomChunk {
+    codec: ChunkCodec,
+    value: Arc<AnyValue>,
+}
+
+impl CustomChunk {
+    /// Returns the chunk ID.
+    pub fn id(&self) -> ChunkId {
+        self.codec.id()
+    }
+
+    /// Returns the decoded value, if it has type `T`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Returns the decoded value.
+    pub fn value(&self) -> &AnyValue {
+        &*self.value
+    }
+
+    /// Replaces the value.
+    pub fn set<T: Any + Send + Sync>(&mut self, value: T) {
+        self.value = Arc::new(value);
+    }
+
+    /// Encodes the value using the chunk's codec.
+    pub fn encode(&self) -> Result<Vec<u8>, WriteError> {
+        let mut buf = vec![];
+        (self.codec.write)(&*self.value, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+impl fmt::Debug for CustomChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomChunk")
+            .field("id", &self.id())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{
+        Cursor,
+        Write,
+    };
+
+    use super::ChunkCodec;
+    use crate::{
+        chunk::ChunkId,
+        from_slice,
+        reader::{
+            from_reader_with_options,
+            ReaderOptions,
+        },
+        to_vec,
+        writer::Error as WriteError,
+        VoxData,
+    };
+
+    fn spawn_points() -> ChunkCodec {
+        ChunkCodec::new(
+            ChunkId::from(*b"SPWN"),
+            |data: &[u8]| {
+                Ok(data
+                    .chunks_exact(3)
+                    .map(|p| [p[0], p[1], p[2]])
+                    .collect::<Vec<_>>())
+            },
+            |points: &Vec<[u8; 3]>, writer| {
+                for point in points {
+                    writer.write_all(point)?;
+                }
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn it_round_trips_custom_chunks() {
+        let codec = spawn_points();
+        let mut vox = VoxData::default();
+        vox.custom_chunks
+            .push(codec.chunk(vec![[1u8, 2, 3], [4, 5, 6]]));
+        let data = to_vec(&vox).unwrap();
+
+        // Without the codec, the chunk is skipped.
+        assert!(from_slice(&data).unwrap().custom_chunks.is_empty());
+
+        let mut options = ReaderOptions::default();
+        options.codecs.register(codec);
+        let (vox, _) = from_reader_with_options(Cursor::new(&data), &options).unwrap();
+
+        assert_eq!(vox.custom_chunks.len(), 1);
+        assert_eq!(vox.custom_chunks[0].id(), ChunkId::from(*b"SPWN"));
+        assert_eq!(
+            vox.custom_chunks[0].get::<Vec<[u8; 3]>>().unwrap(),
+            &vec![[1, 2, 3], [4, 5, 6]]
+        );
+        assert_eq!(to_vec(&vox).unwrap(), data);
+    }
+
+    #[test]
+    fn it_rejects_values_of_the_wrong_type() {
+        let mut vox = VoxData::default();
+        vox.custom_chunks.push(spawn_points().chunk("not a point"));
+
+        let error = to_vec(&vox).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            WriteError::InvalidCustomChunk { .. }
+        ));
+    }
+}
@@ -1,18 +1,35 @@
 //! Contains trait for for reading voxel data, and a simple implementation for
 //! it.
 
+use std::io::Write;
+
+use byteorder::{
+    WriteBytesExt,
+    LE,
+};
 #[cfg(feature = "serialize")]
 use serde::{
     Deserialize,
     Serialize,
 };
 
-use crate::types::{
-    Model,
-    Palette,
-    Size,
-    Version,
-    Voxel,
+use crate::{
+    custom::CustomChunk,
+    types::{
+        Attributes,
+        ColorIndex,
+        Layer,
+        Material,
+        MaterialPalette,
+        MaterialProperties,
+        Model,
+        Node,
+        Palette,
+        Size,
+        Version,
+        Voxel,
+    },
+    writer::Error as WriteError,
 };
 
 /// A simple implementation of [`VoxBuffer`] that collects voxels into `Vec`s.
@@ -38,13 +55,17 @@ impl VoxModelBuffer for Model {
 /// These are always called in this order:
 /// 1. `set_version`
 /// 2. `set_palette`
-/// 3. `set_num_models`
-/// 4. `set_model_size`
+/// 3. `set_material` and `set_material_properties`
+/// 4. `set_num_models`
+/// 5. `set_model_size`
 ///   1. `set_voxel`
+/// 6. `set_node`
+/// 7. `set_layer`
 ///
 /// `set_model_size` is always called before the voxels from this model are
 /// passed via `set_voxel`. `set_model_size` is called for each model, and
-/// `set_voxel` is called for each voxel in a model.
+/// `set_voxel` is called for each voxel in a model. `set_node` and `set_layer`
+/// are called for each node and layer in the order they appear in the file.
 pub trait VoxBuffer {
     /// Called after the file version was read.
     ///
@@ -65,6 +86,22 @@ pub trait VoxBuffer {
     /// Called when the color palette was read. This will be read before any
     /// calls to [`Self::set_voxel`].
     fn set_palette(&mut self, palette: Palette);
+
+    /// Called for each material in a legacy `MATT` chunk.
+    fn set_material(&mut self, _material_id: ColorIndex, _material: Material) {}
+
+    /// Called for each `MATL` chunk.
+    fn set_material_properties(&mut self, _properties: MaterialProperties) {}
+
+    /// Called for each node of the scene graph.
+    fn set_node(&mut self, _node: Node) {}
+
+    /// Called for each layer.
+    fn set_layer(&mut self, _layer: Layer) {}
+
+    /// Called for each custom chunk, for which a codec was registered in
+    /// [`crate::reader::ReaderOptions::codecs`].
+    fn set_custom_chunk(&mut self, _chunk: CustomChunk) {}
 }
 
 /// Trait for reading a single model.
@@ -81,6 +118,22 @@ pub struct VoxModels<V> {
     pub version: Version,
     pub models: Vec<V>,
     pub palette: Palette,
+
+    /// Materials from legacy `MATT` chunks.
+    pub materials: MaterialPalette,
+
+    /// Materials from `MATL` chunks, in the order they appear in the file.
+    pub material_properties: Vec<MaterialProperties>,
+
+    /// The nodes of the scene graph, in the order they appear in the file.
+    pub nodes: Vec<Node>,
+
+    pub layers: Vec<Layer>,
+
+    /// Custom chunks, in the order they appear in the file. See
+    /// [`crate::custom`].
+    #[cfg_attr(feature = "serialize", serde(skip))]
+    pub custom_chunks: Vec<CustomChunk>,
 }
 
 impl<V> Default for VoxModels<V> {
@@ -89,10 +142,23 @@ impl<V> Default for VoxModels<V> {
             version: Version::default(),
             models: vec![],
             palette: Palette::default(),
+            materials: MaterialPalette::default(),
+            material_properties: vec![],
+            nodes: vec![],
+            layers: vec![],
+            custom_chunks: vec![],
         }
     }
 }
 
+impl<V> VoxModels<V> {
+    /// Returns the scene graph node with the given ID, or `None`, if there is
+    /// no such node.
+    pub fn node(&self, node_id: u32) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.node_id() == node_id)
+    }
+}
+
 impl<V: VoxModelBuffer> VoxBuffer for VoxModels<V> {
     fn set_version(&mut self, version: Version) {
         self.version = version;
@@ -114,4 +180,258 @@ impl<V: VoxModelBuffer> VoxBuffer for VoxModels<V> {
     fn set_palette(&mut self, palette: Palette) {
         self.palette = palette;
     }
+
+    fn set_material(&mut self, material_id: ColorIndex, material: Material) {
+        self.materials.insert(material_id, material);
+    }
+
+    fn set_material_properties(&mut self, properties: MaterialProperties) {
+        self.material_properties.push(properties);
+    }
+
+    fn set_node(&mut self, node: Node) {
+        self.nodes.push(node);
+    }
+
+    fn set_layer(&mut self, layer: Layer) {
+        self.layers.push(layer);
+    }
+
+    fn set_custom_chunk(&mut self, chunk: CustomChunk) {
+        self.custom_chunks.push(chunk);
+    }
+}
+
+impl VoxData {
+    /// Returns a hash of the content, which doesn't depend on how it's laid
+    /// out in a file. Voxels, materials, nodes, layers and attributes are
+    /// hashed in a sorted order, so e.g. reordering the voxels of a model
+    /// doesn't change the hash. The order of models and of a group's children
+    /// is significant though, since they're referred to by index.
+    ///
+    /// The hash doesn't depend on the platform, so it can be used as a cache
+    /// key for build outputs. Write files with
+    /// [`crate::writer::WriterOptions::canonical`] to also get identical
+    /// bytes for identical content.
+    ///
+    /// This fails, if a custom chunk can't be encoded.
+    pub fn canonical_hash(&self) -> Result<u64, WriteError> {
+        let mut hasher = CanonicalHasher::default();
+
+        self.version.write(&mut hasher)?;
+
+        hasher.write_u64::<LE>(self.models.len() as u64)?;
+        for model in &self.models {
+            model.size.write(&mut hasher)?;
+            let mut voxels = model.voxels.clone();
+            voxels.sort_unstable_by_key(|voxel| {
+                let point = voxel.point;
+                (
+                    point.z as u8,
+                    point.y as u8,
+                    point.x as u8,
+                    voxel.color_index,
+                )
+            });
+            hasher.write_u64::<LE>(voxels.len() as u64)?;
+            for voxel in &voxels {
+                voxel.write(&mut hasher)?;
+            }
+        }
+
+        self.palette.write(&mut hasher)?;
+
+        let mut materials = self.materials.iter().collect::<Vec<_>>();
+        materials.sort_unstable_by_key(|(material_id, _)| *material_id);
+        hasher.write_u64::<LE>(materials.len() as u64)?;
+        for (material_id, material) in materials {
+            material.write_matt(material_id, &mut hasher)?;
+        }
+
+        // Only hashed if present, so that hashes of older files stay the same.
+        if !self.material_properties.is_empty() {
+            let mut material_properties = self.material_properties.iter().collect::<Vec<_>>();
+            material_properties.sort_by_key(|properties| properties.material_id);
+            hasher.write_u64::<LE>(material_properties.len() as u64)?;
+            for properties in material_properties {
+                hasher.write_u32::<LE>(properties.material_id)?;
+                hash_attributes(&mut hasher, &properties.attributes)?;
+            }
+        }
+
+        let mut nodes = self.nodes.iter().collect::<Vec<_>>();
+        nodes.sort_by_key(|node| node.node_id());
+        hasher.write_u64::<LE>(nodes.len() as u64)?;
+        for node in nodes {
+            hash_node(&mut hasher, node)?;
+        }
+
+        let mut layers = self.layers.iter().collect::<Vec<_>>();
+        layers.sort_by_key(|layer| layer.node_id);
+        hasher.write_u64::<LE>(layers.len() as u64)?;
+        for layer in layers {
+            hasher.write_u32::<LE>(layer.node_id)?;
+            hash_attributes(&mut hasher, &layer.attributes)?;
+            hash_id_opt(&mut hasher, layer.reserved_id)?;
+        }
+
+        // The sort is stable, so chunks with the same ID keep their order.
+        let mut custom_chunks = self.custom_chunks.iter().collect::<Vec<_>>();
+        custom_chunks.sort_by_key(|custom_chunk| <[u8; 4]>::from(custom_chunk.id()));
+        hasher.write_u64::<LE>(custom_chunks.len() as u64)?;
+        for custom_chunk in custom_chunks {
+            custom_chunk.id().write(&mut hasher)?;
+            hash_bytes(&mut hasher, &custom_chunk.encode()?)?;
+        }
+
+        Ok(hasher.0)
+    }
+}
+
+fn hash_node(hasher: &mut CanonicalHasher, node: &Node) -> Result<(), WriteError> {
+    match node {
+        Node::Transform(transform) => {
+            hasher.write_u8(0)?;
+            hasher.write_u32::<LE>(transform.node_id)?;
+            hash_attributes(hasher, &transform.attributes)?;
+            hasher.write_u32::<LE>(transform.child_node_id)?;
+            hash_id_opt(hasher, transform.reserved_id)?;
+            hash_id_opt(hasher, transform.layer_id)?;
+            hasher.write_u64::<LE>(transform.frames.len() as u64)?;
+            for frame in &transform.frames {
+                hash_attributes(hasher, frame)?;
+            }
+        }
+        Node::Group(group) => {
+            hasher.write_u8(1)?;
+            hasher.write_u32::<LE>(group.node_id)?;
+            hash_attributes(hasher, &group.attributes)?;
+            hasher.write_u64::<LE>(group.children.len() as u64)?;
+            for child in &group.children {
+                hasher.write_u32::<LE>(*child)?;
+            }
+        }
+        Node::Shape(shape) => {
+            hasher.write_u8(2)?;
+            hasher.write_u32::<LE>(shape.node_id)?;
+            hash_attributes(hasher, &shape.attributes)?;
+            hasher.write_u64::<LE>(shape.models.len() as u64)?;
+            for model in &shape.models {
+                hasher.write_u32::<LE>(model.model_id)?;
+                hash_attributes(hasher, &model.attributes)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn hash_attributes(
+    hasher: &mut CanonicalHasher,
+    attributes: &Attributes,
+) -> Result<(), WriteError> {
+    let mut attributes = attributes.iter().collect::<Vec<_>>();
+    attributes.sort_unstable();
+    hasher.write_u64::<LE>(attributes.len() as u64)?;
+    for (key, value) in attributes {
+        hash_bytes(hasher, key.as_bytes())?;
+        hash_bytes(hasher, value.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn hash_id_opt(hasher: &mut CanonicalHasher, id: Option<u32>) -> Result<(), WriteError> {
+    match id {
+        Some(id) => {
+            hasher.write_u8(1)?;
+            hasher.write_u32::<LE>(id)?;
+        }
+        None => hasher.write_u8(0)?,
+    }
+    Ok(())
+}
+
+/// Hashes a length-prefixed byte string, so that e.g. adjacent strings can't
+/// be shifted into each other.
+fn hash_bytes(hasher: &mut CanonicalHasher, bytes: &[u8]) -> Result<(), WriteError> {
+    hasher.write_u64::<LE>(bytes.len() as u64)?;
+    hasher.write_all(bytes)?;
+    Ok(())
+}
+
+/// A 64-bit FNV-1a hash. Unlike [`std::collections::hash_map::DefaultHasher`]
+/// its output is specified, so it's stable across platforms and Rust
+/// versions. It implements [`std::io::Write`], so that the types' `write`
+/// methods can be used to feed it.
+struct CanonicalHasher(u64);
+
+impl Default for CanonicalHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for CanonicalHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for byte in buf {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VoxData;
+    use crate::{
+        from_slice,
+        types::{
+            Model,
+            Vector,
+            Voxel,
+        },
+        writer::{
+            to_vec_with_options,
+            WriterOptions,
+        },
+    };
+
+    fn vox(voxels: Vec<Voxel>) -> VoxData {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 2, 2),
+            voxels,
+        });
+        vox
+    }
+
+    #[test]
+    fn the_canonical_hash_ignores_the_voxel_order() {
+        let a = vox(vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 1, 1], 2)]);
+        let b = vox(vec![Voxel::new([1, 1, 1], 2), Voxel::new([0, 0, 0], 1)]);
+        let c = vox(vec![Voxel::new([1, 1, 1], 3), Voxel::new([0, 0, 0], 1)]);
+
+        assert_eq!(a.canonical_hash().unwrap(), b.canonical_hash().unwrap());
+        assert_ne!(a.canonical_hash().unwrap(), c.canonical_hash().unwrap());
+
+        let options = WriterOptions::canonical();
+        let data = to_vec_with_options(&a, &options).unwrap();
+        assert_eq!(data, to_vec_with_options(&b, &options).unwrap());
+        assert_eq!(
+            from_slice(&data).unwrap().canonical_hash().unwrap(),
+            a.canonical_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn the_canonical_hash_is_stable() {
+        // This must only change, if the hashed content changes on purpose, since it's
+        // used as a cache key.
+        let vox = vox(vec![Voxel::new([0, 0, 0], 1)]);
+        assert_eq!(vox.canonical_hash().unwrap(), 0xed84_2596_c70b_6fad);
+    }
 }
@@ -0,0 +1,772 @@
+//! Exporting scenes to glTF 2.0.
+//!
+//! [`export`] meshes every model with [`crate::mesh`] and writes a glTF file
+//! with a node for each object in the scene graph. Nodes have the names and
+//! transforms of their objects at frame 0, and hidden objects, or objects on
+//! hidden layers, are marked as invisible using `KHR_node_visibility`.
+//!
+//! The palette is embedded as a 256x1 PNG texture, which all materials use as
+//! base color. MATL material properties are mapped to PBR materials:
+//!
+//!  * `_metal` materials get their metalness (`_metal`) and roughness
+//!    (`_rough`).
+//!  * `_glass` materials use `KHR_materials_transmission` for their
+//!    transparency (`_trans`) and `KHR_materials_ior` for their index of
+//!    refraction (`_ior`).
+//!  * `_emit` materials use the palette texture as emissive texture and
+//!    `KHR_materials_emissive_strength` for their emission. The strength is
+//!    `_emit` and is doubled for each step of `_flux`.
+//!  * All other materials are fully rough dielectrics.
+//!
+//! MagicaVoxel's z axis points up, while glTF's y axis does. The scene is
+//! placed below a root node that rotates it accordingly. Voxel centers are at
+//! the integer coordinates that [`crate::bake`] would place them at.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{gltf::{export, GltfFormat, GltfOptions}, types::{Model, Vector, Voxel}, VoxData};
+//! let mut vox = VoxData::default();
+//! vox.models.push(Model {
+//!     size: Vector::new(1, 1, 1),
+//!     voxels: vec![Voxel::new([0, 0, 0], 1)],
+//! });
+//!
+//! let glb = export(&vox, GltfFormat::Glb, &GltfOptions::default()).unwrap();
+//! assert_eq!(&glb[..4], b"glTF");
+//! ```
+
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt,
+    str::FromStr,
+};
+
+use image::{
+    png::PngEncoder,
+    ColorType,
+};
+use serde_json::{
+    json,
+    Map,
+    Value,
+};
+use thiserror::Error;
+
+use crate::{
+    animation::AnimationError,
+    data::VoxData,
+    mesh::{
+        mesh,
+        Mesh,
+        MeshOptions,
+    },
+    scene::{
+        Object,
+        SceneError,
+    },
+    types::{
+        ColorIndex,
+        HiddenParseError,
+        MaterialProperties,
+    },
+};
+
+const GLB_MAGIC: u32 = 0x4654_6c67;
+const GLB_VERSION: u32 = 2;
+const GLB_CHUNK_JSON: u32 = 0x4e4f_534a;
+const GLB_CHUNK_BIN: u32 = 0x004e_4942;
+
+const COMPONENT_FLOAT: u32 = 5126;
+const COMPONENT_UNSIGNED_INT: u32 = 5125;
+const TARGET_ARRAY_BUFFER: u32 = 34962;
+const TARGET_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FILTER_NEAREST: u32 = 9728;
+const WRAP_CLAMP_TO_EDGE: u32 = 33071;
+
+/// Rotates MagicaVoxel's z-up coordinates into glTF's y-up coordinates, as a
+/// column-major matrix.
+const Z_UP_TO_Y_UP: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+
+/// Error type for [`export`].
+#[derive(Debug, Error)]
+pub enum GltfError {
+    #[error("Shape refers to model {model_id}, which doesn't exist.")]
+    InvalidModelId { model_id: u32 },
+
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+
+    #[error("{0}")]
+    Hidden(#[from] HiddenParseError),
+
+    #[error("{0}")]
+    Image(#[from] image::ImageError),
+
+    #[error("{0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// The container format of an exported glTF file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GltfFormat {
+    /// A JSON file (`.gltf`), with the binary data embedded as data URI.
+    Gltf,
+
+    /// A binary file (`.glb`).
+    Glb,
+}
+
+impl GltfFormat {
+    /// Returns the file extension for the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GltfFormat::Gltf => "gltf",
+            GltfFormat::Glb => "glb",
+        }
+    }
+}
+
+impl fmt::Display for GltfFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+impl FromStr for GltfFormat {
+    type Err = GltfFormatParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gltf" => Ok(GltfFormat::Gltf),
+            "glb" => Ok(GltfFormat::Glb),
+            _ => Err(GltfFormatParseError(s.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown glTF format: {0}")]
+pub struct GltfFormatParseError(String);
+
+/// Options for [`export`].
+#[derive(Clone, Debug, Default)]
+pub struct GltfOptions {
+    /// Store ambient occlusion as vertex colors (`COLOR_0`), which viewers
+    /// multiply with the base color.
+    pub ambient_occlusion: bool,
+}
+
+/// Exports the scene to glTF. See the [module documentation](self).
+pub fn export(
+    vox: &VoxData,
+    format: GltfFormat,
+    options: &GltfOptions,
+) -> Result<Vec<u8>, GltfError> {
+    let mut builder = Builder {
+        color_materials: vec![None; 256],
+        ..Builder::default()
+    };
+
+    let mut png = vec![];
+    PngEncoder::new(&mut png).encode(vox.palette.as_image().as_raw(), 256, 1, ColorType::Rgba8)?;
+    let image_view = builder.push_view(&png, None);
+
+    // Mesh each model once, so that shapes which display the same model share
+    // it.
+    let mesh_options = MeshOptions {
+        ambient_occlusion: options.ambient_occlusion,
+    };
+    let mut meshes = HashMap::new();
+
+    let scene = vox.scene()?;
+    let objects = scene.objects().collect::<Vec<_>>();
+    let node_indices = objects
+        .iter()
+        .enumerate()
+        .map(|(i, (id, _))| (*id, i + 1))
+        .collect::<HashMap<_, _>>();
+
+    let mut nodes = vec![json!({
+        "matrix": Z_UP_TO_Y_UP,
+        "children": [node_indices[&scene.root()]],
+    })];
+
+    for (_, object) in &objects {
+        let mut node = Map::new();
+        if let Some(name) = object.name() {
+            node.insert("name".into(), name.into());
+        }
+
+        let pose = object.transform.pose_at(0)?;
+        let m = pose.rotation.to_matrix();
+        let t = pose.translation;
+        if m != [[1, 0, 0], [0, 1, 0], [0, 0, 1]] {
+            let r = |i: usize, j: usize| f32::from(m[i][j]);
+            node.insert(
+                "matrix".into(),
+                json!([
+                    r(0, 0),
+                    r(1, 0),
+                    r(2, 0),
+                    0.0,
+                    r(0, 1),
+                    r(1, 1),
+                    r(2, 1),
+                    0.0,
+                    r(0, 2),
+                    r(1, 2),
+                    r(2, 2),
+                    0.0,
+                    t.x as f32,
+                    t.y as f32,
+                    t.z as f32,
+                    1.0,
+                ]),
+            );
+        }
+        else if t.x != 0 || t.y != 0 || t.z != 0 {
+            node.insert(
+                "translation".into(),
+                json!([t.x as f32, t.y as f32, t.z as f32]),
+            );
+        }
+
+        if !object.children().is_empty() {
+            let children = object
+                .children()
+                .iter()
+                .map(|id| node_indices[id])
+                .collect::<Vec<_>>();
+            node.insert("children".into(), children.into());
+        }
+
+        if let Some(shape) = object.shape() {
+            if let Some(model_id) = shape.model_at(0)? {
+                let mesh_index = match meshes.get(&model_id) {
+                    Some(mesh_index) => *mesh_index,
+                    None => {
+                        let model = vox
+                            .models
+                            .get(model_id as usize)
+                            .ok_or(GltfError::InvalidModelId { model_id })?;
+                        let pivot = model.pivot();
+                        let offset = [
+                            pivot.x as f32 + 0.5,
+                            pivot.y as f32 + 0.5,
+                            pivot.z as f32 + 0.5,
+                        ];
+                        let mesh_index = builder.push_mesh(
+                            vox,
+                            &mesh(model, &vox.palette, &mesh_options),
+                            offset,
+                            options,
+                        );
+                        meshes.insert(model_id, mesh_index);
+                        mesh_index
+                    }
+                };
+                if let Some(mesh_index) = mesh_index {
+                    node.insert("mesh".into(), mesh_index.into());
+                }
+            }
+        }
+
+        if is_hidden(vox, object)? {
+            builder.use_extension("KHR_node_visibility");
+            node.insert(
+                "extensions".into(),
+                json!({ "KHR_node_visibility": { "visible": false } }),
+            );
+        }
+
+        nodes.push(node.into());
+    }
+
+    let mut materials = vec![];
+    for material in &builder.materials {
+        materials.push(material.to_json(&mut builder.extensions));
+    }
+
+    let mut root = json!({
+        "asset": {
+            "version": "2.0",
+            "generator": concat!("vox-format ", env!("CARGO_PKG_VERSION")),
+        },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": nodes,
+        "textures": [{ "sampler": 0, "source": 0 }],
+        "samplers": [{
+            "magFilter": FILTER_NEAREST,
+            "minFilter": FILTER_NEAREST,
+            "wrapS": WRAP_CLAMP_TO_EDGE,
+            "wrapT": WRAP_CLAMP_TO_EDGE,
+        }],
+        "images": [{ "bufferView": image_view, "mimeType": "image/png" }],
+        "bufferViews": builder.views,
+        "buffers": [{ "byteLength": builder.buffer.len() }],
+    });
+    // glTF doesn't allow empty arrays, which these are if there are no voxels.
+    for (key, values) in [
+        ("meshes", builder.meshes),
+        ("materials", materials),
+        ("accessors", builder.accessors),
+    ] {
+        if !values.is_empty() {
+            root[key] = values.into();
+        }
+    }
+    if !builder.extensions.is_empty() {
+        root["extensionsUsed"] = builder.extensions.clone().into();
+    }
+
+    match format {
+        GltfFormat::Gltf => {
+            root["buffers"][0]["uri"] = format!(
+                "data:application/octet-stream;base64,{}",
+                base64::encode(&builder.buffer)
+            )
+            .into();
+            Ok(serde_json::to_vec_pretty(&root)?)
+        }
+        GltfFormat::Glb => Ok(glb(&serde_json::to_vec(&root)?, &builder.buffer)),
+    }
+}
+
+/// Returns `true`, if the object is hidden, or is on a hidden layer.
+fn is_hidden(vox: &VoxData, object: &Object) -> Result<bool, GltfError> {
+    if object.transform.attributes.hidden()?.unwrap_or_default() {
+        return Ok(true);
+    }
+    match object.layer_id().and_then(|layer_id| vox.layer(layer_id)) {
+        Some(layer) => Ok(layer.is_hidden()?),
+        None => Ok(false),
+    }
+}
+
+/// Wraps the JSON and the binary buffer in a GLB container.
+fn glb(json: &[u8], bin: &[u8]) -> Vec<u8> {
+    let padded = |len: usize| (len + 3) & !3;
+    let json_len = padded(json.len());
+    let bin_len = padded(bin.len());
+    let total_len = 12 + 8 + json_len + 8 + bin_len;
+
+    let mut data = Vec::with_capacity(total_len);
+    for word in [GLB_MAGIC, GLB_VERSION, total_len as u32] {
+        data.extend_from_slice(&word.to_le_bytes());
+    }
+
+    data.extend_from_slice(&(json_len as u32).to_le_bytes());
+    data.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+    data.extend_from_slice(json);
+    data.resize(data.len() + json_len - json.len(), b' ');
+
+    data.extend_from_slice(&(bin_len as u32).to_le_bytes());
+    data.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+    data.extend_from_slice(bin);
+    data.resize(total_len, 0);
+
+    data
+}
+
+/// A glTF material for a MATL material.
+#[derive(Clone, Debug, PartialEq)]
+struct PbrMaterial {
+    name: &'static str,
+    metallic: f32,
+    roughness: f32,
+    transmission: Option<(f32, Option<f32>)>,
+    emissive_strength: Option<f32>,
+}
+
+impl PbrMaterial {
+    fn new(properties: Option<&MaterialProperties>) -> Self {
+        let mut material = Self {
+            name: "diffuse",
+            metallic: 0.0,
+            roughness: 1.0,
+            transmission: None,
+            emissive_strength: None,
+        };
+        let properties = match properties {
+            Some(properties) => properties,
+            None => return material,
+        };
+        let get = |key, default| properties.get_f32(key).unwrap_or(default);
+
+        match properties.material_type() {
+            Some("_metal") => {
+                material.name = "metal";
+                material.metallic = get("_metal", 0.0);
+                material.roughness = get("_rough", 1.0);
+            }
+            Some("_glass") => {
+                material.name = "glass";
+                material.roughness = get("_rough", 1.0);
+                material.transmission = Some((
+                    get("_trans", 0.0),
+                    properties.get_f32("_ior").map(|ior| 1.0 + ior),
+                ));
+            }
+            Some("_emit") => {
+                material.name = "emit";
+                material.emissive_strength =
+                    Some(get("_emit", 0.0) * 2.0f32.powf(get("_flux", 0.0)));
+            }
+            _ => {}
+        }
+
+        material
+    }
+
+    fn to_json(&self, extensions_used: &mut Vec<&'static str>) -> Value {
+        let mut use_extension = |extension| {
+            if !extensions_used.contains(&extension) {
+                extensions_used.push(extension);
+            }
+        };
+
+        let mut material = json!({
+            "name": self.name,
+            "pbrMetallicRoughness": {
+                "baseColorTexture": { "index": 0 },
+                "metallicFactor": self.metallic,
+                "roughnessFactor": self.roughness,
+            },
+        });
+
+        let mut extensions = Map::new();
+        if let Some((transmission, ior)) = self.transmission {
+            use_extension("KHR_materials_transmission");
+            extensions.insert(
+                "KHR_materials_transmission".into(),
+                json!({ "transmissionFactor": transmission }),
+            );
+            if let Some(ior) = ior {
+                use_extension("KHR_materials_ior");
+                extensions.insert("KHR_materials_ior".into(), json!({ "ior": ior }));
+            }
+        }
+        if let Some(strength) = self.emissive_strength {
+            material["emissiveTexture"] = json!({ "index": 0 });
+            material["emissiveFactor"] = json!([1.0, 1.0, 1.0]);
+            use_extension("KHR_materials_emissive_strength");
+            extensions.insert(
+                "KHR_materials_emissive_strength".into(),
+                json!({ "emissiveStrength": strength }),
+            );
+        }
+        if !extensions.is_empty() {
+            material["extensions"] = extensions.into();
+        }
+
+        material
+    }
+}
+
+/// Collects the binary buffer and the JSON objects that refer to it.
+#[derive(Default)]
+struct Builder {
+    buffer: Vec<u8>,
+    views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    materials: Vec<PbrMaterial>,
+    /// Material index for each color index, filled in when a color is first
+    /// used.
+    color_materials: Vec<Option<usize>>,
+    extensions: Vec<&'static str>,
+}
+
+impl Builder {
+    fn use_extension(&mut self, extension: &'static str) {
+        if !self.extensions.contains(&extension) {
+            self.extensions.push(extension);
+        }
+    }
+
+    /// Appends `data` to the buffer and returns the index of its buffer view.
+    fn push_view(&mut self, data: &[u8], target: Option<u32>) -> usize {
+        // Accessors must be aligned to their component size, which is always 4.
+        self.buffer.resize((self.buffer.len() + 3) & !3, 0);
+
+        let mut view = json!({
+            "buffer": 0,
+            "byteOffset": self.buffer.len(),
+            "byteLength": data.len(),
+        });
+        if let Some(target) = target {
+            view["target"] = target.into();
+        }
+        self.buffer.extend_from_slice(data);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    /// Appends vectors of floats and returns the index of their accessor.
+    fn push_floats<const N: usize>(&mut self, values: &[[f32; N]], bounds: bool) -> usize {
+        let data = values
+            .iter()
+            .flatten()
+            .flat_map(|x| x.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&data, Some(TARGET_ARRAY_BUFFER));
+
+        let mut accessor = json!({
+            "bufferView": view,
+            "componentType": COMPONENT_FLOAT,
+            "count": values.len(),
+            "type": format!("VEC{}", N),
+        });
+        if bounds {
+            let (min, max) = values.iter().fold(
+                ([f32::INFINITY; N], [f32::NEG_INFINITY; N]),
+                |(mut min, mut max), value| {
+                    for i in 0..N {
+                        min[i] = min[i].min(value[i]);
+                        max[i] = max[i].max(value[i]);
+                    }
+                    (min, max)
+                },
+            );
+            accessor["min"] = min.to_vec().into();
+            accessor["max"] = max.to_vec().into();
+        }
+
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    fn push_indices(&mut self, indices: &[u32]) -> usize {
+        let data = indices
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect::<Vec<_>>();
+        let view = self.push_view(&data, Some(TARGET_ELEMENT_ARRAY_BUFFER));
+
+        self.accessors.push(json!({
+            "bufferView": view,
+            "componentType": COMPONENT_UNSIGNED_INT,
+            "count": indices.len(),
+            "type": "SCALAR",
+        }));
+        self.accessors.len() - 1
+    }
+
+    fn material_index(&mut self, material: PbrMaterial) -> usize {
+        match self.materials.iter().position(|m| *m == material) {
+            Some(index) => index,
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        }
+    }
+
+    /// Returns the index of the material for a color index, adding the material
+    /// if the color wasn't used before.
+    fn color_material(&mut self, vox: &VoxData, color_index: ColorIndex) -> usize {
+        if let Some(material) = self.color_materials[usize::from(color_index.0)] {
+            return material;
+        }
+
+        let properties = vox
+            .material_properties
+            .iter()
+            .find(|properties| properties.material_id == u32::from(color_index.0));
+        let material = self.material_index(PbrMaterial::new(properties));
+        self.color_materials[usize::from(color_index.0)] = Some(material);
+        material
+    }
+
+    /// Adds a mesh, with one primitive per material. Returns `None`, if the
+    /// mesh is empty, since glTF meshes must have at least one primitive.
+    fn push_mesh(
+        &mut self,
+        vox: &VoxData,
+        mesh: &Mesh,
+        offset: [f32; 3],
+        options: &GltfOptions,
+    ) -> Option<usize> {
+        if mesh.is_empty() {
+            return None;
+        }
+
+        let positions = mesh
+            .positions
+            .iter()
+            .map(|p| [p[0] - offset[0], p[1] - offset[1], p[2] - offset[2]])
+            .collect::<Vec<_>>();
+        let mut attributes = json!({
+            "POSITION": self.push_floats(&positions, true),
+            "NORMAL": self.push_floats(&mesh.normals, false),
+            "TEXCOORD_0": self.push_floats(&mesh.uvs, false),
+        });
+        if options.ambient_occlusion {
+            let colors = mesh
+                .ambient_occlusion
+                .iter()
+                .map(|ao| [*ao, *ao, *ao])
+                .collect::<Vec<_>>();
+            attributes["COLOR_0"] = self.push_floats(&colors, false).into();
+        }
+
+        let mut triangles = BTreeMap::<_, Vec<u32>>::new();
+        for triangle in mesh.indices.chunks(3) {
+            let material = self.color_material(vox, mesh.color_indices[triangle[0] as usize]);
+            triangles
+                .entry(material)
+                .or_default()
+                .extend_from_slice(triangle);
+        }
+
+        let primitives = triangles
+            .into_iter()
+            .map(|(material, indices)| {
+                json!({
+                    "attributes": attributes,
+                    "indices": self.push_indices(&indices),
+                    "material": material,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.meshes.push(json!({ "primitives": primitives }));
+        Some(self.meshes.len() - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::{
+        export,
+        GltfFormat,
+        GltfOptions,
+    };
+    use crate::{
+        types::{
+            Attributes,
+            MaterialProperties,
+            Model,
+            Vector,
+            Voxel,
+        },
+        VoxData,
+    };
+
+    fn parse_glb(glb: &[u8]) -> (Value, &[u8]) {
+        let word = |i: usize| u32::from_le_bytes([glb[i], glb[i + 1], glb[i + 2], glb[i + 3]]);
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8) as usize, glb.len());
+
+        let json_len = word(12) as usize;
+        assert_eq!(&glb[16..20], b"JSON");
+        let json = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+
+        let bin_start = 20 + json_len;
+        let bin_len = word(bin_start) as usize;
+        assert_eq!(&glb[bin_start + 4..bin_start + 8], b"BIN\0");
+        (json, &glb[bin_start + 8..bin_start + 8 + bin_len])
+    }
+
+    #[test]
+    fn it_exports_scenes() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 1, 1),
+            voxels: vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 0, 0], 2)],
+        });
+        vox.models.push(Model {
+            size: Vector::new(1, 1, 1),
+            voxels: vec![],
+        });
+        let mut attributes = Attributes::new();
+        attributes.insert("_type", "_glass");
+        attributes.insert("_trans", "0.5");
+        attributes.insert("_ior", "0.3");
+        vox.material_properties.push(MaterialProperties {
+            material_id: 2,
+            attributes,
+        });
+
+        let mut scene = vox.scene().unwrap();
+        let copy = scene.add_shape(scene.root(), 0).unwrap();
+        let object = scene.get_mut(copy).unwrap();
+        object.set_name("copy");
+        object.transform.attributes.set_hidden(true);
+        object.transform.frames[0].set_translation(Vector::new(0, 0, 4));
+        vox.set_scene(&scene).unwrap();
+
+        let glb = export(&vox, GltfFormat::Glb, &GltfOptions::default()).unwrap();
+        let (json, bin) = parse_glb(&glb);
+        assert_eq!(
+            json["buffers"][0]["byteLength"].as_u64(),
+            Some(bin.len() as u64)
+        );
+
+        // The y-up root node and one node per object.
+        let nodes = json["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 1 + scene.objects().count());
+        let copy = nodes.iter().find(|node| node["name"] == "copy").unwrap();
+        assert_eq!(copy["translation"], serde_json::json!([0.0, 0.0, 4.0]));
+        assert_eq!(copy["extensions"]["KHR_node_visibility"]["visible"], false);
+
+        // Both shapes that display model 0 share its mesh, and the empty model has
+        // none.
+        assert_eq!(json["meshes"].as_array().unwrap().len(), 1);
+        assert_eq!(nodes.iter().filter(|node| node["mesh"] == 0).count(), 2);
+        assert_eq!(json["meshes"][0]["primitives"].as_array().unwrap().len(), 2);
+
+        let glass = json["materials"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|material| material["name"] == "glass")
+            .unwrap();
+        let extensions = &glass["extensions"];
+        assert_eq!(
+            extensions["KHR_materials_transmission"]["transmissionFactor"],
+            0.5
+        );
+        assert!((extensions["KHR_materials_ior"]["ior"].as_f64().unwrap() - 1.3).abs() < 1e-6);
+
+        let gltf = export(&vox, GltfFormat::Gltf, &GltfOptions::default()).unwrap();
+        let json: Value = serde_json::from_slice(&gltf).unwrap();
+        assert!(json["buffers"][0]["uri"]
+            .as_str()
+            .unwrap()
+            .starts_with("data:application/octet-stream;base64,"));
+    }
+
+    #[test]
+    fn it_exports_empty_scenes() {
+        let mut vox = VoxData::default();
+        let glb = export(&vox, GltfFormat::Glb, &GltfOptions::default()).unwrap();
+        let (json, _) = parse_glb(&glb);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 2);
+
+        // A model without voxels has no mesh either.
+        vox.models.push(Model {
+            size: Vector::new(1, 1, 1),
+            voxels: vec![],
+        });
+        let glb = export(&vox, GltfFormat::Glb, &GltfOptions::default()).unwrap();
+        let (json, _) = parse_glb(&glb);
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+        for key in &["meshes", "materials", "accessors"] {
+            assert!(json.get(key).is_none(), "{} must be omitted", key);
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Operations on the layers of a file.
+//!
+//! Each object in the scene graph is on a layer, which is stored as the
+//! `layer_id` of its [`Transform`] node. The layers themselves are stored in
+//! [`crate::data::VoxModels::layers`], identified by their `node_id`. Layer IDs
+//! are kept stable by these operations, i.e. deleting a layer doesn't renumber
+//! the others.
+//!
+//! # Example
+//!
+//! Keep only the objects on the "collision" and "visual" layers:
+//!
+//! ```
+//! # use vox_format::{types::{Model, Vector}, VoxData};
+//! # let mut vox = VoxData::default();
+//! # vox.models.push(Model { size: Vector::new(1, 1, 1), voxels: vec![] });
+//! let visual = vox.create_layer("visual").unwrap();
+//! vox.create_layer("collision").unwrap();
+//! vox.create_layer("notes").unwrap();
+//!
+//! // Write the default scene graph, in which the transform node 2 places the
+//! // model, and put the model on the "visual" layer.
+//! let scene = vox.scene().unwrap();
+//! vox.set_scene(&scene).unwrap();
+//! vox.move_to_layer(2, visual).unwrap();
+//!
+//! vox.retain_layers(|layer| matches!(layer.name(), Some("collision") | Some("visual")))
+//!     .unwrap();
+//!
+//! assert_eq!(vox.layers.len(), 2);
+//! assert!(vox.layer_by_name("notes").is_none());
+//! assert_eq!(vox.models_on_layer(visual).unwrap(), [0]);
+//! ```
+//!
+//! [`Transform`]: crate::types::Transform
+
+use thiserror::Error;
+
+use crate::{
+    data::VoxModels,
+    scene::SceneError,
+    types::{
+        Layer,
+        Node,
+    },
+};
+
+/// Error type for operations on layers.
+#[derive(Debug, Error)]
+pub enum LayerError {
+    #[error("Layer {layer_id} doesn't exist.")]
+    NoSuchLayer { layer_id: u32 },
+
+    #[error("Node {node_id} is not a transform node.")]
+    NotATransform { node_id: u32 },
+
+    #[error("Layer IDs overflowed.")]
+    LayerIdOverflow,
+
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+}
+
+impl<V> VoxModels<V> {
+    /// Returns the layer with the given ID.
+    pub fn layer(&self, layer_id: u32) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.node_id == layer_id)
+    }
+
+    /// Returns the layer with the given ID.
+    pub fn layer_mut(&mut self, layer_id: u32) -> Option<&mut Layer> {
+        self.layers
+            .iter_mut()
+            .find(|layer| layer.node_id == layer_id)
+    }
+
+    /// Returns the first layer with the given name.
+    pub fn layer_by_name(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|layer| layer.name() == Some(name))
+    }
+
+    /// Adds a new, empty layer and returns its ID, which is one more than the
+    /// largest layer ID.
+    pub fn create_layer(&mut self, name: impl Into<String>) -> Result<u32, LayerError> {
+        let layer_id = match self.layers.iter().map(|layer| layer.node_id).max() {
+            Some(max) => max.checked_add(1).ok_or(LayerError::LayerIdOverflow)?,
+            None => 0,
+        };
+
+        let mut layer = Layer::new(layer_id);
+        layer.set_name(name);
+        self.layers.push(layer);
+
+        Ok(layer_id)
+    }
+
+    /// Renames a layer.
+    pub fn rename_layer(
+        &mut self,
+        layer_id: u32,
+        name: impl Into<String>,
+    ) -> Result<(), LayerError> {
+        self.layer_mut(layer_id)
+            .ok_or(LayerError::NoSuchLayer { layer_id })?
+            .set_name(name);
+        Ok(())
+    }
+
+    /// Deletes a layer and removes all objects on it from the scene graph. The
+    /// removed layer is returned.
+    ///
+    /// Models that are no longer displayed are not removed.
+    pub fn delete_layer(&mut self, layer_id: u32) -> Result<Layer, LayerError> {
+        let index = self
+            .layers
+            .iter()
+            .position(|layer| layer.node_id == layer_id)
+            .ok_or(LayerError::NoSuchLayer { layer_id })?;
+
+        self.remove_objects_on_layers(&[layer_id])?;
+        Ok(self.layers.remove(index))
+    }
+
+    /// Deletes all layers for which `f` returns `false`, and removes the
+    /// objects on them from the scene graph. See [`Self::delete_layer`].
+    pub fn retain_layers(&mut self, mut f: impl FnMut(&Layer) -> bool) -> Result<(), LayerError> {
+        let removed = self
+            .layers
+            .iter()
+            .filter(|layer| !f(layer))
+            .map(|layer| layer.node_id)
+            .collect::<Vec<_>>();
+
+        if !removed.is_empty() {
+            self.remove_objects_on_layers(&removed)?;
+            self.layers
+                .retain(|layer| !removed.contains(&layer.node_id));
+        }
+
+        Ok(())
+    }
+
+    fn remove_objects_on_layers(&mut self, layer_ids: &[u32]) -> Result<(), LayerError> {
+        let mut scene = self.scene()?;
+
+        let objects = layer_ids
+            .iter()
+            .flat_map(|layer_id| scene.objects_on_layer(*layer_id))
+            .collect::<Vec<_>>();
+
+        for id in objects {
+            // The object might already have been removed with its parent.
+            if id != scene.root() && scene.get(id).is_some() {
+                scene.remove(id)?;
+            }
+        }
+
+        self.set_scene(&scene)?;
+        Ok(())
+    }
+
+    /// Moves the object placed by the transform node `node_id` to another
+    /// layer.
+    pub fn move_to_layer(&mut self, node_id: u32, layer_id: u32) -> Result<(), LayerError> {
+        if self.layer(layer_id).is_none() {
+            return Err(LayerError::NoSuchLayer { layer_id });
+        }
+
+        match self.nodes.iter_mut().find(|node| node.node_id() == node_id) {
+            Some(Node::Transform(transform)) => {
+                transform.layer_id = Some(layer_id);
+                Ok(())
+            }
+            _ => Err(LayerError::NotATransform { node_id }),
+        }
+    }
+
+    /// Returns the IDs of the models that are displayed by objects on the
+    /// given layer, in ascending order.
+    pub fn models_on_layer(&self, layer_id: u32) -> Result<Vec<u32>, LayerError> {
+        let scene = self.scene()?;
+
+        let mut model_ids = scene
+            .objects_on_layer(layer_id)
+            .into_iter()
+            .filter_map(|id| scene.get(id)?.shape())
+            .flat_map(|shape| shape.models.iter().map(|model| model.model_id))
+            .collect::<Vec<_>>();
+        model_ids.sort_unstable();
+        model_ids.dedup();
+
+        Ok(model_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayerError;
+    use crate::{
+        from_slice,
+        types::{
+            Color,
+            Node,
+        },
+        VoxData,
+    };
+
+    fn vox() -> VoxData {
+        from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap()
+    }
+
+    #[test]
+    fn it_manages_layers() {
+        let mut vox = vox();
+        let num_layers = vox.layers.len();
+        assert_eq!(vox.models_on_layer(0).unwrap(), [0, 1]);
+
+        let collision = vox.create_layer("collision").unwrap();
+        assert_eq!(vox.layers.len(), num_layers + 1);
+        assert_eq!(vox.layer(collision).unwrap().name(), Some("collision"));
+
+        let layer = vox.layer_mut(collision).unwrap();
+        layer.set_hidden(true);
+        layer.set_color(Color::light_blue());
+        assert!(layer.is_hidden().unwrap());
+        assert_eq!(layer.color().unwrap(), Some(Color::light_blue()));
+
+        vox.rename_layer(collision, "physics").unwrap();
+        assert_eq!(vox.layer_by_name("physics").unwrap().node_id, collision);
+        assert!(matches!(
+            vox.rename_layer(1000, "nope"),
+            Err(LayerError::NoSuchLayer { layer_id: 1000 })
+        ));
+
+        // Move the first object to the new layer.
+        let node_id = match vox.node(0) {
+            Some(Node::Transform(transform)) => transform.child_node_id,
+            node => panic!("Unexpected root node: {:?}", node),
+        };
+        let node_id = match vox.node(node_id) {
+            Some(Node::Group(group)) => group.children[0],
+            node => panic!("Unexpected node: {:?}", node),
+        };
+        vox.move_to_layer(node_id, collision).unwrap();
+        assert!(matches!(
+            vox.move_to_layer(1, collision),
+            Err(LayerError::NotATransform { node_id: 1 })
+        ));
+
+        let moved = vox.models_on_layer(collision).unwrap();
+        assert_eq!(moved.len(), 1);
+        assert_eq!(vox.models_on_layer(0).unwrap().len(), 1);
+
+        let layer = vox.delete_layer(collision).unwrap();
+        assert_eq!(layer.name(), Some("physics"));
+        assert_eq!(vox.layers.len(), num_layers);
+        assert!(vox.models_on_layer(collision).unwrap().is_empty());
+        assert_eq!(vox.scene().unwrap().objects().count(), 2);
+    }
+
+    #[test]
+    fn it_doesnt_overflow_layer_ids() {
+        let mut vox = vox();
+        let layer = vox.create_layer("last").unwrap();
+        vox.layer_mut(layer).unwrap().node_id = u32::MAX;
+        assert!(matches!(
+            vox.create_layer("overflow"),
+            Err(LayerError::LayerIdOverflow)
+        ));
+    }
+}
@@ -12,6 +12,17 @@
 //!
 //! ## Features
 //!
+//! ### `gzip` and `zstd` support
+//!
+//! Enables [`crate::compression`]. [`from_reader`] and [`from_file`] then
+//! detect VOX files compressed with gzip or zstd and decompress them
+//! transparently.
+//!
+//! ### `gltf` support
+//!
+//! Enables [`crate::gltf`], which exports scenes as glTF 2.0 files, using
+//! [`serde_json`].
+//!
 //! ### `image` support
 //!
 //! This crate has support for some conversion between its types and [`image`]
@@ -28,11 +39,22 @@
 //! This feature enables conversion between [`crate::types::Color`] and
 //! `Srgb<u8>` from the [`palette`] crate.
 //!
+//! ### `rayon` support
+//!
+//! Enables [`crate::parallel`], which decodes the models of a VOX file on
+//! multiple threads using [`rayon`].
+//!
 //! ### `serialize`
 //!
 //! Enables serialization using [`serde`] for types in [`crate::types`] and
 //! [`crate::data::VoxData`].
 //!
+//! ### `tokio` support
+//!
+//! Enables [`crate::async_io`], which reads and writes VOX files using
+//! [`tokio`]'s `AsyncRead` and `AsyncWrite`. Reading doesn't stream, but
+//! buffers the whole file and parses it on tokio's blocking thread pool.
+//!
 //! # This crate is work-in-progress
 //!
 //! Although this crate has a very limited scope and already mostly implements
@@ -50,18 +72,43 @@
 //! ```
 //! [MagicaVoxel's]: https://ephtracy.github.io/
 //! [`vox-tool`]: https://crates.io/crates/vox-tool
+//! [`serde_json`]: https://docs.rs/serde_json/1/serde_json/index.html
 //! [`image`]: https://docs.rs/image/0.23.14/image/index.html
 //! [`mint`]: https://docs.rs/mint/0.5.6/mint/index.html
 //! [`nalgebra`]: https://docs.rs/nalgebra/0.28.0/nalgebra/index.html
 //! [`palette`]: https://docs.rs/palette/0.6.0/palette/index.html
+//! [`rayon`]: https://docs.rs/rayon/1.5.1/rayon/index.html
+//! [`tokio`]: https://docs.rs/tokio/1/tokio/index.html
 
+pub mod animation;
+#[cfg(feature = "tokio")]
+pub mod async_io;
+pub mod bake;
 pub mod chunk;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub mod compression;
+pub mod custom;
 pub mod data;
 pub mod default_palette;
+#[cfg(feature = "gltf")]
+pub mod gltf;
+pub mod layers;
+pub mod merge;
+pub mod mesh;
+#[cfg(feature = "rayon")]
+pub mod parallel;
 pub mod reader;
+pub mod scene;
+pub mod split;
+pub mod tiling;
 pub mod types;
 pub mod writer;
 
+#[cfg(feature = "tokio")]
+pub use crate::async_io::{
+    from_async_reader,
+    to_async_writer,
+};
 pub use crate::{
     data::VoxData,
     reader::{
@@ -0,0 +1,487 @@
+//! Merging several files into one.
+//!
+//! [`merge`] combines the models and scene graphs of several files. Each input
+//! is placed in its own group under a new root group, translated by the
+//! input's offset.
+//!
+//! Each input can have its own palette, so the colors are reconciled into a
+//! single palette: Colors that appear in several inputs share an entry. If the
+//! inputs use more than 255 distinct colors, the most used colors are kept and
+//! all other colors are replaced by the perceptually nearest kept color.
+//!
+//! Materials are moved with their palette entries. If several inputs have a
+//! material for the same merged palette entry, the first one is kept.
+//!
+//! Layers with the same name are merged. Unnamed layers are merged by their
+//! IDs. Custom chunks are not merged.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{merge::{merge, MergeInput}, types::{Model, Vector, Voxel}, VoxData};
+//! let mut prop = VoxData::default();
+//! prop.models.push(Model {
+//!     size: Vector::new(1, 1, 1),
+//!     voxels: vec![Voxel::new([0, 0, 0], 1)],
+//! });
+//!
+//! let level = merge(&[
+//!     MergeInput::new(&prop, Vector::new(0, 0, 0)),
+//!     MergeInput::new(&prop, Vector::new(10, 0, 0)),
+//! ])
+//! .unwrap();
+//!
+//! assert_eq!(level.models.len(), 2);
+//! ```
+
+use std::collections::{
+    hash_map::Entry,
+    HashMap,
+};
+
+use thiserror::Error;
+
+use crate::{
+    data::VoxData,
+    scene::{
+        Content,
+        Scene,
+        SceneError,
+    },
+    types::{
+        Color,
+        ColorIndex,
+        Layer,
+        MaterialProperties,
+        Palette,
+        TranslationParseError,
+        Vector,
+    },
+};
+
+/// Error type for [`merge`].
+#[derive(Debug, Error)]
+pub enum MergeError {
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Translation(#[from] TranslationParseError),
+}
+
+/// A file that is merged by [`merge`].
+#[derive(Clone, Debug)]
+pub struct MergeInput<'a> {
+    pub vox: &'a VoxData,
+
+    /// The translation of the group that contains the input's scene graph.
+    pub offset: Vector<i32>,
+
+    /// The name of the group that contains the input's scene graph.
+    pub name: Option<String>,
+}
+
+impl<'a> MergeInput<'a> {
+    /// Creates an input without a name.
+    pub fn new(vox: &'a VoxData, offset: Vector<i32>) -> Self {
+        Self {
+            vox,
+            offset,
+            name: None,
+        }
+    }
+}
+
+/// Merges several files into one. See the [module documentation](self).
+pub fn merge(inputs: &[MergeInput]) -> Result<VoxData, MergeError> {
+    let mut merged = VoxData::default();
+
+    let (palette, color_maps) = reconcile_palettes(inputs.iter().map(|input| input.vox));
+    merged.palette = palette;
+
+    let mut layers = LayerMap::default();
+    let mut scene = Scene::new();
+    let root = scene.root();
+
+    for (input, color_map) in inputs.iter().zip(&color_maps) {
+        let vox = input.vox;
+        let model_offset = merged.models.len() as u32;
+
+        merged.models.extend(vox.models.iter().map(|model| {
+            let mut model = model.clone();
+            for voxel in &mut model.voxels {
+                voxel.color_index = color_map[usize::from(voxel.color_index.0)];
+            }
+            model
+        }));
+
+        for (color_index, material) in vox.materials.iter() {
+            let color_index = color_map[usize::from(color_index.0)];
+            if merged.materials.get(color_index).is_none() {
+                merged.materials.insert(color_index, material.clone());
+            }
+        }
+        for properties in &vox.material_properties {
+            let material_id = match color_map.get(properties.material_id as usize) {
+                Some(color_index) => u32::from(color_index.0),
+                None => continue,
+            };
+            let exists = merged
+                .material_properties
+                .iter()
+                .any(|existing| existing.material_id == material_id);
+            if !exists {
+                merged.material_properties.push(MaterialProperties {
+                    material_id,
+                    attributes: properties.attributes.clone(),
+                });
+            }
+        }
+
+        // Keep all layers, even if they are empty.
+        for layer in &vox.layers {
+            layers.map(vox, &mut merged.layers, layer.node_id);
+        }
+
+        // The input's root object becomes a group under the new root.
+        let source = vox.scene()?;
+        let group = scene.copy_from(root, &source, source.root(), &mut |object| {
+            object.transform.layer_id = object
+                .layer_id()
+                .map(|layer_id| layers.map(vox, &mut merged.layers, layer_id));
+            if let Content::Shape(shape) = &mut object.content {
+                for model in &mut shape.models {
+                    model.model_id += model_offset;
+                }
+            }
+        })?;
+
+        let object = scene.get_mut(group).expect("object was just added");
+        let layer_id = object
+            .layer_id()
+            .unwrap_or_else(|| layers.map(vox, &mut merged.layers, 0));
+        object.set_layer_id(Some(layer_id));
+        if let Some(name) = &input.name {
+            object.set_name(name.clone());
+        }
+        if object.transform.frames.is_empty() {
+            object.transform.frames.push(Default::default());
+        }
+        for frame in &mut object.transform.frames {
+            let translation = frame.translation()?.unwrap_or_default();
+            frame.set_translation(Vector::new(
+                translation.x + input.offset.x,
+                translation.y + input.offset.y,
+                translation.z + input.offset.z,
+            ));
+        }
+    }
+
+    merged.set_scene(&scene)?;
+
+    Ok(merged)
+}
+
+/// Identifies a layer across files.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum LayerKey {
+    Named(String),
+    Unnamed(u32),
+}
+
+/// Maps layers of the inputs to merged layers.
+#[derive(Debug, Default)]
+struct LayerMap {
+    layers: HashMap<LayerKey, u32>,
+}
+
+impl LayerMap {
+    /// Returns the merged layer ID for the layer `layer_id` of `vox`, and adds
+    /// the layer to `merged`, if necessary.
+    fn map(&mut self, vox: &VoxData, merged: &mut Vec<Layer>, layer_id: u32) -> u32 {
+        let layer = vox.layer(layer_id);
+        let key = match layer.and_then(|layer| layer.name()) {
+            Some(name) if !name.is_empty() => LayerKey::Named(name.to_owned()),
+            _ => LayerKey::Unnamed(layer_id),
+        };
+
+        match self.layers.entry(key) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let merged_id = merged.len() as u32;
+                let mut merged_layer = layer.cloned().unwrap_or_else(|| Layer::new(merged_id));
+                merged_layer.node_id = merged_id;
+                merged.push(merged_layer);
+                *entry.insert(merged_id)
+            }
+        }
+    }
+}
+
+/// Reconciles the palettes of several files. Returns the merged palette, and
+/// for each input a map from its color indices to color indices in the merged
+/// palette.
+///
+/// Only colors that are used by voxels count. Unused entries of the merged
+/// palette are filled with the remaining colors of the input palettes.
+fn reconcile_palettes<'a>(
+    inputs: impl Iterator<Item = &'a VoxData>,
+) -> (Palette, Vec<[ColorIndex; 256]>) {
+    let inputs = inputs.collect::<Vec<_>>();
+
+    // Count how often each color is used, and remember when it was first seen.
+    let mut usage: HashMap<Color, (u64, usize)> = HashMap::new();
+    for vox in &inputs {
+        for model in &vox.models {
+            for voxel in &model.voxels {
+                if voxel.color_index.0 == 0 {
+                    continue;
+                }
+                let color = vox.palette.get(voxel.color_index);
+                let first_seen = usage.len();
+                usage.entry(color).or_insert((0, first_seen)).0 += 1;
+            }
+        }
+    }
+
+    let mut colors = usage.into_iter().collect::<Vec<_>>();
+    // Keep the most used colors.
+    colors.sort_by_key(|(_, (count, first_seen))| (std::cmp::Reverse(*count), *first_seen));
+    let dropped = colors.split_off(colors.len().min(255));
+    colors.sort_by_key(|(_, (_, first_seen))| *first_seen);
+
+    let mut palette = Palette::default();
+    let mut indices = HashMap::new();
+    for (i, (color, _)) in colors.iter().enumerate() {
+        palette.colors[i + 1] = *color;
+        indices.insert(*color, ColorIndex(i as u8 + 1));
+    }
+
+    // Map the colors that didn't fit to the nearest kept color.
+    let kept = colors
+        .iter()
+        .map(|(color, _)| (*color, Lab::from(*color)))
+        .collect::<Vec<_>>();
+    for (color, _) in dropped {
+        let lab = Lab::from(color);
+        let (nearest, _) = kept
+            .iter()
+            .min_by(|(_, a), (_, b)| lab.distance(a).total_cmp(&lab.distance(b)))
+            .expect("colors are only dropped if more than 255 are kept");
+        indices.insert(color, indices[nearest]);
+    }
+
+    // Fill the unused entries.
+    let mut next = colors.len() + 1;
+    for vox in &inputs {
+        for (_, color) in vox.palette.iter().skip(1) {
+            if next > 255 {
+                break;
+            }
+            if let Entry::Vacant(entry) = indices.entry(color) {
+                palette.colors[next] = color;
+                entry.insert(ColorIndex(next as u8));
+                next += 1;
+            }
+        }
+    }
+
+    let color_maps = inputs
+        .iter()
+        .map(|vox| {
+            let mut color_map = [ColorIndex(0); 256];
+            for (index, color) in vox.palette.iter().skip(1) {
+                // If the merged palette is full, unused colors might not have an entry.
+                color_map[usize::from(index.0)] =
+                    indices.get(&color).copied().unwrap_or_else(|| {
+                        let lab = Lab::from(color);
+                        (1..=255)
+                            .map(ColorIndex)
+                            .min_by(|a, b| {
+                                lab.distance(&Lab::from(palette.get(*a)))
+                                    .total_cmp(&lab.distance(&Lab::from(palette.get(*b))))
+                            })
+                            .unwrap()
+                    });
+            }
+            color_map
+        })
+        .collect();
+
+    (palette, color_maps)
+}
+
+/// A color in the CIELAB color space, in which euclidean distances are roughly
+/// proportional to perceived differences. The alpha channel is scaled to the
+/// range of the lightness.
+#[derive(Copy, Clone, Debug)]
+struct Lab([f32; 4]);
+
+impl From<Color> for Lab {
+    fn from(color: Color) -> Self {
+        fn linear(channel: u8) -> f32 {
+            let c = f32::from(channel) / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            }
+            else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        }
+
+        fn f(t: f32) -> f32 {
+            if t > 216.0 / 24389.0 {
+                t.cbrt()
+            }
+            else {
+                (24389.0 / 27.0 * t + 16.0) / 116.0
+            }
+        }
+
+        let (r, g, b) = (linear(color.r), linear(color.g), linear(color.b));
+
+        // sRGB to XYZ, relative to the D65 white point.
+        let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.950_47;
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.088_83;
+
+        let (fx, fy, fz) = (f(x), f(y), f(z));
+        Self([
+            116.0 * fy - 16.0,
+            500.0 * (fx - fy),
+            200.0 * (fy - fz),
+            f32::from(color.a) / 255.0 * 100.0,
+        ])
+    }
+}
+
+impl Lab {
+    fn distance(&self, other: &Self) -> f32 {
+        self.0
+            .iter()
+            .zip(&other.0)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        merge,
+        MergeInput,
+    };
+    use crate::{
+        from_slice,
+        types::{
+            Color,
+            ColorIndex,
+            Model,
+            Node,
+            Vector,
+            Voxel,
+        },
+        VoxData,
+    };
+
+    /// Creates a file with one model, that uses `num_colors` colors, starting
+    /// with `first`.
+    fn prop(first: u8, num_colors: u8) -> VoxData {
+        let mut vox = VoxData::default();
+        let mut voxels = vec![];
+        for i in 0..num_colors {
+            let color_index = ColorIndex(i + 1);
+            vox.palette.colors[usize::from(color_index.0)] =
+                Color::new(first.wrapping_add(i), 0, 0, 255);
+            voxels.push(Voxel::new(
+                [(i % 16) as i8, (i / 16) as i8, 0],
+                color_index.0,
+            ));
+        }
+        vox.models.push(Model {
+            size: Vector::new(16, 16, 1),
+            voxels,
+        });
+        vox
+    }
+
+    fn colors(vox: &VoxData) -> Vec<Color> {
+        vox.models
+            .iter()
+            .flat_map(|model| &model.voxels)
+            .map(|voxel| vox.palette.get(voxel.color_index))
+            .collect()
+    }
+
+    #[test]
+    fn it_shares_exact_matches() {
+        let a = prop(0, 10);
+        let b = prop(5, 10);
+        let merged = merge(&[
+            MergeInput::new(&a, Vector::new(0, 0, 0)),
+            MergeInput::new(&b, Vector::new(0, 0, 0)),
+        ])
+        .unwrap();
+
+        assert_eq!(colors(&merged), [colors(&a), colors(&b)].concat());
+        // 15 distinct colors are used.
+        assert_eq!(
+            merged.palette.colors[1..=15].to_vec(),
+            (0..15)
+                .map(|r| Color::new(r, 0, 0, 255))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn it_merges_nearest_colors_if_the_palette_is_full() {
+        let a = prop(0, 200);
+        let b = prop(100, 155);
+        let merged = merge(&[
+            MergeInput::new(&a, Vector::new(0, 0, 0)),
+            MergeInput::new(&b, Vector::new(0, 0, 0)),
+        ])
+        .unwrap();
+
+        // 255 distinct colors are used, which just fit.
+        assert_eq!(colors(&merged), [colors(&a), colors(&b)].concat());
+
+        let c = prop(0, 255);
+        let mut d = prop(0, 1);
+        d.palette.colors[1] = Color::new(255, 1, 0, 255);
+        let merged = merge(&[
+            MergeInput::new(&c, Vector::new(0, 0, 0)),
+            MergeInput::new(&d, Vector::new(0, 0, 0)),
+        ])
+        .unwrap();
+        let merged_colors = colors(&merged);
+        assert_eq!(merged_colors[..255], colors(&c)[..]);
+        assert_eq!(merged_colors[255], Color::new(254, 0, 0, 255));
+    }
+
+    #[test]
+    fn it_merges_scene_graphs() {
+        let vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap();
+        let mut first = MergeInput::new(&vox, Vector::new(0, 0, 0));
+        first.name = Some("first".to_owned());
+        let mut second = MergeInput::new(&vox, Vector::new(0, 20, 0));
+        second.name = Some("second".to_owned());
+        let merged = merge(&[first, second]).unwrap();
+
+        assert_eq!(merged.models.len(), 4);
+        assert_eq!(merged.layers.len(), vox.layers.len());
+        assert!(matches!(merged.node(0), Some(Node::Transform(_))));
+
+        let scene = merged.scene().unwrap();
+        assert_eq!(scene.instances(2).len(), 1);
+        let second = scene.find_by_name("second").unwrap();
+        let shape = scene.get(second).unwrap().children()[0];
+        let pose = scene.world_pose(shape, 0).unwrap();
+        let local = scene.get(shape).unwrap().transform.pose_at(0).unwrap();
+        assert_eq!(pose.translation.y, local.translation.y + 20);
+    }
+}
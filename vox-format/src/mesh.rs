@@ -0,0 +1,485 @@
+//! Converting models to triangle meshes.
+//!
+//! [`mesh`] creates an indexed triangle mesh of the visible faces of a model.
+//! Adjacent faces with the same color are merged into larger rectangles
+//! ("greedy meshing"), which reduces the number of triangles a lot for most
+//! models.
+//!
+//! The mesh is in the model's coordinate system: A voxel at `(x, y, z)` covers
+//! the unit cube from `(x, y, z)` to `(x + 1, y + 1, z + 1)`. Like in
+//! MagicaVoxel, the z axis points up.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{mesh::{mesh, MeshOptions}, types::{Model, Palette, Vector, Voxel}};
+//! let model = Model {
+//!     size: Vector::new(2, 1, 1),
+//!     voxels: vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 0, 0], 1)],
+//! };
+//!
+//! let mesh = mesh(&model, &Palette::default(), &MeshOptions::default());
+//!
+//! // The voxels have the same color, so each side is a single rectangle.
+//! assert_eq!(mesh.indices.len(), 6 * 6);
+//! ```
+
+use crate::{
+    tiling::MAX_MODEL_SIZE,
+    types::{
+        ColorIndex,
+        Model,
+        Palette,
+    },
+};
+
+/// Options for [`mesh`].
+#[derive(Clone, Debug, Default)]
+pub struct MeshOptions {
+    /// Compute ambient occlusion for each vertex. Faces are only merged if
+    /// the ambient occlusion is the same at all their corners.
+    pub ambient_occlusion: bool,
+}
+
+/// An indexed triangle mesh. All vertex attributes have one entry per vertex.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+
+    /// Normals, which point out of the model.
+    pub normals: Vec<[f32; 3]>,
+
+    /// The color of each vertex as sRGB with alpha.
+    pub colors: Vec<[u8; 4]>,
+
+    /// The palette index of each vertex.
+    pub color_indices: Vec<ColorIndex>,
+
+    /// Texture coordinates into a palette texture, that is 256 pixels wide
+    /// and one pixel high, like the image created by `Palette::as_image`.
+    pub uvs: Vec<[f32; 2]>,
+
+    /// Ambient occlusion of each vertex from `0.0` (fully occluded) to `1.0`
+    /// (not occluded). This is empty, unless
+    /// [`MeshOptions::ambient_occlusion`] is set.
+    pub ambient_occlusion: Vec<f32>,
+
+    /// Vertex indices. Each three indices form a triangle, whose vertices are
+    /// in counter-clockwise order, when looking at its front.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// Returns the number of vertices.
+    pub fn num_vertices(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns the number of triangles.
+    pub fn num_triangles(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// The properties of a face that must be equal for faces to be merged.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Face {
+    color_index: u8,
+
+    /// Ambient occlusion at the corners, from 0 (fully occluded) to 3.
+    ambient_occlusion: [u8; 4],
+}
+
+impl Face {
+    fn can_merge(&self, other: &Option<Face>) -> bool {
+        let uniform = self
+            .ambient_occlusion
+            .iter()
+            .all(|ao| *ao == self.ambient_occlusion[0]);
+        uniform && other.as_ref() == Some(self)
+    }
+}
+
+/// The voxels of a model as a dense grid.
+struct Grid {
+    size: [usize; 3],
+    colors: Vec<u8>,
+}
+
+impl Grid {
+    fn new(model: &Model) -> Self {
+        // Voxel coordinates can't be larger than 255, so we don't allocate more than
+        // that, even if the model claims to be larger.
+        let clamp = |size: u32| size.min(MAX_MODEL_SIZE) as usize;
+        let size = [
+            clamp(model.size.x),
+            clamp(model.size.y),
+            clamp(model.size.z),
+        ];
+        let mut grid = Self {
+            size,
+            colors: vec![0; size[0] * size[1] * size[2]],
+        };
+
+        for voxel in &model.voxels {
+            let point = [
+                usize::from(voxel.point.x as u8),
+                usize::from(voxel.point.y as u8),
+                usize::from(voxel.point.z as u8),
+            ];
+            if let Some(index) = grid.index(point) {
+                grid.colors[index] = voxel.color_index.0;
+            }
+        }
+
+        grid
+    }
+
+    fn index(&self, point: [usize; 3]) -> Option<usize> {
+        (point[0] < self.size[0] && point[1] < self.size[1] && point[2] < self.size[2])
+            .then(|| point[0] + self.size[0] * (point[1] + self.size[1] * point[2]))
+    }
+
+    /// Returns the color index at `point`, or 0 if the point is empty or
+    /// outside of the grid.
+    fn get(&self, point: [i64; 3]) -> u8 {
+        if point.iter().any(|c| *c < 0) {
+            return 0;
+        }
+        self.index([point[0] as usize, point[1] as usize, point[2] as usize])
+            .map_or(0, |index| self.colors[index])
+    }
+
+    fn is_solid(&self, point: [i64; 3]) -> bool {
+        self.get(point) != 0
+    }
+}
+
+/// Converts a model into a mesh. See the [module documentation](self).
+///
+/// Voxels with color index 0 are treated as empty.
+pub fn mesh(model: &Model, palette: &Palette, options: &MeshOptions) -> Mesh {
+    let grid = Grid::new(model);
+    let mut mesh = Mesh::default();
+
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+        let (size_u, size_v) = (grid.size[u], grid.size[v]);
+
+        for &positive in &[false, true] {
+            let step = if positive { 1 } else { -1 };
+            let mut mask = vec![None; size_u * size_v];
+
+            for slice in 0..grid.size[d] {
+                // Find the visible faces in this slice.
+                for j in 0..size_v {
+                    for i in 0..size_u {
+                        let mut point = [0; 3];
+                        point[d] = slice as i64;
+                        point[u] = i as i64;
+                        point[v] = j as i64;
+
+                        let color_index = grid.get(point);
+                        let mut neighbor = point;
+                        neighbor[d] += step;
+
+                        mask[i + j * size_u] =
+                            (color_index != 0 && !grid.is_solid(neighbor)).then(|| {
+                                Face {
+                                    color_index,
+                                    ambient_occlusion: if options.ambient_occlusion {
+                                        ambient_occlusion(&grid, neighbor, u, v)
+                                    }
+                                    else {
+                                        [3; 4]
+                                    },
+                                }
+                            });
+                    }
+                }
+
+                // Merge faces into rectangles.
+                for j in 0..size_v {
+                    let mut i = 0;
+                    while i < size_u {
+                        let face = match mask[i + j * size_u] {
+                            Some(face) => face,
+                            None => {
+                                i += 1;
+                                continue;
+                            }
+                        };
+
+                        let mut width = 1;
+                        while i + width < size_u && face.can_merge(&mask[i + width + j * size_u]) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+                        while j + height < size_v
+                            && (i..i + width)
+                                .all(|k| face.can_merge(&mask[k + (j + height) * size_u]))
+                        {
+                            height += 1;
+                        }
+
+                        for l in j..j + height {
+                            for k in i..i + width {
+                                mask[k + l * size_u] = None;
+                            }
+                        }
+
+                        let mut corner = [0.0; 3];
+                        corner[d] = (slice + usize::from(positive)) as f32;
+                        corner[u] = i as f32;
+                        corner[v] = j as f32;
+                        let quad = Quad {
+                            d,
+                            u,
+                            v,
+                            positive,
+                            corner,
+                            width: width as f32,
+                            height: height as f32,
+                            face,
+                        };
+                        quad.push_to(&mut mesh, palette, options);
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Computes the ambient occlusion at the corners of a face, whose outside is
+/// the empty cell `point`. The corners are in the order `(-u, -v)`,
+/// `(+u, -v)`, `(+u, +v)` and `(-u, +v)`.
+fn ambient_occlusion(grid: &Grid, point: [i64; 3], u: usize, v: usize) -> [u8; 4] {
+    let offset = |du: i64, dv: i64| {
+        let mut p = point;
+        p[u] += du;
+        p[v] += dv;
+        grid.is_solid(p)
+    };
+
+    let corner = |du: i64, dv: i64| {
+        let side_u = offset(du, 0);
+        let side_v = offset(0, dv);
+        if side_u && side_v {
+            0
+        }
+        else {
+            3 - side_u as u8 - side_v as u8 - offset(du, dv) as u8
+        }
+    };
+
+    [corner(-1, -1), corner(1, -1), corner(1, 1), corner(-1, 1)]
+}
+
+/// A rectangle of merged faces.
+struct Quad {
+    /// The axis of the normal.
+    d: usize,
+    u: usize,
+    v: usize,
+
+    /// Whether the normal points in positive direction.
+    positive: bool,
+
+    /// The corner with the smallest coordinates.
+    corner: [f32; 3],
+    width: f32,
+    height: f32,
+    face: Face,
+}
+
+impl Quad {
+    fn push_to(&self, mesh: &mut Mesh, palette: &Palette, options: &MeshOptions) {
+        let base = mesh.positions.len() as u32;
+
+        let mut normal = [0.0; 3];
+        normal[self.d] = if self.positive { 1.0 } else { -1.0 };
+
+        let color_index = ColorIndex(self.face.color_index);
+        let color = palette.get(color_index);
+        let uv = [(f32::from(color_index.0) + 0.5) / 256.0, 0.5];
+
+        let corners = [
+            (0.0, 0.0),
+            (self.width, 0.0),
+            (self.width, self.height),
+            (0.0, self.height),
+        ];
+        for (&(du, dv), &ao) in corners.iter().zip(&self.face.ambient_occlusion) {
+            let mut position = self.corner;
+            position[self.u] += du;
+            position[self.v] += dv;
+
+            mesh.positions.push(position);
+            mesh.normals.push(normal);
+            mesh.colors.push(color.into());
+            mesh.color_indices.push(color_index);
+            mesh.uvs.push(uv);
+            if options.ambient_occlusion {
+                mesh.ambient_occlusion.push(f32::from(ao) / 3.0);
+            }
+        }
+
+        // Split the quad along the diagonal between the less occluded corners.
+        // Otherwise the occlusion of a single corner bleeds into the whole
+        // triangle.
+        let ao = self.face.ambient_occlusion;
+        let triangles = if ao[0] + ao[2] < ao[1] + ao[3] {
+            [[1, 2, 3], [1, 3, 0]]
+        }
+        else {
+            [[0, 1, 2], [0, 2, 3]]
+        };
+
+        for triangle in &triangles {
+            // The corners are in counter-clockwise order around the positive
+            // direction of the normal axis.
+            let triangle = if self.positive {
+                *triangle
+            }
+            else {
+                [triangle[0], triangle[2], triangle[1]]
+            };
+            mesh.indices
+                .extend(triangle.iter().map(|index| base + index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        mesh,
+        Mesh,
+        MeshOptions,
+    };
+    use crate::{
+        from_slice,
+        types::{
+            Model,
+            Palette,
+            Vector,
+            Voxel,
+        },
+    };
+
+    /// Checks that the triangles face in the direction of their normals.
+    fn check_winding(mesh: &Mesh) {
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let ab = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let ac = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            let cross = [
+                ab[1] * ac[2] - ab[2] * ac[1],
+                ab[2] * ac[0] - ab[0] * ac[2],
+                ab[0] * ac[1] - ab[1] * ac[0],
+            ];
+            let normal = mesh.normals[triangle[0] as usize];
+            let dot: f32 = (0..3).map(|i| cross[i] * normal[i]).sum();
+            assert!(dot > 0.0, "{:?} faces away from {:?}", triangle, normal);
+        }
+    }
+
+    #[test]
+    fn it_merges_faces() {
+        let options = MeshOptions::default();
+
+        let cube = Model {
+            size: Vector::new(1, 1, 1),
+            voxels: vec![Voxel::new([0, 0, 0], 1)],
+        };
+        let cube = mesh(&cube, &Palette::default(), &options);
+        assert_eq!(cube.num_vertices(), 24);
+        assert_eq!(cube.num_triangles(), 12);
+        assert!(cube.ambient_occlusion.is_empty());
+        check_winding(&cube);
+
+        let slab = Model {
+            size: Vector::new(3, 3, 1),
+            voxels: (0..9).map(|i| Voxel::new([i % 3, i / 3, 0], 1)).collect(),
+        };
+        let slab = mesh(&slab, &Palette::default(), &options);
+        assert_eq!(slab.num_triangles(), 12);
+        check_winding(&slab);
+
+        let two_colors = Model {
+            size: Vector::new(2, 1, 1),
+            voxels: vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 0, 0], 2)],
+        };
+        let two_colors = mesh(&two_colors, &Palette::default(), &options);
+        assert_eq!(two_colors.num_triangles(), 20);
+        assert!(two_colors
+            .colors
+            .contains(&Palette::default().colors[2].into()));
+    }
+
+    #[test]
+    fn it_computes_ambient_occlusion() {
+        let options = MeshOptions {
+            ambient_occlusion: true,
+        };
+
+        // Two voxels in an L-shape.
+        let model = Model {
+            size: Vector::new(2, 1, 2),
+            voxels: vec![
+                Voxel::new([0, 0, 0], 1),
+                Voxel::new([1, 0, 0], 1),
+                Voxel::new([0, 0, 1], 1),
+            ],
+        };
+        let mesh = mesh(&model, &Palette::default(), &options);
+        check_winding(&mesh);
+        assert_eq!(mesh.ambient_occlusion.len(), mesh.num_vertices());
+
+        // The top of the voxel at (1, 0, 0) is occluded next to the voxel at (0, 0, 1).
+        let occluded = mesh
+            .positions
+            .iter()
+            .zip(&mesh.ambient_occlusion)
+            .filter(|(position, _)| position[2] == 1.0 && position[0] == 1.0)
+            .any(|(_, ao)| *ao < 1.0);
+        assert!(occluded);
+    }
+
+    #[test]
+    fn it_meshes_files() {
+        let vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap();
+        for model in &vox.models {
+            let mesh = mesh(model, &vox.palette, &MeshOptions::default());
+            assert!(!mesh.is_empty());
+            check_winding(&mesh);
+        }
+    }
+
+    #[test]
+    fn it_ignores_oversized_models() {
+        // Voxels can't be outside of 256³, so the size must not be used to allocate
+        // the grid.
+        let model = Model {
+            size: Vector::new(u32::MAX, u32::MAX, 1),
+            voxels: vec![Voxel::new([-1, 0, 0], 1)],
+        };
+        let mesh = mesh(&model, &Palette::default(), &MeshOptions::default());
+        assert_eq!(mesh.num_triangles(), 12);
+        assert!(mesh.positions.iter().all(|position| position[0] >= 255.0));
+    }
+}
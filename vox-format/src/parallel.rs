@@ -0,0 +1,131 @@
+//! Decodes models on multiple threads using [`rayon`].
+//!
+//! Models in a VOX file are independent of each other, so their `XYZI` chunks
+//! can be decoded in parallel. This needs random access to the whole file, so
+//! it works on byte slices. A memory-mapped file (e.g. from the `memmap2`
+//! crate) dereferences to a `&[u8]` and can be used as well.
+//!
+//! [`rayon`]: https://docs.rs/rayon
+
+use std::io::Cursor;
+
+use rayon::prelude::*;
+
+use crate::{
+    data::{
+        VoxModelBuffer,
+        VoxModels,
+    },
+    reader::{
+        read_main_children,
+        read_size,
+        read_voxels,
+        Error,
+        ReaderOptions,
+    },
+};
+
+/// Reads a VOX file from a slice into [`crate::data::VoxModels`], decoding the
+/// models in parallel.
+///
+/// The models are in the same order as they would be when read with
+/// [`crate::reader::read_vox_into`].
+///
+/// ```
+/// # use vox_format::{parallel::from_slice_parallel, types::Model};
+/// # let data = std::fs::read("../test_files/test_multiple_models.vox").unwrap();
+/// let vox = from_slice_parallel::<Model>(&data);
+/// ```
+pub fn from_slice_parallel<V: VoxModelBuffer + Send>(slice: &[u8]) -> Result<VoxModels<V>, Error> {
+    let options = ReaderOptions::default();
+    let mut problems = vec![];
+
+    let mut reader = Cursor::new(slice);
+    let (version, chunks) = read_main_children(&mut reader, &options, &mut problems)?;
+
+    let mut vox = VoxModels {
+        version,
+        ..Default::default()
+    };
+
+    if let Some(palette) = chunks.read_palette(&mut reader, &options, &mut problems)? {
+        vox.palette = palette;
+    }
+
+    chunks.read_materials(&mut reader, &mut vox, &options, &mut problems)?;
+
+    log::trace!("num_models = {}", chunks.models.len());
+
+    let palette = &vox.palette;
+    vox.models = chunks
+        .models
+        .par_iter()
+        .enumerate()
+        .map(|(model_index, (size_chunk, xyzi_chunk))| {
+            // With the default options, nothing is repaired, so there are no problems.
+            let mut problems = vec![];
+            let mut reader = Cursor::new(slice);
+
+            let model_size = read_size(&mut reader, size_chunk, &options, &mut problems)
+                .map_err(|e| size_chunk.context(e))?;
+            log::trace!("model_size = {:?}", model_size);
+            let mut model = V::new(model_size);
+
+            read_voxels(
+                &mut reader,
+                xyzi_chunk,
+                model_index,
+                model_size,
+                &options,
+                &mut problems,
+                |voxel| model.set_voxel(voxel, palette),
+            )
+            .map_err(|e| xyzi_chunk.context(e))?;
+
+            Ok(model)
+        })
+        .collect::<Result<Vec<V>, Error>>()?;
+
+    chunks.read_nodes(&mut reader, &mut vox, &options, &mut problems)?;
+    chunks.read_custom_chunks(&mut reader, &mut vox, &options, &mut problems)?;
+
+    Ok(vox)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::from_slice_parallel;
+    use crate::{
+        data::VoxData,
+        from_slice,
+        to_vec,
+        types::{
+            Model,
+            Vector,
+            Voxel,
+        },
+    };
+
+    #[test]
+    fn it_keeps_the_model_order() {
+        let mut vox = VoxData::default();
+        for i in 0..32u8 {
+            vox.models.push(Model {
+                size: Vector::new(u32::from(i) + 1, 1, 1),
+                voxels: (0..=i)
+                    .map(|x| Voxel::new([x as i8, 0, 0], i + 1))
+                    .collect(),
+            });
+        }
+        let data = to_vec(&vox).unwrap();
+
+        let sequential = from_slice(&data).unwrap();
+        let parallel = from_slice_parallel::<Model>(&data).unwrap();
+
+        assert_eq!(parallel.models.len(), sequential.models.len());
+        for (a, b) in parallel.models.iter().zip(&sequential.models) {
+            assert_eq!(a.size, b.size);
+            assert_eq!(a.voxels, b.voxels);
+        }
+    }
+}
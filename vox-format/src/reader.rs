@@ -1,6 +1,11 @@
 //! Provides functions to read VOX files.
 
 use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    fmt,
     fs::File,
     io::{
         Cursor,
@@ -19,17 +24,27 @@ use thiserror::Error;
 
 use crate::{
     chunk::{
-        read_main_chunk,
+        read_main_chunk_any_version,
         Chunk,
         ChunkId,
+        ChunkPath,
     },
+    custom::ChunkCodecs,
     data::{
         VoxBuffer,
         VoxData,
     },
     types::{
+        Group,
+        Layer,
+        Material,
+        MaterialProperties,
+        Node,
         Palette,
+        Point,
+        Shape,
         Size,
+        Transform,
         Version,
         Voxel,
     },
@@ -64,7 +79,54 @@ pub enum Error {
 
     /// Unknown material type.
     #[error("Invalid material type: {material_type}")]
-    InvalidMaterial { material_type: u8 },
+    InvalidMaterial { material_type: u32 },
+
+    /// A `MATT` chunk has a material ID outside of `1..=255`.
+    #[error("Invalid material ID: {material_id}")]
+    InvalidMaterialId { material_id: u32 },
+
+    /// A shape node refers to a model that isn't in the file.
+    #[error(
+        "Shape node {node_id} refers to model {model_id}, but there are only {num_models} models."
+    )]
+    InvalidModelId {
+        node_id: u32,
+        model_id: u32,
+        num_models: usize,
+    },
+
+    /// A voxel lies outside of the size given by the model's `SIZE` chunk.
+    #[error("Voxel at {:?} in model {model_index} is outside of size {size:?}.", .voxel.point)]
+    VoxelOutOfBounds {
+        model_index: usize,
+        voxel: Voxel,
+        size: Size,
+    },
+
+    /// A model contains multiple voxels at the same position.
+    #[error("Model {model_index} contains multiple voxels at {point:?}.")]
+    DuplicateVoxel { model_index: usize, point: Point },
+
+    /// A voxel uses color index 0, which MagicaVoxel reserves for empty space.
+    #[error("Voxel at {point:?} in model {model_index} has color index 0.")]
+    ZeroColorIndex { model_index: usize, point: Point },
+
+    /// A chunk's length is invalid. Either it extends beyond its parent chunk,
+    /// or beyond the maximum offset.
+    #[error("Chunk {:?} at {} has an invalid length.", .chunk.id(), .chunk.offset())]
+    InvalidChunkLength { chunk: Chunk },
+
+    /// A limit set in [`Limits`] was exceeded.
+    #[error("Limit for {kind} exceeded: {value} > {max}")]
+    LimitExceeded {
+        kind: LimitKind,
+        value: u64,
+        max: u64,
+    },
+
+    /// A chunk's content is longer than the data that was decoded from it.
+    #[error("Chunk {:?} at {} has {num_bytes} trailing bytes.", .chunk.id(), .chunk.offset())]
+    TrailingBytes { chunk: Chunk, num_bytes: u64 },
 
     /// An error of the underlying IO
     #[error("IO error")]
@@ -73,6 +135,471 @@ pub enum Error {
     /// An error while decoding strings to UTF-8.
     #[error("Failed to decode UTF-8 string")]
     Utf8(#[from] std::string::FromUtf8Error),
+
+    /// An error with information about where it occured. Use [`Error::root`]
+    /// to get the underlying error.
+    #[error("Error {context}")]
+    Context {
+        context: ErrorContext,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the message of the underlying error followed by the context,
+    /// for logging.
+    pub(crate) fn message(&self) -> String {
+        match self.context() {
+            Some(context) => format!("{} ({})", self.root(), context),
+            None => self.to_string(),
+        }
+    }
+
+    /// Returns the underlying error without any [`Error::Context`].
+    pub fn root(&self) -> &Error {
+        let mut error = self;
+        while let Error::Context { source, .. } = error {
+            error = source;
+        }
+        error
+    }
+
+    /// Returns the context of this error, if it has any.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn into_context(self) -> (ErrorContext, Box<Error>) {
+        match self {
+            Error::Context { context, source } => (context, source),
+            error => (ErrorContext::default(), Box::new(error)),
+        }
+    }
+
+    /// Adds the name of the field that was being decoded. If the error already
+    /// has a field, it's kept, since it's more specific.
+    pub(crate) fn with_field(self, field: &'static str) -> Self {
+        let (mut context, source) = self.into_context();
+        context.field.get_or_insert(field);
+        Error::Context { context, source }
+    }
+
+    /// Adds the path and offset of the chunk that was being decoded, if the
+    /// error doesn't have them already.
+    pub(crate) fn with_chunk(self, path: &ChunkPath, offset: u64) -> Self {
+        let (mut context, source) = self.into_context();
+        if context.chunk_path.is_none() {
+            context.chunk_path = Some(path.clone());
+            context.offset = Some(offset);
+        }
+        Error::Context { context, source }
+    }
+}
+
+/// Information about where an error occured. See [`Error::Context`].
+#[derive(Clone, Debug, Default)]
+pub struct ErrorContext {
+    /// Path of the chunk in which the error occured, e.g. `MAIN/nTRN#12`.
+    pub chunk_path: Option<ChunkPath>,
+
+    /// Offset of the chunk in the file.
+    pub offset: Option<u64>,
+
+    /// The field that was being decoded, e.g. `attribute value string`.
+    pub field: Option<&'static str>,
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        if let Some(chunk_path) = &self.chunk_path {
+            write!(f, "in chunk {}", chunk_path)?;
+            separator = ", ";
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "{}at offset {}", separator, offset)?;
+            separator = ", ";
+        }
+        if let Some(field) = self.field {
+            write!(f, "{}while reading {}", separator, field)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds the name of the field that was being decoded to errors.
+pub(crate) trait ResultExt<T> {
+    fn field(self, field: &'static str) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for Result<T, E> {
+    fn field(self, field: &'static str) -> Result<T, Error> {
+        self.map_err(|e| e.into().with_field(field))
+    }
+}
+
+/// A problem that was found while reading a VOX file, but was repaired or
+/// skipped. These are returned in [`ValidationMode::Lenient`] and in recovery
+/// mode (see [`ReaderOptions::recover`]).
+#[derive(Debug)]
+pub struct Problem {
+    /// The ID of the chunk in which the problem was found. This is `None`, if
+    /// the chunk header couldn't be read.
+    pub chunk_id: Option<ChunkId>,
+
+    /// The offset of the chunk, or the offset at which a chunk was expected.
+    pub offset: u64,
+
+    /// What went wrong. This includes the chunk path, see [`Error::context`].
+    pub error: Error,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+/// A problem is displayed like its error, so the error's source comes next,
+/// instead of the error itself.
+impl std::error::Error for Problem {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(&self.error)
+    }
+}
+
+impl Problem {
+    fn new(chunk: &LocatedChunk, error: Error) -> Self {
+        Self {
+            chunk_id: Some(chunk.chunk.id()),
+            offset: chunk.chunk.offset(),
+            error: chunk.context(error),
+        }
+    }
+}
+
+/// How problems that were found by validation are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Problems are returned as an error.
+    Strict,
+
+    /// Problems are repaired or skipped, and logged as warnings.
+    Lenient,
+}
+
+/// The kind of limit that was exceeded. See [`Limits`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LimitKind {
+    ChunkSize,
+    StringLength,
+    Voxels,
+    Models,
+    Nesting,
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            LimitKind::ChunkSize => "chunk size",
+            LimitKind::StringLength => "string length",
+            LimitKind::Voxels => "number of voxels",
+            LimitKind::Models => "number of models",
+            LimitKind::Nesting => "chunk nesting",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Resource limits for reading VOX files.
+///
+/// The default limits only restrict how deep chunks can be nested, since
+/// reading nested chunks recursively could overflow the stack. If you read
+/// files from an untrusted source, use [`Limits::untrusted`], or set your own
+/// limits.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximum length of a chunk, including its header, contents and children.
+    /// Since all data is contained in the `MAIN` chunk, this also limits the
+    /// file size.
+    pub max_chunk_size: u32,
+
+    /// Maximum length of strings, e.g. in node attributes.
+    pub max_string_len: u32,
+
+    /// Maximum number of voxels per model.
+    pub max_voxels: u32,
+
+    /// Maximum number of models.
+    pub max_models: usize,
+
+    /// Maximum depth of nested chunks. The `MAIN` chunk has depth 1. Chunk
+    /// trees are read recursively, so a very large limit lets files overflow
+    /// the stack.
+    pub max_nesting: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_chunk_size: u32::MAX,
+            max_string_len: u32::MAX,
+            max_voxels: u32::MAX,
+            max_models: usize::MAX,
+            max_nesting: 64,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits that are suitable for files from untrusted sources, but still
+    /// allow most files created with MagicaVoxel.
+    pub fn untrusted() -> Self {
+        Self {
+            max_chunk_size: 64 * 1024 * 1024,
+            max_string_len: 64 * 1024,
+            max_voxels: 256 * 256 * 256,
+            max_models: 4096,
+            max_nesting: 16,
+        }
+    }
+
+    /// Returns an error if `value` exceeds `max`.
+    pub(crate) fn check(kind: LimitKind, value: u64, max: u64) -> Result<(), Error> {
+        if value > max {
+            Err(Error::LimitExceeded { kind, value, max })
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    /// Checks the length of `chunk` and the depth at which it's nested.
+    pub(crate) fn check_chunk(&self, chunk: &Chunk, depth: usize) -> Result<(), Error> {
+        Self::check(
+            LimitKind::ChunkSize,
+            chunk.len(),
+            self.max_chunk_size.into(),
+        )?;
+        Self::check(LimitKind::Nesting, depth as u64, self.max_nesting as u64)
+    }
+
+    /// Checks `chunk`, which is nested at `depth`, and all of its descendants.
+    /// This walks the chunks with an explicit stack, so it doesn't recurse.
+    pub(crate) fn check_chunk_tree<R: Read + Seek>(
+        &self,
+        mut reader: R,
+        chunk: &Chunk,
+        depth: usize,
+    ) -> Result<(), Error> {
+        let mut stack = vec![(chunk.clone(), depth)];
+        while let Some((chunk, depth)) = stack.pop() {
+            self.check_chunk(&chunk, depth)?;
+            for child in chunk.children(&mut reader) {
+                stack.push((child?, depth + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options for reading VOX files.
+///
+/// The number of `SIZE` and `XYZI` chunks and the number of `RGBA` chunks are
+/// always validated. The other checks must be enabled. [`Self::strict`] and
+/// [`Self::lenient`] enable all of them. The default options enable no extra
+/// checks and use [`ValidationMode::Strict`], which is what
+/// [`read_vox_into`] does.
+///
+/// In lenient mode, problems are repaired as follows:
+///
+///  - Excess `SIZE` or `XYZI` chunks are ignored.
+///  - Only the first `RGBA` chunk is used.
+///  - Voxels outside of the model, voxels at a position that is already
+///    occupied, and voxels with color index 0 are skipped.
+///  - Trailing bytes in chunks are ignored.
+///
+/// In recovery mode, which can be used in addition to either validation
+/// mode, errors inside of a chunk don't stop reading. See
+/// [`Self::recovery`].
+#[derive(Clone, Debug)]
+pub struct ReaderOptions {
+    /// Whether to fail or repair on problems.
+    pub mode: ValidationMode,
+
+    /// Check that voxels are inside the model's size.
+    pub check_bounds: bool,
+
+    /// Check that no two voxels in a model have the same position.
+    pub check_duplicates: bool,
+
+    /// Check that no voxel has color index 0.
+    pub check_color_indices: bool,
+
+    /// Check that shape nodes only refer to models that are in the file. In
+    /// lenient mode, invalid references are removed from the shape.
+    pub check_model_ids: bool,
+
+    /// Check that `MATL` chunks can be decoded. Otherwise chunks that can't
+    /// be decoded are skipped with a warning.
+    pub check_material_properties: bool,
+
+    /// Check that `SIZE`, `XYZI` and `RGBA` chunks don't contain more data than
+    /// is decoded.
+    pub check_trailing_bytes: bool,
+
+    /// Resource limits. These are always enforced, regardless of
+    /// [`Self::mode`].
+    pub limits: Limits,
+
+    /// Recover from errors in chunks by skipping them. See [`Self::recovery`].
+    pub recover: bool,
+
+    /// Codecs for custom chunks. See [`crate::custom`].
+    pub codecs: ChunkCodecs,
+
+    /// Try to read files with an unknown version anyway. Only the chunks that
+    /// this crate understands are read, and the unknown version is reported
+    /// as a [`Problem`].
+    pub allow_unknown_versions: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            mode: ValidationMode::Strict,
+            check_bounds: false,
+            check_duplicates: false,
+            check_color_indices: false,
+            check_model_ids: false,
+            check_material_properties: false,
+            check_trailing_bytes: false,
+            limits: Limits::default(),
+            recover: false,
+            codecs: ChunkCodecs::default(),
+            allow_unknown_versions: false,
+        }
+    }
+}
+
+impl ReaderOptions {
+    /// Enables all checks and fails on the first problem.
+    pub fn strict() -> Self {
+        Self {
+            mode: ValidationMode::Strict,
+            ..Self::all_checks()
+        }
+    }
+
+    /// Enables all checks and repairs any problems found.
+    pub fn lenient() -> Self {
+        Self {
+            mode: ValidationMode::Lenient,
+            ..Self::all_checks()
+        }
+    }
+
+    /// Reads as much as possible from damaged files, e.g. ones that were
+    /// truncated by an interrupted download.
+    ///
+    /// If reading a chunk fails, the error is recorded as [`Problem`], and
+    /// reading continues with the next chunk. Voxels of a model that were
+    /// decoded before the damage are kept. If a chunk header can't be read,
+    /// the remaining chunks are skipped, since we can't know where they
+    /// start. Only errors in the file header and `MAIN` chunk header are
+    /// still returned as errors.
+    ///
+    /// This also enables all checks in [`ValidationMode::Lenient`], and tries
+    /// to read files with unknown versions.
+    pub fn recovery() -> Self {
+        Self {
+            mode: ValidationMode::Lenient,
+            recover: true,
+            allow_unknown_versions: true,
+            ..Self::all_checks()
+        }
+    }
+
+    fn all_checks() -> Self {
+        Self {
+            mode: ValidationMode::Strict,
+            check_bounds: true,
+            check_duplicates: true,
+            check_color_indices: true,
+            check_model_ids: true,
+            check_material_properties: true,
+            check_trailing_bytes: true,
+            limits: Limits::default(),
+            recover: false,
+            codecs: ChunkCodecs::default(),
+            allow_unknown_versions: false,
+        }
+    }
+
+    /// Returns `error` in strict mode. In lenient mode the error is logged and
+    /// added to `problems` instead.
+    fn report(
+        &self,
+        chunk: &LocatedChunk,
+        error: Error,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        match self.mode {
+            ValidationMode::Strict => Err(chunk.context(error)),
+            ValidationMode::Lenient => {
+                let problem = Problem::new(chunk, error);
+                log::warn!("{}", problem.error.message());
+                problems.push(problem);
+                Ok(())
+            }
+        }
+    }
+
+    /// In recovery mode, an error in `result` is logged and added to
+    /// `problems`, and `None` is returned. Otherwise the error is returned.
+    fn try_recover<T>(
+        &self,
+        chunk: &LocatedChunk,
+        result: Result<T, Error>,
+        problems: &mut Vec<Problem>,
+    ) -> Result<Option<T>, Error> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) if self.recover => {
+                let problem = Problem::new(chunk, error);
+                log::warn!("Recovering from: {}", problem.error.message());
+                problems.push(problem);
+                Ok(None)
+            }
+            Err(error) => Err(chunk.context(error)),
+        }
+    }
+
+    /// Reports trailing bytes in `chunk`, if `num_bytes_read` is less than the
+    /// expected length.
+    fn check_trailing_bytes(
+        &self,
+        chunk: &LocatedChunk,
+        num_bytes_read: u64,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        let content_len = u64::from(chunk.chunk.content_len());
+        if self.check_trailing_bytes && content_len > num_bytes_read {
+            self.report(
+                chunk,
+                Error::TrailingBytes {
+                    chunk: chunk.chunk.clone(),
+                    num_bytes: content_len - num_bytes_read,
+                },
+                problems,
+            )?;
+        }
+        Ok(())
+    }
 }
 
 /// Reads a VOX file from the reader into the [`VoxBuffer`]. This function is
@@ -102,30 +629,375 @@ pub enum Error {
 /// read_vox_into(vox_file, &mut counter).unwrap();
 /// println!("{}", counter.num_models);
 /// ```
-pub fn read_vox_into<R: Read + Seek, B: VoxBuffer>(
+pub fn read_vox_into<R: Read + Seek, B: VoxBuffer>(reader: R, buffer: &mut B) -> Result<(), Error> {
+    read_vox_into_with_options(reader, buffer, &ReaderOptions::default())?;
+    Ok(())
+}
+
+/// Reads a VOX file from the reader into the [`VoxBuffer`], validating it as
+/// specified by `options`.
+///
+/// Returns the problems that were repaired or skipped, if `options` uses
+/// [`ValidationMode::Lenient`] or recovery mode.
+///
+/// ```
+/// # use vox_format::{reader::{read_vox_into_with_options, ReaderOptions}, data::VoxData};
+/// # let vox_file = std::fs::File::open("../test_files/test_multiple_models.vox").unwrap();
+/// let mut vox = VoxData::default();
+/// let problems = read_vox_into_with_options(vox_file, &mut vox, &ReaderOptions::lenient());
+/// ```
+pub fn read_vox_into_with_options<R: Read + Seek, B: VoxBuffer>(
     mut reader: R,
     buffer: &mut B,
-) -> Result<(), Error> {
-    let (main_chunk, version) = read_main_chunk(&mut reader)?;
+    options: &ReaderOptions,
+) -> Result<Vec<Problem>, Error> {
+    let mut problems = vec![];
+
+    let (version, chunks) = read_main_children(&mut reader, options, &mut problems)?;
 
     buffer.set_version(version);
 
+    // Call `set_palette` first, so the trait impl has the palette data already when
+    // reading the voxels.
+    if let Some(palette) = chunks.read_palette(&mut reader, options, &mut problems)? {
+        buffer.set_palette(palette);
+    }
+
+    chunks.read_materials(&mut reader, buffer, options, &mut problems)?;
+
+    let num_models = chunks.models.len();
+    log::trace!("num_models = {}", num_models);
+    buffer.set_num_models(num_models);
+
+    for (model_index, (size_chunk, xyzi_chunk)) in chunks.models.iter().enumerate() {
+        let result = read_size(&mut reader, size_chunk, options, &mut problems);
+        let model_size = match options.try_recover(size_chunk, result, &mut problems)? {
+            Some(model_size) => model_size,
+            None => {
+                // Add an empty model, so that shapes still refer to the right models.
+                buffer.set_model_size(Size::new(1, 1, 1));
+                continue;
+            }
+        };
+        log::trace!("model_size = {:?}", model_size);
+        buffer.set_model_size(model_size);
+
+        let result = read_voxels(
+            &mut reader,
+            xyzi_chunk,
+            model_index,
+            model_size,
+            options,
+            &mut problems,
+            |voxel| buffer.set_voxel(voxel),
+        );
+        options.try_recover(xyzi_chunk, result, &mut problems)?;
+    }
+
+    chunks.read_nodes(&mut reader, buffer, options, &mut problems)?;
+    chunks.read_custom_chunks(&mut reader, buffer, options, &mut problems)?;
+
+    Ok(problems)
+}
+
+/// A chunk and its path from the `MAIN` chunk, which is used to add context to
+/// errors.
+#[derive(Clone, Debug)]
+pub(crate) struct LocatedChunk {
+    pub chunk: Chunk,
+    pub path: ChunkPath,
+}
+
+impl LocatedChunk {
+    /// Adds the chunk path and offset to `error`.
+    pub fn context(&self, error: Error) -> Error {
+        error.with_chunk(&self.path, self.chunk.offset())
+    }
+}
+
+/// The chunks in a `MAIN` chunk that we decode.
+pub(crate) struct MainChildren {
+    /// Pairs of `SIZE` and `XYZI` chunks in the order they appear in the file.
+    pub models: Vec<(LocatedChunk, LocatedChunk)>,
+
+    pub rgba: Option<LocatedChunk>,
+
+    /// Legacy `MATT` chunks.
+    pub materials: Vec<LocatedChunk>,
+
+    /// `MATL` chunks.
+    pub material_properties: Vec<LocatedChunk>,
+
+    /// `nTRN`, `nGRP` and `nSHP` chunks in the order they appear in the file.
+    pub nodes: Vec<LocatedChunk>,
+
+    pub layers: Vec<LocatedChunk>,
+
+    /// Chunks for which a codec was registered.
+    pub custom: Vec<LocatedChunk>,
+}
+
+impl MainChildren {
+    /// Reads the palette from the `RGBA` chunk, if there is one.
+    pub fn read_palette<R: Read + Seek>(
+        &self,
+        reader: R,
+        options: &ReaderOptions,
+        problems: &mut Vec<Problem>,
+    ) -> Result<Option<Palette>, Error> {
+        if let Some(rgba_chunk) = &self.rgba {
+            log::trace!("read RGBA chunk");
+            let result = rgba_chunk.chunk.content(reader).and_then(Palette::read);
+            let palette = options.try_recover(rgba_chunk, result, problems)?;
+
+            // The chunk contains 256 colors, but the last one is unused. We also accept
+            // palettes with only 255 colors, since that's what we write.
+            options.check_trailing_bytes(rgba_chunk, 1024, problems)?;
+
+            Ok(palette)
+        }
+        else {
+            log::trace!("no RGBA chunk found");
+            Ok(None)
+        }
+    }
+
+    /// Reads the legacy `MATT` chunks and the `MATL` chunks, and passes the
+    /// materials to `buffer`.
+    pub fn read_materials<R: Read + Seek, B: VoxBuffer>(
+        &self,
+        mut reader: R,
+        buffer: &mut B,
+        options: &ReaderOptions,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        for chunk in &self.materials {
+            let result = chunk
+                .chunk
+                .read_content_to_vec(&mut reader)
+                .and_then(|content| {
+                    let mut content = Cursor::new(content);
+                    let material = Material::read_matt(&mut content)?;
+                    Ok((material, content.position()))
+                });
+            if let Some(((material_id, material), num_bytes_read)) =
+                options.try_recover(chunk, result, problems)?
+            {
+                log::trace!("material {} = {:?}", material_id, material);
+                buffer.set_material(material_id, material);
+                options.check_trailing_bytes(chunk, num_bytes_read, problems)?;
+            }
+        }
+
+        for chunk in &self.material_properties {
+            let result = chunk
+                .chunk
+                .content(&mut reader)
+                .and_then(|content| MaterialProperties::read_with_limits(content, &options.limits));
+            match result {
+                Ok(properties) => {
+                    log::trace!("material properties = {:?}", properties);
+                    buffer.set_material_properties(properties);
+                }
+                Err(error) if options.check_material_properties => {
+                    options.report(chunk, error, problems)?;
+                }
+                Err(error) => log::warn!("Skipping {}: {}", chunk.path, error.message()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the scene graph nodes and layers and passes them to `buffer`.
+    pub fn read_nodes<R: Read + Seek, B: VoxBuffer>(
+        &self,
+        mut reader: R,
+        buffer: &mut B,
+        options: &ReaderOptions,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        for chunk in &self.nodes {
+            let result = chunk.chunk.content(&mut reader).and_then(|content| {
+                Ok(match chunk.chunk.id() {
+                    ChunkId::NTrn => {
+                        Node::Transform(Transform::read_with_limits(content, &options.limits)?)
+                    }
+                    ChunkId::NGrp => {
+                        Node::Group(Group::read_with_limits(content, &options.limits)?)
+                    }
+                    ChunkId::NShp => {
+                        Node::Shape(Shape::read_with_limits(content, &options.limits)?)
+                    }
+                    id => unreachable!("Not a node chunk: {:?}", id),
+                })
+            });
+            if let Some(mut node) = options.try_recover(chunk, result, problems)? {
+                log::trace!("node = {:?}", node);
+                if let (Node::Shape(shape), true) = (&mut node, options.check_model_ids) {
+                    self.check_model_ids(chunk, shape, options, problems)?;
+                }
+                buffer.set_node(node);
+            }
+        }
+
+        for chunk in &self.layers {
+            let result = chunk
+                .chunk
+                .content(&mut reader)
+                .and_then(|content| Layer::read_with_limits(content, &options.limits));
+            if let Some(layer) = options.try_recover(chunk, result, problems)? {
+                log::trace!("layer = {:?}", layer);
+                buffer.set_layer(layer);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports model IDs in `shape` that don't refer to a pair of `SIZE` and
+    /// `XYZI` chunks, and removes them.
+    fn check_model_ids(
+        &self,
+        chunk: &LocatedChunk,
+        shape: &mut Shape,
+        options: &ReaderOptions,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        let num_models = self.models.len();
+        let node_id = shape.node_id;
+        let mut result = Ok(());
+        shape.models.retain(|model| {
+            if result.is_err() || (model.model_id as usize) < num_models {
+                return true;
+            }
+            result = options.report(
+                chunk,
+                Error::InvalidModelId {
+                    node_id,
+                    model_id: model.model_id,
+                    num_models,
+                },
+                problems,
+            );
+            false
+        });
+        result
+    }
+
+    /// Decodes the custom chunks and passes them to `buffer`.
+    pub fn read_custom_chunks<R: Read + Seek, B: VoxBuffer>(
+        &self,
+        mut reader: R,
+        buffer: &mut B,
+        options: &ReaderOptions,
+        problems: &mut Vec<Problem>,
+    ) -> Result<(), Error> {
+        for chunk in &self.custom {
+            let codec = options
+                .codecs
+                .get(chunk.chunk.id())
+                .expect("codec for custom chunk");
+            let result = chunk
+                .chunk
+                .read_content_to_vec(&mut reader)
+                .and_then(|content| codec.decode(&content));
+            if let Some(custom_chunk) = options.try_recover(chunk, result, problems)? {
+                log::trace!("custom chunk = {:?}", custom_chunk);
+                buffer.set_custom_chunk(custom_chunk);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the file header and `MAIN` chunk, and collects the chunks that we
+/// decode.
+pub(crate) fn read_main_children<R: Read + Seek>(
+    mut reader: R,
+    options: &ReaderOptions,
+    problems: &mut Vec<Problem>,
+) -> Result<(Version, MainChildren), Error> {
+    let (main_chunk, version) = read_main_chunk_any_version(&mut reader)?;
+    if !version.is_supported() {
+        let error = Error::UnsupportedFileVersion { version };
+        if !options.allow_unknown_versions {
+            return Err(error);
+        }
+        let problem = Problem {
+            chunk_id: None,
+            // The version follows the 4 byte file magic.
+            offset: 4,
+            error,
+        };
+        log::warn!("Reading anyway: {}", problem.error.message());
+        problems.push(problem);
+    }
+
+    let main_chunk = LocatedChunk {
+        chunk: main_chunk,
+        path: ChunkPath::root(ChunkId::Main),
+    };
+    options
+        .limits
+        .check_chunk(&main_chunk.chunk, 1)
+        .map_err(|e| main_chunk.context(e))?;
+
     //print_chunk(&main_chunk, &mut self.reader, 0)?;
     log::trace!("main chunk: {:#?}", main_chunk);
 
     //let mut pack_chunk = None;
     let mut size_chunks = vec![];
     let mut xyzi_chunks = vec![];
-    let mut rgba_chunk = None;
-    let mut transform_chunks = vec![];
-    let mut group_chunks = vec![];
-    let mut shape_chunks = vec![];
+    let mut rgba_chunk: Option<LocatedChunk> = None;
+    let mut matt_chunks = vec![];
+    let mut matl_chunks = vec![];
+    let mut node_chunks = vec![];
     let mut layer_chunks = vec![];
+    let mut custom_chunks = vec![];
+    let mut num_children = HashMap::new();
+
+    let mut children = main_chunk.chunk.children(&mut reader);
+    loop {
+        let offset = children.offset();
+        let chunk = match children.next() {
+            Some(Ok(chunk)) => {
+                let index = num_children.entry(chunk.id()).or_insert(0);
+                let path = main_chunk.path.child(chunk.id(), *index);
+                *index += 1;
+                LocatedChunk { chunk, path }
+            }
+            Some(Err(error)) if options.recover => {
+                // We can't continue reading children, since we don't know where the next one
+                // starts.
+                let chunk_id = match &error {
+                    Error::InvalidChunkLength { chunk } => Some(chunk.id()),
+                    _ => None,
+                };
+                let problem = Problem {
+                    chunk_id,
+                    offset,
+                    error: error.with_chunk(&main_chunk.path, offset),
+                };
+                log::warn!("Recovering from: {}", problem.error.message());
+                problems.push(problem);
+                break;
+            }
+            Some(Err(error)) => return Err(error.with_chunk(&main_chunk.path, offset)),
+            None => break,
+        };
 
-    for r in main_chunk.children(&mut reader) {
-        let chunk = r?;
+        // We skip the children of `MAIN`'s children, but still reject them, if they're
+        // nested too deep.
+        let result = options
+            .limits
+            .check_chunk_tree(children.reader_mut(), &chunk.chunk, 2);
+        if options.try_recover(&chunk, result, problems)?.is_none() {
+            continue;
+        }
 
-        match chunk.id() {
+        match chunk.chunk.id() {
             /*ChunkId::Pack => {
                 log::debug!("read PACK chunk: {:?}", chunk);
                 if pack_chunk.is_some() {
@@ -138,21 +1010,30 @@ pub fn read_vox_into<R: Read + Seek, B: VoxBuffer>(
             ChunkId::Size => size_chunks.push(chunk),
             ChunkId::Xyzi => xyzi_chunks.push(chunk),
             ChunkId::Rgba => {
-                if rgba_chunk.is_some() {
-                    return Err(Error::MultipleRgbaChunks {
-                        chunks: [rgba_chunk.take().unwrap(), chunk],
-                    });
+                if let Some(first) = &rgba_chunk {
+                    options.report(
+                        &chunk,
+                        Error::MultipleRgbaChunks {
+                            chunks: [first.chunk.clone(), chunk.chunk.clone()],
+                        },
+                        problems,
+                    )?;
+                }
+                else {
+                    rgba_chunk = Some(chunk);
                 }
-                rgba_chunk = Some(chunk);
             }
+            ChunkId::Matt => matt_chunks.push(chunk),
+            // Codecs that were registered for `MATL` take precedence, since it used to be
+            // read only as a custom chunk.
+            id if options.codecs.get(id).is_some() => custom_chunks.push(chunk),
+            ChunkId::Matl => matl_chunks.push(chunk),
             /*ChunkId::Note => {
                 let data = chunk.read_content_to_vec(&mut reader)?;
                 log::error!("{:#?}", data);
                 todo!();
             },*/
-            ChunkId::NTrn => transform_chunks.push(chunk),
-            ChunkId::NGrp => group_chunks.push(chunk),
-            ChunkId::NShp => shape_chunks.push(chunk),
+            ChunkId::NTrn | ChunkId::NGrp | ChunkId::NShp => node_chunks.push(chunk),
             ChunkId::Layr => layer_chunks.push(chunk),
             ChunkId::Unsupported(raw) => {
                 let str_opt = from_utf8(&raw).ok();
@@ -162,39 +1043,6 @@ pub fn read_vox_into<R: Read + Seek, B: VoxBuffer>(
         }
     }
 
-    /*
-    for chunk in &transform_chunks {
-        let transform = Transform::read(chunk.content(&mut reader)?)?;
-        log::debug!("{:#?}", transform);
-    }
-
-    for chunk in &group_chunks {
-        let group = Group::read(chunk.content(&mut reader)?)?;
-        log::debug!("{:#?}", group);
-    }
-
-    for chunk in &shape_chunks {
-        let shape = Shape::read(chunk.content(&mut reader)?)?;
-        log::debug!("{:#?}", shape);
-    }
-
-    for chunk in &layer_chunks {
-        let layer = Layer::read(chunk.content(&mut reader)?)?;
-        log::debug!("{:#?}", layer);
-    }
-    */
-
-    // Call `set_palette` first, so the trait impl has the palette data already when
-    // reading the voxels.
-    if let Some(rgba_chunk) = rgba_chunk {
-        log::trace!("read RGBA chunk");
-        let palette = Palette::read(rgba_chunk.content(&mut reader)?)?;
-        buffer.set_palette(palette);
-    }
-    else {
-        log::trace!("no RGBA chunk found");
-    }
-
     /*let num_models = pack_chunk
         .map(|pack| Ok::<_, Error>(pack.content(&mut reader)?.read_u32::<LE>()? as usize))
         .transpose()?
@@ -202,40 +1050,151 @@ pub fn read_vox_into<R: Read + Seek, B: VoxBuffer>(
     log::trace!("num_models = {}", num_models);*/
 
     if xyzi_chunks.len() != size_chunks.len() {
-        return Err(Error::InvalidNumberOfSizeAndXyziChunks {
-            size_chunks,
-            xyzi_chunks,
-        });
+        options.report(
+            &main_chunk,
+            Error::InvalidNumberOfSizeAndXyziChunks {
+                size_chunks: size_chunks
+                    .iter()
+                    .map(|c: &LocatedChunk| c.chunk.clone())
+                    .collect(),
+                xyzi_chunks: xyzi_chunks
+                    .iter()
+                    .map(|c: &LocatedChunk| c.chunk.clone())
+                    .collect(),
+            },
+            problems,
+        )?;
     }
-    let num_models = size_chunks.len();
-    log::trace!("num_models = {}", num_models);
-    buffer.set_num_models(num_models);
 
-    for (size_chunk, xyzi_chunk) in size_chunks.into_iter().zip(xyzi_chunks) {
-        let model_size = Size::read(size_chunk.content(&mut reader)?)?;
-        log::trace!("model_size = {:?}", model_size);
-        buffer.set_model_size(model_size);
+    Limits::check(
+        LimitKind::Models,
+        size_chunks.len().min(xyzi_chunks.len()) as u64,
+        options.limits.max_models as u64,
+    )
+    .map_err(|e| main_chunk.context(e))?;
+
+    Ok((
+        version,
+        MainChildren {
+            models: size_chunks.into_iter().zip(xyzi_chunks).collect(),
+            rgba: rgba_chunk,
+            materials: matt_chunks,
+            material_properties: matl_chunks,
+            nodes: node_chunks,
+            layers: layer_chunks,
+            custom: custom_chunks,
+        },
+    ))
+}
+
+/// Reads the model size from a `SIZE` chunk.
+pub(crate) fn read_size<R: Read + Seek>(
+    reader: R,
+    size_chunk: &LocatedChunk,
+    options: &ReaderOptions,
+    problems: &mut Vec<Problem>,
+) -> Result<Size, Error> {
+    let model_size = Size::read(size_chunk.chunk.content(reader)?).field("model size")?;
+    options.check_trailing_bytes(size_chunk, 12, problems)?;
+    Ok(model_size)
+}
+
+/// Reads the voxels from a `XYZI` chunk and calls `f` for each of them.
+/// Voxels are validated against the model's size according to `options`.
+pub(crate) fn read_voxels<R: Read + Seek, F: FnMut(Voxel)>(
+    reader: R,
+    xyzi_chunk: &LocatedChunk,
+    model_index: usize,
+    model_size: Size,
+    options: &ReaderOptions,
+    problems: &mut Vec<Problem>,
+    mut f: F,
+) -> Result<(), Error> {
+    let mut reader = xyzi_chunk.chunk.content(reader)?;
+
+    let num_voxels = reader.read_u32::<LE>().field("number of voxels")?;
+    log::trace!("num_voxels = {}", num_voxels);
+    Limits::check(
+        LimitKind::Voxels,
+        num_voxels.into(),
+        options.limits.max_voxels.into(),
+    )?;
+
+    let mut occupied = HashSet::new();
+
+    for _ in 0..num_voxels {
+        let voxel = Voxel::read(&mut reader).field("voxel")?;
+        log::trace!("voxel = {:?}", voxel);
 
-        let mut reader = xyzi_chunk.content(&mut reader)?;
+        let mut errors = vec![];
 
-        let num_voxels = reader.read_u32::<LE>()?;
-        log::trace!("num_voxels = {}", num_voxels);
+        // Coordinates are stored as `u8` in the file.
+        let in_bounds = u32::from(voxel.point.x as u8) < model_size.x
+            && u32::from(voxel.point.y as u8) < model_size.y
+            && u32::from(voxel.point.z as u8) < model_size.z;
+        if options.check_bounds && !in_bounds {
+            errors.push(Error::VoxelOutOfBounds {
+                model_index,
+                voxel,
+                size: model_size,
+            });
+        }
+
+        if options.check_color_indices && voxel.color_index.0 == 0 {
+            errors.push(Error::ZeroColorIndex {
+                model_index,
+                point: voxel.point,
+            });
+        }
 
-        for _ in 0..num_voxels {
-            let voxel = Voxel::read(&mut reader)?;
-            log::trace!("voxel = {:?}", voxel);
-            buffer.set_voxel(voxel);
+        if options.check_duplicates && errors.is_empty() && !occupied.insert(voxel.point) {
+            errors.push(Error::DuplicateVoxel {
+                model_index,
+                point: voxel.point,
+            });
         }
+
+        if !errors.is_empty() {
+            for error in errors {
+                options.report(xyzi_chunk, error, problems)?;
+            }
+            continue;
+        }
+
+        f(voxel);
     }
 
+    let num_bytes_read = 4 + 4 * u64::from(num_voxels);
+    options.check_trailing_bytes(xyzi_chunk, num_bytes_read, problems)?;
+
     Ok(())
 }
 
 /// Reads a VOX file from a reader into [`crate::data::VoxData`].
+///
+/// With the `gzip` or `zstd` features, compressed files are decompressed
+/// transparently.
 pub fn from_reader<R: Read + Seek>(reader: R) -> Result<VoxData, Error> {
+    let (vox, _) = from_reader_with_options(reader, &ReaderOptions::default())?;
+    Ok(vox)
+}
+
+/// Reads a VOX file from a reader into [`crate::data::VoxData`], validating it
+/// as specified by `options`. Returns the data and the problems that were
+/// repaired or skipped. See [`read_vox_into_with_options`].
+pub fn from_reader_with_options<R: Read + Seek>(
+    #[allow(unused_mut)] mut reader: R,
+    options: &ReaderOptions,
+) -> Result<(VoxData, Vec<Problem>), Error> {
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    if let Some(data) = crate::compression::decompress_if_compressed(&mut reader, &options.limits)?
+    {
+        return from_reader_with_options(Cursor::new(data), options);
+    }
+
     let mut buffer = VoxData::default();
-    read_vox_into(reader, &mut buffer)?;
-    Ok(buffer)
+    let problems = read_vox_into_with_options(reader, &mut buffer, options)?;
+    Ok((buffer, problems))
 }
 
 /// Reads a VOX file from a slice into [`crate::data::VoxData`].
@@ -248,18 +1207,62 @@ pub fn from_file<P: AsRef<Path>>(path: P) -> Result<VoxData, Error> {
     from_reader(File::open(path)?)
 }
 
+/// Reads a VOX file from the specified path into [`crate::data::VoxData`],
+/// validating it as specified by `options`. See [`from_reader_with_options`].
+pub fn from_file_with_options<P: AsRef<Path>>(
+    path: P,
+    options: &ReaderOptions,
+) -> Result<(VoxData, Vec<Problem>), Error> {
+    from_reader_with_options(File::open(path)?, options)
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{
+        collections::HashMap,
+        io::{
+            Cursor,
+            Read,
+            Seek,
+            SeekFrom,
+        },
+    };
 
-    use super::from_slice;
-    use crate::types::{
-        Color,
-        ColorIndex,
-        Model,
-        Point,
-        Vector,
-        Voxel,
+    use super::{
+        from_reader_with_options,
+        from_slice,
+        Error,
+        ErrorContext,
+        LimitKind,
+        Limits,
+        Problem,
+        ReaderOptions,
+    };
+    use crate::{
+        chunk::{
+            ChunkId,
+            ChunkPath,
+            ChunkTree,
+        },
+        custom::ChunkCodec,
+        data::VoxData,
+        to_vec,
+        types::{
+            Attributes,
+            Color,
+            ColorIndex,
+            Material,
+            MaterialProperties,
+            MaterialType,
+            Model,
+            Node,
+            Point,
+            Shape,
+            ShapeModel,
+            Vector,
+            Version,
+            Voxel,
+        },
     };
 
     fn glider() -> Vec<Voxel> {
@@ -290,7 +1293,7 @@ mod tests {
             .collect::<HashMap<Point, ColorIndex>>();
 
         for expected_voxel in expected {
-            let voxel = voxels.get(&Vector::from(expected_voxel.point)).copied();
+            let voxel = voxels.get(&expected_voxel.point).copied();
             assert_eq!(
                 voxel,
                 Some(expected_voxel.color_index),
@@ -368,7 +1371,7 @@ mod tests {
 
         let color_index = vox
             .models
-            .get(0)
+            .first()
             .unwrap()
             .voxels
             .first()
@@ -376,4 +1379,459 @@ mod tests {
             .color_index;
         assert_eq!(vox.palette[color_index], Color::light_blue());
     }
+
+    fn invalid_voxels() -> Vec<u8> {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 2, 2),
+            voxels: vec![
+                Voxel::new([0, 0, 0], 1),
+                Voxel::new([2, 0, 0], 1),
+                Voxel::new([0, 0, 0], 2),
+                Voxel::new([1, 1, 1], 0),
+                Voxel::new([1, 0, 1], 3),
+            ],
+        });
+        to_vec(&vox).unwrap()
+    }
+
+    #[test]
+    fn strict_mode_rejects_invalid_voxels() {
+        let data = invalid_voxels();
+
+        let error =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::strict()).unwrap_err();
+        assert!(matches!(error.root(), Error::VoxelOutOfBounds { .. }));
+        assert_eq!(
+            error
+                .context()
+                .unwrap()
+                .chunk_path
+                .as_ref()
+                .unwrap()
+                .to_string(),
+            "MAIN/XYZI#0"
+        );
+
+        // The default options accept this file.
+        let vox = from_slice(&data).unwrap();
+        assert_eq!(vox.models[0].voxels.len(), 5);
+    }
+
+    #[test]
+    fn lenient_mode_skips_invalid_voxels() {
+        let (vox, problems) =
+            from_reader_with_options(Cursor::new(invalid_voxels()), &ReaderOptions::lenient())
+                .unwrap();
+
+        assert_eq!(
+            vox.models[0].voxels,
+            vec![Voxel::new([0, 0, 0], 1), Voxel::new([1, 0, 1], 3)]
+        );
+        assert_eq!(problems.len(), 3);
+        assert!(matches!(
+            problems[0].error.root(),
+            Error::VoxelOutOfBounds { .. }
+        ));
+        assert!(matches!(
+            problems[1].error.root(),
+            Error::DuplicateVoxel { .. }
+        ));
+        assert!(matches!(
+            problems[2].error.root(),
+            Error::ZeroColorIndex { .. }
+        ));
+    }
+
+    #[test]
+    fn it_rejects_chunks_with_overflowing_lengths() {
+        let mut data = b"VOX ".to_vec();
+        data.extend_from_slice(&200u32.to_le_bytes());
+        data.extend_from_slice(b"MAIN");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&12u32.to_le_bytes());
+        // A child that claims to be longer than its parent.
+        data.extend_from_slice(b"SIZE");
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        let error = from_slice(&data).unwrap_err();
+        assert!(matches!(error.root(), Error::InvalidChunkLength { .. }));
+    }
+
+    /// A reader that pretends to start at `base`, e.g. a VOX file embedded
+    /// deep in a larger archive.
+    struct Embedded<R> {
+        inner: R,
+        base: u64,
+    }
+
+    impl<R: Read> Read for Embedded<R> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.inner.read(buf)
+        }
+    }
+
+    impl<R: Seek> Seek for Embedded<R> {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            let pos = match pos {
+                SeekFrom::Start(pos) => SeekFrom::Start(pos - self.base),
+                pos => pos,
+            };
+            Ok(self.inner.seek(pos)? + self.base)
+        }
+    }
+
+    #[test]
+    fn it_reads_files_at_offsets_beyond_4_gib() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 3),
+            voxels: glider(),
+        });
+        let reader = Embedded {
+            inner: Cursor::new(to_vec(&vox).unwrap()),
+            base: 5 << 30,
+        };
+
+        let (vox, _) = from_reader_with_options(reader, &ReaderOptions::strict()).unwrap();
+        assert_eq!(vox.models[0].voxels, glider());
+    }
+
+    #[test]
+    fn it_formats_error_contexts() {
+        let context = ErrorContext {
+            chunk_path: Some(ChunkPath::root(ChunkId::Main).child(ChunkId::Matl, 0)),
+            offset: Some(20),
+            field: Some("material attributes"),
+        };
+        assert_eq!(
+            context.to_string(),
+            "in chunk MAIN/MATL#0, at offset 20, while reading material attributes"
+        );
+
+        let context = ErrorContext {
+            chunk_path: None,
+            offset: Some(20),
+            field: Some("material attributes"),
+        };
+        assert_eq!(
+            context.to_string(),
+            "at offset 20, while reading material attributes"
+        );
+
+        let context = ErrorContext {
+            chunk_path: None,
+            offset: None,
+            field: Some("material attributes"),
+        };
+        assert_eq!(context.to_string(), "while reading material attributes");
+    }
+
+    #[test]
+    fn it_doesnt_repeat_messages_in_error_chains() {
+        let error = Error::Context {
+            context: ErrorContext {
+                chunk_path: Some(ChunkPath::root(ChunkId::Main).child(ChunkId::Xyzi, 0)),
+                offset: Some(20),
+                field: Some("voxel"),
+            },
+            source: Box::new(Error::ZeroColorIndex {
+                model_index: 0,
+                point: Point::new(1, 2, 3),
+            }),
+        };
+        let problem = Problem {
+            chunk_id: Some(ChunkId::Xyzi),
+            offset: 20,
+            error,
+        };
+
+        let mut messages = vec![problem.to_string()];
+        let mut source = std::error::Error::source(&problem);
+        while let Some(error) = source {
+            messages.push(error.to_string());
+            source = error.source();
+        }
+        assert_eq!(
+            messages,
+            [
+                "Error in chunk MAIN/XYZI#0, at offset 20, while reading voxel",
+                "Voxel at (1, 2, 3) in model 0 has color index 0.",
+            ]
+        );
+        assert_eq!(
+            problem.error.message(),
+            "Voxel at (1, 2, 3) in model 0 has color index 0. (in chunk MAIN/XYZI#0, at \
+             offset 20, while reading voxel)"
+        );
+    }
+
+    #[test]
+    fn it_enforces_the_string_length_limit() {
+        // One attribute with a key that claims to be 4 GiB long.
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&u32::MAX.to_le_bytes());
+        data.extend_from_slice(b"_name");
+
+        let error = Attributes::read_with_limits(&data[..], &Limits::untrusted()).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::LimitExceeded {
+                kind: LimitKind::StringLength,
+                ..
+            }
+        ));
+
+        // Without limits we must not allocate the buffer upfront.
+        let error = Attributes::read(&data[..]).unwrap_err();
+        assert!(matches!(error.root(), Error::Io(_)));
+        assert_eq!(error.context().unwrap().field, Some("attribute key string"));
+    }
+
+    #[test]
+    fn it_keeps_model_indices_when_recovering_from_invalid_sizes() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 3),
+            voxels: glider(),
+        });
+        vox.models.push(Model {
+            size: Vector::new(3, 3, 1),
+            voxels: glider2(),
+        });
+        let scene = vox.scene().unwrap();
+        vox.set_scene(&scene).unwrap();
+
+        // Cut the first `SIZE` chunk short.
+        let mut tree = ChunkTree::from_slice(&to_vec(&vox).unwrap()).unwrap();
+        assert_eq!(tree.root.children[0].id, ChunkId::Size);
+        tree.root.children[0].content.truncate(4);
+        let data = tree.to_vec().unwrap();
+
+        let (read, problems) =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::recovery()).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].chunk_id, Some(ChunkId::Size));
+
+        assert_eq!(read.models.len(), 2);
+        assert!(read.models[0].voxels.is_empty());
+        assert_eq!(read.models[1].voxels, glider2());
+        let scene = read.scene().unwrap();
+        assert_eq!(scene.instances(1).len(), 1);
+    }
+
+    #[test]
+    fn it_recovers_models_from_truncated_files() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 3),
+            voxels: glider(),
+        });
+        vox.models.push(Model {
+            size: Vector::new(3, 3, 1),
+            voxels: glider2(),
+        });
+        let mut data = to_vec(&vox).unwrap();
+
+        // Cut off the last two voxels.
+        data.truncate(data.len() - 8);
+        assert!(from_slice(&data).is_err());
+
+        let (vox, problems) =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::recovery()).unwrap();
+
+        assert_eq!(vox.models.len(), 2);
+        assert_eq!(vox.models[0].voxels, glider());
+        assert_eq!(vox.models[1].voxels, &glider2()[..3]);
+
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].chunk_id, Some(ChunkId::Xyzi));
+        assert!(matches!(problems[0].error.root(), Error::Io(_)));
+        let context = problems[0].error.context().unwrap();
+        assert_eq!(
+            context.chunk_path.as_ref().unwrap().to_string(),
+            "MAIN/XYZI#1"
+        );
+        assert_eq!(context.field, Some("voxel"));
+    }
+
+    #[test]
+    fn it_reads_legacy_materials() {
+        let mut vox = VoxData {
+            version: Version::V150,
+            ..VoxData::default()
+        };
+        vox.materials.insert(
+            ColorIndex(3),
+            Material {
+                ty: MaterialType::Metal,
+                weight: 0.5,
+                plastic: None,
+                roughness: Some(0.25),
+                specular: None,
+                ior: None,
+                attenuation: None,
+                power: None,
+                glow: None,
+                is_total_power: true,
+            },
+        );
+        let data = to_vec(&vox).unwrap();
+
+        let vox = from_slice(&data).unwrap();
+        assert_eq!(vox.version, Version::V150);
+        let material = vox.materials.get(ColorIndex(3)).unwrap();
+        assert_eq!(material.ty, MaterialType::Metal);
+        assert_eq!(material.weight, 0.5);
+        assert_eq!(material.roughness, Some(0.25));
+        assert!(material.is_total_power);
+        assert_eq!(to_vec(&vox).unwrap(), data);
+    }
+
+    #[test]
+    fn it_reads_material_properties() {
+        let vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_materials.vox"
+        )))
+        .unwrap();
+        assert_eq!(vox.material_properties.len(), 256);
+
+        let properties = vox
+            .material_properties
+            .iter()
+            .find(|properties| properties.material_id == 1)
+            .unwrap();
+        assert_eq!(properties.material_type(), Some("_diffuse"));
+        assert_eq!(properties.get_f32("_rough"), Some(0.1));
+
+        let data = to_vec(&vox).unwrap();
+        let read = from_slice(&data).unwrap();
+        assert_eq!(read.material_properties.len(), 256);
+        assert_eq!(
+            read.canonical_hash().unwrap(),
+            vox.canonical_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn it_skips_invalid_material_properties() {
+        let mut vox = VoxData::default();
+        vox.material_properties.push(MaterialProperties {
+            material_id: 1,
+            attributes: Attributes::new(),
+        });
+        let mut data = to_vec(&vox).unwrap();
+
+        // Let the attributes claim to have one entry. Their number follows the chunk
+        // header and the material ID.
+        let offset = data.windows(4).position(|id| id == b"MATL").unwrap() + 16;
+        data[offset..offset + 4].copy_from_slice(&1u32.to_le_bytes());
+
+        assert!(from_slice(&data).unwrap().material_properties.is_empty());
+        let error =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::strict()).unwrap_err();
+        let chunk_path = error.context().unwrap().chunk_path.as_ref().unwrap();
+        assert_eq!(chunk_path.to_string(), "MAIN/MATL#0");
+
+        // A codec for `MATL` takes precedence.
+        let mut options = ReaderOptions::default();
+        options.codecs.register(ChunkCodec::new(
+            ChunkId::Matl,
+            |data: &[u8]| Ok(data.to_vec()),
+            |data: &Vec<u8>, writer| {
+                writer.extend_from_slice(data);
+                Ok(())
+            },
+        ));
+        let (vox, _) = from_reader_with_options(Cursor::new(&data), &options).unwrap();
+        assert!(vox.material_properties.is_empty());
+        assert_eq!(vox.custom_chunks.len(), 1);
+    }
+
+    #[test]
+    fn it_tries_to_read_unknown_versions() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 3),
+            voxels: glider(),
+        });
+        let mut data = to_vec(&vox).unwrap();
+        data[4..8].copy_from_slice(&201u32.to_le_bytes());
+
+        assert!(matches!(
+            from_slice(&data).unwrap_err(),
+            Error::UnsupportedFileVersion {
+                version: Version(201)
+            }
+        ));
+
+        let options = ReaderOptions {
+            allow_unknown_versions: true,
+            ..ReaderOptions::default()
+        };
+        let (vox, problems) = from_reader_with_options(Cursor::new(&data), &options).unwrap();
+        assert_eq!(vox.version, Version(201));
+        assert_eq!(vox.models[0].voxels, glider());
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            problems[0].error,
+            Error::UnsupportedFileVersion { .. }
+        ));
+    }
+
+    #[test]
+    fn it_checks_model_ids_of_shapes() {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(3, 1, 3),
+            voxels: glider(),
+        });
+        vox.nodes.push(Node::Shape(Shape {
+            node_id: 0,
+            attributes: Attributes::new(),
+            models: vec![
+                ShapeModel {
+                    model_id: 0,
+                    attributes: Attributes::new(),
+                },
+                ShapeModel {
+                    model_id: 0,
+                    attributes: Attributes::new(),
+                },
+            ],
+        }));
+        let mut data = to_vec(&vox).unwrap();
+
+        // Let the second model entry refer to model 1. It's the last 8 bytes: The model
+        // ID and the empty attributes.
+        let len = data.len();
+        data[len - 8..len - 4].copy_from_slice(&1u32.to_le_bytes());
+
+        let vox = from_slice(&data).unwrap();
+        match &vox.nodes[0] {
+            Node::Shape(shape) => assert_eq!(shape.models[1].model_id, 1),
+            node => panic!("Expected shape, but got: {:?}", node),
+        }
+
+        let error =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::strict()).unwrap_err();
+        assert!(matches!(
+            error.root(),
+            Error::InvalidModelId {
+                node_id: 0,
+                model_id: 1,
+                num_models: 1
+            }
+        ));
+
+        let (vox, problems) =
+            from_reader_with_options(Cursor::new(&data), &ReaderOptions::lenient()).unwrap();
+        assert_eq!(problems.len(), 1);
+        match &vox.nodes[0] {
+            Node::Shape(shape) => assert_eq!(shape.models.len(), 1),
+            node => panic!("Expected shape, but got: {:?}", node),
+        }
+    }
 }
@@ -0,0 +1,821 @@
+//! A high-level view of the scene graph.
+//!
+//! The scene graph in a VOX file consists of nodes that refer to each other by
+//! ID: Transform nodes place a group or shape node, groups contain transform
+//! nodes, and shapes display models. A [`Scene`] combines each transform with
+//! the node it places into an [`Object`], and keeps track of the hierarchy
+//! itself, so objects can be added, removed and moved around without caring
+//! about node IDs. Node IDs are assigned when the scene is converted back to
+//! nodes with [`Scene::to_nodes`].
+//!
+//! Several shapes can display the same model. This is how MagicaVoxel
+//! implements instancing.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{scene::Scene, types::{Model, Vector}, VoxData};
+//! let mut vox = VoxData::default();
+//! vox.models.push(Model {
+//!     size: Vector::new(1, 1, 1),
+//!     voxels: vec![],
+//! });
+//!
+//! let mut scene = vox.scene().unwrap();
+//! let tree = scene.find_by_name("tree");
+//! assert!(tree.is_none());
+//!
+//! // Place the model a second time.
+//! let instance = scene.add_shape(scene.root(), 0).unwrap();
+//! scene.get_mut(instance).unwrap().set_name("tree");
+//! vox.set_scene(&scene).unwrap();
+//!
+//! let scene = vox.scene().unwrap();
+//! assert_eq!(scene.instances(0).len(), 2);
+//! assert!(scene.find_by_name("tree").is_some());
+//! ```
+
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    convert::TryFrom,
+};
+
+use thiserror::Error;
+
+use crate::{
+    animation::{
+        AnimationError,
+        Pose,
+    },
+    data::{
+        VoxData,
+        VoxModels,
+    },
+    types::{
+        Attributes,
+        ColorIndex,
+        Group,
+        Node,
+        Shape,
+        ShapeModel,
+        Transform,
+        Vector,
+    },
+    writer::default_scene_graph,
+};
+
+/// Error type for operations on a [`Scene`].
+#[derive(Debug, Error)]
+pub enum SceneError {
+    /// A node refers to a node that doesn't exist.
+    #[error("Node {node_id} doesn't exist.")]
+    MissingNode { node_id: u32 },
+
+    /// A node has a different type than expected, e.g. a group contains a
+    /// shape without a transform.
+    #[error("Expected node {node_id} to be a {expected}.")]
+    UnexpectedNode {
+        node_id: u32,
+        expected: &'static str,
+    },
+
+    /// A node is referred to more than once.
+    #[error("Node {node_id} is referred to more than once.")]
+    SharedNode { node_id: u32 },
+
+    /// The object doesn't exist, e.g. because it was removed.
+    #[error("Object {0:?} doesn't exist.")]
+    InvalidObject(ObjectId),
+
+    /// Objects can only be added to groups.
+    #[error("Object {0:?} isn't a group.")]
+    NotAGroup(ObjectId),
+
+    /// An object can't be moved into itself or one of its descendants.
+    #[error("Object {object:?} can't be moved into {parent:?}.")]
+    InvalidParent { object: ObjectId, parent: ObjectId },
+
+    /// The root object can't be removed or moved.
+    #[error("The root object can't be removed or moved.")]
+    Root,
+
+    /// There are too many objects to assign node IDs.
+    #[error("Node IDs overflowed.")]
+    NodeIdOverflow,
+}
+
+/// Identifies an object in a [`Scene`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ObjectId(usize);
+
+/// What an [`Object`] places in the scene.
+#[derive(Clone, Debug)]
+pub enum Content {
+    /// A group of objects. The group's `node_id` and `children` are ignored.
+    Group(Group),
+
+    /// A shape that displays models. The shape's `node_id` is ignored.
+    Shape(Shape),
+}
+
+/// An object in a [`Scene`]. This is a transform node together with the group
+/// or shape node it places.
+#[derive(Clone, Debug)]
+pub struct Object {
+    /// The transform node. Its `node_id` and `child_node_id` are ignored. Its
+    /// attributes contain the object's name and whether it's hidden, and its
+    /// frames contain the object's position.
+    pub transform: Transform,
+
+    pub content: Content,
+
+    parent: Option<ObjectId>,
+    children: Vec<ObjectId>,
+}
+
+impl Object {
+    fn new(content: Content, layer_id: Option<u32>) -> Self {
+        Self {
+            transform: Transform {
+                node_id: 0,
+                attributes: Attributes::new(),
+                child_node_id: 0,
+                reserved_id: None,
+                layer_id,
+                frames: vec![Attributes::new()],
+            },
+            content,
+            parent: None,
+            children: vec![],
+        }
+    }
+
+    /// Returns the object's name (`_name`).
+    pub fn name(&self) -> Option<&str> {
+        self.transform.attributes.name()
+    }
+
+    /// Sets the object's name (`_name`).
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.transform.attributes.set_name(name);
+    }
+
+    /// Returns the ID of the layer the object is on.
+    pub fn layer_id(&self) -> Option<u32> {
+        self.transform.layer_id
+    }
+
+    /// Moves the object to another layer.
+    pub fn set_layer_id(&mut self, layer_id: Option<u32>) {
+        self.transform.layer_id = layer_id;
+    }
+
+    /// Returns the parent object, or `None` for the root object.
+    pub fn parent(&self) -> Option<ObjectId> {
+        self.parent
+    }
+
+    /// Returns the children of a group object.
+    pub fn children(&self) -> &[ObjectId] {
+        &self.children
+    }
+
+    /// Returns `true`, if the object is a group.
+    pub fn is_group(&self) -> bool {
+        matches!(self.content, Content::Group(_))
+    }
+
+    /// Returns the shape, if the object is a shape.
+    pub fn shape(&self) -> Option<&Shape> {
+        match &self.content {
+            Content::Shape(shape) => Some(shape),
+            Content::Group(_) => None,
+        }
+    }
+}
+
+/// A scene graph made of [`Object`]s.
+#[derive(Clone, Debug)]
+pub struct Scene {
+    /// Removed objects are `None`, so that IDs stay valid.
+    objects: Vec<Option<Object>>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scene {
+    /// Creates a scene that only contains an empty root group.
+    pub fn new() -> Self {
+        let root = Object::new(
+            Content::Group(Group {
+                node_id: 0,
+                attributes: Attributes::new(),
+                children: vec![],
+            }),
+            None,
+        );
+        Self {
+            objects: vec![Some(root)],
+        }
+    }
+
+    /// Builds a scene from scene graph nodes. The root of the scene graph is
+    /// the transform node with ID 0.
+    pub fn from_nodes(nodes: &[Node]) -> Result<Self, SceneError> {
+        let nodes = nodes
+            .iter()
+            .map(|node| (node.node_id(), node))
+            .collect::<HashMap<_, _>>();
+
+        let mut scene = Self { objects: vec![] };
+        let mut visited = HashSet::new();
+
+        // The scene graph can be nested arbitrarily deep, so we don't recurse. Children
+        // are pushed in reverse, so objects get their IDs in depth-first order.
+        let mut stack = vec![(0, None)];
+        while let Some((node_id, parent)) = stack.pop() {
+            let (id, children) = scene.add_node(&nodes, node_id, parent, &mut visited)?;
+            stack.extend(children.into_iter().rev().map(|child| (child, Some(id))));
+        }
+
+        Ok(scene)
+    }
+
+    /// Adds the transform node `node_id` as an object to `parent`. Returns the
+    /// object and the transform nodes of its children, which are added by the
+    /// caller.
+    fn add_node(
+        &mut self,
+        nodes: &HashMap<u32, &Node>,
+        node_id: u32,
+        parent: Option<ObjectId>,
+        visited: &mut HashSet<u32>,
+    ) -> Result<(ObjectId, Vec<u32>), SceneError> {
+        let mut get = |node_id: u32| {
+            if !visited.insert(node_id) {
+                return Err(SceneError::SharedNode { node_id });
+            }
+            nodes
+                .get(&node_id)
+                .copied()
+                .ok_or(SceneError::MissingNode { node_id })
+        };
+
+        let transform = match get(node_id)? {
+            Node::Transform(transform) => transform.clone(),
+            _ => {
+                return Err(SceneError::UnexpectedNode {
+                    node_id,
+                    expected: "transform",
+                })
+            }
+        };
+
+        let (content, children) = match get(transform.child_node_id)? {
+            Node::Group(group) => (Content::Group(group.clone()), group.children.clone()),
+            Node::Shape(shape) => (Content::Shape(shape.clone()), vec![]),
+            Node::Transform(_) => {
+                return Err(SceneError::UnexpectedNode {
+                    node_id: transform.child_node_id,
+                    expected: "group or shape",
+                })
+            }
+        };
+
+        let id = ObjectId(self.objects.len());
+        self.objects.push(Some(Object {
+            transform,
+            content,
+            parent,
+            children: vec![],
+        }));
+        if let Some(parent) = parent {
+            self.object_mut(parent)?.children.push(id);
+        }
+
+        Ok((id, children))
+    }
+
+    /// Converts the scene to scene graph nodes.
+    ///
+    /// Node IDs are assigned in depth-first order, like MagicaVoxel does: The
+    /// root object's transform has ID 0, and its group ID 1.
+    pub fn to_nodes(&self) -> Result<Vec<Node>, SceneError> {
+        // Collect the objects in depth-first order without recursing. Each object
+        // takes two node IDs: One for its transform, and one for its content.
+        let mut objects = vec![];
+        let mut stack = vec![self.root()];
+        while let Some(id) = stack.pop() {
+            let object = self.object(id)?;
+            stack.extend(object.children.iter().rev());
+            objects.push((id, object));
+        }
+
+        let transform_ids = objects
+            .iter()
+            .enumerate()
+            .map(|(index, (id, _))| {
+                // The content's node ID must fit as well.
+                let transform_id = index
+                    .checked_mul(2)
+                    .and_then(|node_id| u32::try_from(node_id).ok())
+                    .filter(|node_id| *node_id < u32::MAX)
+                    .ok_or(SceneError::NodeIdOverflow)?;
+                Ok((*id, transform_id))
+            })
+            .collect::<Result<HashMap<_, _>, SceneError>>()?;
+
+        let mut nodes = Vec::with_capacity(2 * objects.len());
+        for (id, object) in objects {
+            let transform_id = transform_ids[&id];
+            let content_id = transform_id + 1;
+
+            let mut transform = object.transform.clone();
+            transform.node_id = transform_id;
+            transform.child_node_id = content_id;
+            nodes.push(Node::Transform(transform));
+
+            match &object.content {
+                Content::Group(group) => {
+                    nodes.push(Node::Group(Group {
+                        node_id: content_id,
+                        attributes: group.attributes.clone(),
+                        children: object
+                            .children
+                            .iter()
+                            .map(|child| transform_ids[child])
+                            .collect(),
+                    }));
+                }
+                Content::Shape(shape) => {
+                    nodes.push(Node::Shape(Shape {
+                        node_id: content_id,
+                        ..shape.clone()
+                    }));
+                }
+            }
+        }
+
+        Ok(nodes)
+    }
+
+    /// Returns the root object.
+    pub fn root(&self) -> ObjectId {
+        ObjectId(0)
+    }
+
+    /// Returns the object with the given ID, or `None`, if it was removed.
+    pub fn get(&self, id: ObjectId) -> Option<&Object> {
+        self.objects.get(id.0)?.as_ref()
+    }
+
+    /// Returns the object with the given ID, or `None`, if it was removed.
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut Object> {
+        self.objects.get_mut(id.0)?.as_mut()
+    }
+
+    fn object(&self, id: ObjectId) -> Result<&Object, SceneError> {
+        self.get(id).ok_or(SceneError::InvalidObject(id))
+    }
+
+    fn object_mut(&mut self, id: ObjectId) -> Result<&mut Object, SceneError> {
+        self.get_mut(id).ok_or(SceneError::InvalidObject(id))
+    }
+
+    /// Creates an iterator over all objects in depth-first order.
+    pub fn objects(&self) -> impl Iterator<Item = (ObjectId, &Object)> {
+        let mut stack = vec![self.root()];
+        std::iter::from_fn(move || {
+            let id = stack.pop()?;
+            let object = self.get(id)?;
+            stack.extend(object.children.iter().rev());
+            Some((id, object))
+        })
+    }
+
+    /// Returns the first object with the given name in depth-first order.
+    pub fn find_by_name(&self, name: &str) -> Option<ObjectId> {
+        self.objects()
+            .find(|(_, object)| object.name() == Some(name))
+            .map(|(id, _)| id)
+    }
+
+    /// Returns the objects on the given layer.
+    pub fn objects_on_layer(&self, layer_id: u32) -> Vec<ObjectId> {
+        self.objects()
+            .filter(|(_, object)| object.layer_id() == Some(layer_id))
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the shape objects that display the model `model_id`.
+    pub fn instances(&self, model_id: u32) -> Vec<ObjectId> {
+        self.objects()
+            .filter(|(_, object)| {
+                object.shape().is_some_and(|shape| {
+                    shape.models.iter().any(|model| model.model_id == model_id)
+                })
+            })
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Adds an empty group to `parent`. The new object is on the parent's
+    /// layer.
+    pub fn add_group(&mut self, parent: ObjectId) -> Result<ObjectId, SceneError> {
+        self.add(
+            parent,
+            Content::Group(Group {
+                node_id: 0,
+                attributes: Attributes::new(),
+                children: vec![],
+            }),
+        )
+    }
+
+    /// Adds a shape that displays the model `model_id` to `parent`. The new
+    /// object is on the parent's layer.
+    pub fn add_shape(&mut self, parent: ObjectId, model_id: u32) -> Result<ObjectId, SceneError> {
+        self.add(
+            parent,
+            Content::Shape(Shape {
+                node_id: 0,
+                attributes: Attributes::new(),
+                models: vec![ShapeModel {
+                    model_id,
+                    attributes: Attributes::new(),
+                }],
+            }),
+        )
+    }
+
+    fn add(&mut self, parent: ObjectId, content: Content) -> Result<ObjectId, SceneError> {
+        let parent_object = self.object(parent)?;
+        if !parent_object.is_group() {
+            return Err(SceneError::NotAGroup(parent));
+        }
+        // The root transform isn't on a layer, but its children are usually on layer 0.
+        let layer_id = parent_object.layer_id().or(Some(0));
+
+        let id = ObjectId(self.objects.len());
+        let mut object = Object::new(content, layer_id);
+        object.parent = Some(parent);
+        self.objects.push(Some(object));
+        self.object_mut(parent)?.children.push(id);
+        Ok(id)
+    }
+
+    /// Copies the object `source_id` of `source` and its descendants into the
+    /// group `parent`. `f` is called for each copy, e.g. to renumber the
+    /// models of shapes. Returns the ID of the copy of `source_id`.
+    pub fn copy_from(
+        &mut self,
+        parent: ObjectId,
+        source: &Scene,
+        source_id: ObjectId,
+        f: &mut impl FnMut(&mut Object),
+    ) -> Result<ObjectId, SceneError> {
+        if !self.object(parent)?.is_group() {
+            return Err(SceneError::NotAGroup(parent));
+        }
+
+        let object = source.object(source_id)?;
+        let id = self.copy_object(parent, object, f)?;
+
+        // Copy the descendants in depth-first order, without recursing.
+        let mut stack = object
+            .children
+            .iter()
+            .rev()
+            .map(|child| (*child, id))
+            .collect::<Vec<_>>();
+        while let Some((source_id, parent)) = stack.pop() {
+            let object = source.object(source_id)?;
+            let copy = self.copy_object(parent, object, f)?;
+            stack.extend(object.children.iter().rev().map(|child| (*child, copy)));
+        }
+
+        Ok(id)
+    }
+
+    /// Adds a copy of `object` without its children to the group `parent`.
+    fn copy_object(
+        &mut self,
+        parent: ObjectId,
+        object: &Object,
+        f: &mut impl FnMut(&mut Object),
+    ) -> Result<ObjectId, SceneError> {
+        let mut copy = Object {
+            transform: object.transform.clone(),
+            content: object.content.clone(),
+            parent: Some(parent),
+            children: vec![],
+        };
+        f(&mut copy);
+
+        let id = ObjectId(self.objects.len());
+        self.objects.push(Some(copy));
+        self.object_mut(parent)?.children.push(id);
+        Ok(id)
+    }
+
+    /// Removes an object and its descendants.
+    pub fn remove(&mut self, id: ObjectId) -> Result<(), SceneError> {
+        let parent = self.object(id)?.parent.ok_or(SceneError::Root)?;
+        self.object_mut(parent)?
+            .children
+            .retain(|child| *child != id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            if let Some(object) = self.objects[id.0].take() {
+                stack.extend(object.children);
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves an object into the group `parent`. The object's transform stays
+    /// the same, so it's now relative to its new parent.
+    pub fn reparent(&mut self, id: ObjectId, parent: ObjectId) -> Result<(), SceneError> {
+        let old_parent = self.object(id)?.parent.ok_or(SceneError::Root)?;
+        if !self.object(parent)?.is_group() {
+            return Err(SceneError::NotAGroup(parent));
+        }
+
+        // Check that `parent` isn't `id` or one of its descendants.
+        let mut ancestor = Some(parent);
+        while let Some(ancestor_id) = ancestor {
+            if ancestor_id == id {
+                return Err(SceneError::InvalidParent { object: id, parent });
+            }
+            ancestor = self.object(ancestor_id)?.parent;
+        }
+
+        self.object_mut(old_parent)?
+            .children
+            .retain(|child| *child != id);
+        self.object_mut(parent)?.children.push(id);
+        self.object_mut(id)?.parent = Some(parent);
+        Ok(())
+    }
+
+    /// Returns the pose of an object relative to the scene's origin at the
+    /// given animation frame. This combines the poses of the object and all
+    /// its ancestors.
+    pub fn world_pose(&self, id: ObjectId, frame: u32) -> Result<Pose, WorldPoseError> {
+        let mut pose = Pose::default();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let object = self.object(id)?;
+            let local = object.transform.pose_at(frame)?;
+            let translation = local.rotation.apply(pose.translation);
+            pose = Pose {
+                translation: Vector::new(
+                    translation.x + local.translation.x,
+                    translation.y + local.translation.y,
+                    translation.z + local.translation.z,
+                ),
+                rotation: local.rotation * pose.rotation,
+            };
+            current = object.parent;
+        }
+        Ok(pose)
+    }
+}
+
+/// Error returned by [`Scene::world_pose`].
+#[derive(Debug, Error)]
+pub enum WorldPoseError {
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+}
+
+/// Error returned by [`for_each_voxel`].
+#[derive(Debug, Error)]
+pub(crate) enum ForEachVoxelError {
+    #[error("Shape refers to model {model_id}, which doesn't exist.")]
+    InvalidModelId { model_id: u32 },
+
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+}
+
+impl From<WorldPoseError> for ForEachVoxelError {
+    fn from(e: WorldPoseError) -> Self {
+        match e {
+            WorldPoseError::Scene(e) => e.into(),
+            WorldPoseError::Animation(e) => e.into(),
+        }
+    }
+}
+
+/// Calls `f` for each voxel of each shape in the scene, with the voxel's
+/// position in the scene at the given animation frame. Shapes are visited in
+/// depth-first order.
+pub(crate) fn for_each_voxel(
+    vox: &VoxData,
+    frame: u32,
+    mut f: impl FnMut(&Object, Vector<i32>, ColorIndex),
+) -> Result<(), ForEachVoxelError> {
+    let scene = vox.scene()?;
+
+    for (id, object) in scene.objects() {
+        let model_id = match object.shape() {
+            Some(shape) => {
+                match shape.model_at(frame)? {
+                    Some(model_id) => model_id,
+                    None => continue,
+                }
+            }
+            None => continue,
+        };
+        let model = vox
+            .models
+            .get(model_id as usize)
+            .ok_or(ForEachVoxelError::InvalidModelId { model_id })?;
+
+        let pose = scene.world_pose(id, frame)?;
+        let pivot = model.pivot();
+
+        for voxel in &model.voxels {
+            // Coordinates are stored as `i8`, but models can be up to 256 voxels large.
+            let point = voxel.point;
+            let point = Vector::new(point.x as u8, point.y as u8, point.z as u8);
+            let point = pose.rotation.apply(Vector::new(
+                i32::from(point.x) - pivot.x,
+                i32::from(point.y) - pivot.y,
+                i32::from(point.z) - pivot.z,
+            ));
+            f(
+                object,
+                Vector::new(
+                    point.x + pose.translation.x,
+                    point.y + pose.translation.y,
+                    point.z + pose.translation.z,
+                ),
+                voxel.color_index,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+impl<V> VoxModels<V> {
+    /// Returns the scene graph as a [`Scene`]. If there are no nodes, this
+    /// returns the default scene graph, in which each model is displayed
+    /// once.
+    pub fn scene(&self) -> Result<Scene, SceneError> {
+        if self.nodes.is_empty() {
+            let (nodes, _) =
+                default_scene_graph(self.models.len()).map_err(|_| SceneError::NodeIdOverflow)?;
+            Scene::from_nodes(&nodes)
+        }
+        else {
+            Scene::from_nodes(&self.nodes)
+        }
+    }
+
+    /// Replaces the nodes with the nodes of `scene`.
+    pub fn set_scene(&mut self, scene: &Scene) -> Result<(), SceneError> {
+        self.nodes = scene.to_nodes()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Scene,
+        SceneError,
+    };
+    use crate::{
+        from_slice,
+        types::{
+            Node,
+            Rotation,
+            Vector,
+        },
+    };
+
+    fn scene() -> Scene {
+        from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap()
+        .scene()
+        .unwrap()
+    }
+
+    #[test]
+    fn it_reads_the_scene_graph() {
+        let scene = scene();
+        let root = scene.get(scene.root()).unwrap();
+        assert_eq!(root.children().len(), 2);
+        assert_eq!(scene.instances(0).len(), 1);
+        assert_eq!(scene.instances(1).len(), 1);
+        assert_eq!(scene.objects_on_layer(0).len(), 2);
+
+        let nodes = scene.to_nodes().unwrap();
+        assert_eq!(nodes.len(), 6);
+        let scene = Scene::from_nodes(&nodes).unwrap();
+        assert_eq!(scene.objects().count(), 3);
+    }
+
+    #[test]
+    fn it_edits_the_scene() {
+        let mut scene = scene();
+        let root = scene.root();
+        let [a, b] = match scene.get(root).unwrap().children() {
+            [a, b] => [*a, *b],
+            children => panic!("Unexpected children: {:?}", children),
+        };
+
+        let group = scene.add_group(root).unwrap();
+        scene.get_mut(group).unwrap().set_name("group");
+        scene.reparent(a, group).unwrap();
+        assert!(matches!(
+            scene.reparent(group, a),
+            Err(SceneError::NotAGroup(_))
+        ));
+        assert!(matches!(
+            scene.reparent(group, group),
+            Err(SceneError::InvalidParent { .. })
+        ));
+
+        let instance = scene.add_shape(group, 1).unwrap();
+        assert_eq!(scene.instances(1).len(), 2);
+        assert!(scene.instances(1).contains(&instance));
+
+        scene.remove(b).unwrap();
+        let mut scene = Scene::from_nodes(&scene.to_nodes().unwrap()).unwrap();
+        let group = scene.find_by_name("group").unwrap();
+        assert_eq!(scene.get(group).unwrap().children().len(), 2);
+        assert_eq!(scene.objects().count(), 4);
+        assert!(matches!(scene.remove(scene.root()), Err(SceneError::Root)));
+    }
+
+    #[test]
+    fn it_combines_poses() {
+        let mut scene = Scene::new();
+        let group = scene.add_group(scene.root()).unwrap();
+        let shape = scene.add_shape(group, 0).unwrap();
+
+        let rotation = Rotation::from_matrix([[0, -1, 0], [1, 0, 0], [0, 0, 1]]).unwrap();
+        let frame = &mut scene.get_mut(group).unwrap().transform.frames[0];
+        frame.set_translation(Vector::new(10, 0, 0));
+        frame.set_rotation(rotation);
+        let frame = &mut scene.get_mut(shape).unwrap().transform.frames[0];
+        frame.set_translation(Vector::new(1, 2, 3));
+
+        let pose = scene.world_pose(shape, 0).unwrap();
+        assert_eq!(pose.translation, Vector::new(8, 1, 3));
+        assert_eq!(pose.rotation, rotation);
+    }
+
+    #[test]
+    fn it_handles_deeply_nested_scenes() {
+        // Deep enough to overflow the stack, if we recursed for each level.
+        let mut scene = Scene::new();
+        let mut parent = scene.root();
+        for _ in 0..100_000 {
+            parent = scene.add_group(parent).unwrap();
+        }
+        scene.add_shape(parent, 0).unwrap();
+
+        let nodes = scene.to_nodes().unwrap();
+        assert_eq!(nodes.len(), 2 * 100_002);
+        match &nodes[3] {
+            Node::Group(group) => assert_eq!(group.children, [4]),
+            node => panic!("Expected group, but got: {:?}", node),
+        }
+
+        let scene = Scene::from_nodes(&nodes).unwrap();
+        assert_eq!(scene.objects().count(), 100_002);
+
+        let mut copy = Scene::new();
+        let root = copy.root();
+        let mut num_copied = 0;
+        copy.copy_from(root, &scene, scene.root(), &mut |_| num_copied += 1)
+            .unwrap();
+        assert_eq!(num_copied, 100_002);
+        // The copy of the root group is a child of `copy`'s root.
+        assert_eq!(copy.objects().count(), 100_003);
+        assert_eq!(copy.instances(0).len(), 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! Splitting a file into one file per object.
+//!
+//! This is the opposite of [`crate::merge`]: [`split`] creates a file for each
+//! shape, or for each object directly below the root. Each file contains the
+//! object and its descendants, and the models they display. The palette,
+//! materials and layers are copied to every file. The object's translation is
+//! reset, so that it's placed at the origin, but it keeps the rotations of its
+//! ancestors, so that it's oriented like in the original file.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{split::{split, SplitMode}, types::{Model, Vector}, VoxData};
+//! let mut kit = VoxData::default();
+//! for _ in 0..3 {
+//!     kit.models.push(Model {
+//!         size: Vector::new(1, 1, 1),
+//!         voxels: vec![],
+//!     });
+//! }
+//!
+//! let parts = split(&kit, SplitMode::Shapes).unwrap();
+//! assert_eq!(parts.len(), 3);
+//! assert_eq!(parts[0].vox.models.len(), 1);
+//! ```
+
+use std::collections::{
+    HashMap,
+    HashSet,
+};
+
+use thiserror::Error;
+
+use crate::{
+    animation::AnimationError,
+    data::VoxData,
+    scene::{
+        Content,
+        ObjectId,
+        Scene,
+        SceneError,
+        WorldPoseError,
+    },
+    types::Rotation,
+};
+
+/// Error type for [`split`].
+#[derive(Debug, Error)]
+pub enum SplitError {
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+
+    #[error("Shape refers to model {model_id}, which doesn't exist.")]
+    InvalidModelId { model_id: u32 },
+}
+
+impl From<WorldPoseError> for SplitError {
+    fn from(error: WorldPoseError) -> Self {
+        match error {
+            WorldPoseError::Scene(error) => Self::Scene(error),
+            WorldPoseError::Animation(error) => Self::Animation(error),
+        }
+    }
+}
+
+/// Specifies which objects [`split`] puts into their own file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SplitMode {
+    /// Every shape in the scene.
+    Shapes,
+
+    /// Every object directly below the root. Groups are kept together with
+    /// their descendants.
+    TopLevel,
+}
+
+/// A file created by [`split`].
+#[derive(Debug)]
+pub struct SplitPart {
+    /// The name of the object. This is its `_name` attribute, or `object-N`,
+    /// if it doesn't have a name. Names are made unique by appending `-N`.
+    pub name: String,
+
+    pub vox: VoxData,
+}
+
+/// Splits a file into one file per object. See the [module
+/// documentation](self).
+pub fn split(vox: &VoxData, mode: SplitMode) -> Result<Vec<SplitPart>, SplitError> {
+    let scene = vox.scene()?;
+
+    let objects = match mode {
+        SplitMode::Shapes => {
+            scene
+                .objects()
+                .filter(|(_, object)| object.shape().is_some())
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>()
+        }
+        SplitMode::TopLevel => {
+            scene
+                .get(scene.root())
+                .ok_or(SceneError::InvalidObject(scene.root()))?
+                .children()
+                .to_vec()
+        }
+    };
+
+    let mut names = HashSet::new();
+    objects
+        .into_iter()
+        .enumerate()
+        .map(|(i, id)| {
+            let object = scene.get(id).ok_or(SceneError::InvalidObject(id))?;
+            let name = unique_name(
+                &mut names,
+                object
+                    .name()
+                    .filter(|name| !name.is_empty())
+                    .map(ToOwned::to_owned)
+                    .unwrap_or_else(|| format!("object-{}", i)),
+            );
+
+            Ok(SplitPart {
+                name,
+                vox: extract(vox, &scene, id)?,
+            })
+        })
+        .collect()
+}
+
+/// Appends `-N` to `name`, if it is already in `names`.
+fn unique_name(names: &mut HashSet<String>, name: String) -> String {
+    let mut unique = name.clone();
+    let mut n = 2;
+    while names.contains(&unique) {
+        unique = format!("{}-{}", name, n);
+        n += 1;
+    }
+    names.insert(unique.clone());
+    unique
+}
+
+/// Creates a file that contains only the object `id` and its descendants.
+fn extract(vox: &VoxData, scene: &Scene, id: ObjectId) -> Result<VoxData, SplitError> {
+    let mut part = VoxData {
+        version: vox.version,
+        palette: vox.palette.clone(),
+        materials: vox.materials.clone(),
+        material_properties: vox.material_properties.clone(),
+        layers: vox.layers.clone(),
+        ..Default::default()
+    };
+
+    // Copy the models in the order they are used, and renumber them.
+    let mut model_ids = HashMap::new();
+    let mut invalid_model_id = None;
+    let mut part_scene = Scene::new();
+    let copy = part_scene.copy_from(part_scene.root(), scene, id, &mut |object| {
+        if let Content::Shape(shape) = &mut object.content {
+            for model in &mut shape.models {
+                let model_id = model.model_id;
+                model.model_id = *model_ids.entry(model_id).or_insert_with(|| {
+                    if let Some(model) = vox.models.get(model_id as usize) {
+                        part.models.push(model.clone());
+                        part.models.len() as u32 - 1
+                    }
+                    else {
+                        invalid_model_id = Some(model_id);
+                        0
+                    }
+                });
+            }
+        }
+    })?;
+    if let Some(model_id) = invalid_model_id {
+        return Err(SplitError::InvalidModelId { model_id });
+    }
+
+    // The copy is placed directly below the root, so its rotation must include the
+    // rotations of its ancestors.
+    let parent_rotation = match scene.get(id).and_then(|object| object.parent()) {
+        Some(parent) => scene.world_pose(parent, 0)?.rotation,
+        None => Rotation::IDENTITY,
+    };
+    let object = part_scene.get_mut(copy).expect("object was just copied");
+    for frame in &mut object.transform.frames {
+        let rotation = parent_rotation
+            * frame
+                .rotation()
+                .map_err(AnimationError::from)?
+                .unwrap_or_default();
+        if rotation == Rotation::IDENTITY {
+            frame.remove("_r");
+        }
+        else {
+            frame.set_rotation(rotation);
+        }
+        frame.remove("_t");
+    }
+
+    part.set_scene(&part_scene)?;
+
+    Ok(part)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        split,
+        SplitMode,
+    };
+    use crate::{
+        from_slice,
+        types::{
+            Rotation,
+            Vector,
+        },
+    };
+
+    #[test]
+    fn it_splits_files() {
+        let mut vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap();
+
+        let mut scene = vox.scene().unwrap();
+        let root = scene.root();
+        let children = scene.get(root).unwrap().children().to_vec();
+        scene.get_mut(children[0]).unwrap().set_name("rock");
+        scene.get_mut(children[1]).unwrap().set_name("rock");
+        let group = scene.add_group(root).unwrap();
+        scene.reparent(children[1], group).unwrap();
+        let model_id = scene.get(children[1]).unwrap().shape().unwrap().models[0].model_id;
+        scene.add_shape(group, model_id).unwrap();
+        let rotation = Rotation::from_matrix([[0, -1, 0], [1, 0, 0], [0, 0, 1]]).unwrap();
+        scene.get_mut(group).unwrap().transform.frames[0].set_rotation(rotation);
+        vox.set_scene(&scene).unwrap();
+
+        let parts = split(&vox, SplitMode::Shapes).unwrap();
+        let names = parts.iter().map(|part| &part.name[..]).collect::<Vec<_>>();
+        assert_eq!(names, ["rock", "rock-2", "object-2"]);
+        for part in &parts {
+            assert_eq!(part.vox.models.len(), 1);
+            assert!(part.vox.palette.colors == vox.palette.colors);
+            assert_eq!(part.vox.layers.len(), vox.layers.len());
+
+            let scene = part.vox.scene().unwrap();
+            let object = scene.get(scene.root()).unwrap().children()[0];
+            let pose = scene.world_pose(object, 0).unwrap();
+            assert_eq!(pose.translation, Vector::new(0, 0, 0));
+        }
+        // The shapes in the group keep the group's rotation.
+        let rotations = parts
+            .iter()
+            .map(|part| {
+                let scene = part.vox.scene().unwrap();
+                let object = scene.get(scene.root()).unwrap().children()[0];
+                scene.world_pose(object, 0).unwrap().rotation
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rotations, [Rotation::IDENTITY, rotation, rotation]);
+        assert_eq!(
+            parts[1].vox.models[0].voxels,
+            vox.models[model_id as usize].voxels
+        );
+
+        let parts = split(&vox, SplitMode::TopLevel).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].name, "object-1");
+        // Both shapes in the group display the same model, so it's only copied once.
+        assert_eq!(parts[1].vox.models.len(), 1);
+    }
+}
@@ -0,0 +1,331 @@
+//! Volumes that are larger than a single model.
+//!
+//! Models can be at most 256 voxels large on each axis. [`tile`] cuts a
+//! [`Volume`] of any size into tiles that each fit into a model, and places
+//! them with transform nodes, so that MagicaVoxel shows them at their original
+//! position. [`untile`] reassembles a volume from such a file, or from any
+//! other file.
+//!
+//! MagicaVoxel places a model's [pivot](Model::pivot), i.e. its center, at the
+//! translation of its transform node. Tiles are aligned to multiples of the
+//! tile size, and each model is only as large as necessary to contain the
+//! voxels of its tile.
+//!
+//! # Example
+//!
+//! ```
+//! # use vox_format::{tiling::{tile, untile, Volume}, types::{ColorIndex, Vector}};
+//! let mut volume = Volume::new();
+//! for x in -300..300 {
+//!     volume.insert(Vector::new(x, 0, 0), ColorIndex(1));
+//! }
+//!
+//! let vox = tile(&volume);
+//! assert_eq!(vox.models.len(), 4);
+//!
+//! let reassembled = untile(&vox).unwrap();
+//! assert_eq!(reassembled.len(), 600);
+//! assert_eq!(
+//!     reassembled.get(Vector::new(-300, 0, 0)),
+//!     Some(ColorIndex(1))
+//! );
+//! ```
+
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    iter::FromIterator,
+};
+
+use thiserror::Error;
+
+use crate::{
+    animation::AnimationError,
+    data::VoxData,
+    scene::{
+        for_each_voxel,
+        ForEachVoxelError,
+        Scene,
+        SceneError,
+    },
+    types::{
+        ColorIndex,
+        Model,
+        Vector,
+        Voxel,
+    },
+};
+
+/// The maximum size of a model on each axis.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+/// Error type for [`tile_with_size`] and [`untile`].
+#[derive(Debug, Error)]
+pub enum TileError {
+    #[error("Tile size must be between 1 and {}, but is {0}.", MAX_MODEL_SIZE)]
+    InvalidTileSize(u32),
+
+    #[error("Shape refers to model {model_id}, which doesn't exist.")]
+    InvalidModelId { model_id: u32 },
+
+    #[error("{0}")]
+    Scene(#[from] SceneError),
+
+    #[error("{0}")]
+    Animation(#[from] AnimationError),
+}
+
+impl From<ForEachVoxelError> for TileError {
+    fn from(e: ForEachVoxelError) -> Self {
+        match e {
+            ForEachVoxelError::InvalidModelId { model_id } => Self::InvalidModelId { model_id },
+            ForEachVoxelError::Scene(e) => e.into(),
+            ForEachVoxelError::Animation(e) => e.into(),
+        }
+    }
+}
+
+/// A sparse volume of voxels with `i32` coordinates.
+#[derive(Clone, Debug, Default)]
+pub struct Volume {
+    voxels: HashMap<Vector<i32>, ColorIndex>,
+}
+
+impl Volume {
+    /// Creates an empty volume.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the voxel at `point`. Returns the color the voxel had before.
+    pub fn insert(&mut self, point: Vector<i32>, color_index: ColorIndex) -> Option<ColorIndex> {
+        self.voxels.insert(point, color_index)
+    }
+
+    /// Removes the voxel at `point`.
+    pub fn remove(&mut self, point: Vector<i32>) -> Option<ColorIndex> {
+        self.voxels.remove(&point)
+    }
+
+    /// Returns the color of the voxel at `point`.
+    pub fn get(&self, point: Vector<i32>) -> Option<ColorIndex> {
+        self.voxels.get(&point).copied()
+    }
+
+    /// Returns the number of voxels.
+    pub fn len(&self) -> usize {
+        self.voxels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voxels.is_empty()
+    }
+
+    /// Creates an iterator over all voxels in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Vector<i32>, ColorIndex)> + '_ {
+        self.voxels
+            .iter()
+            .map(|(point, color_index)| (*point, *color_index))
+    }
+
+    /// Returns the smallest and largest coordinates of the voxels, or `None`,
+    /// if the volume is empty.
+    pub fn bounds(&self) -> Option<(Vector<i32>, Vector<i32>)> {
+        let mut points = self.voxels.keys();
+        let first = *points.next()?;
+        Some(points.fold((first, first), |(min, max), p| {
+            (
+                Vector::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                Vector::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)),
+            )
+        }))
+    }
+}
+
+impl FromIterator<(Vector<i32>, ColorIndex)> for Volume {
+    fn from_iter<I: IntoIterator<Item = (Vector<i32>, ColorIndex)>>(iter: I) -> Self {
+        Self {
+            voxels: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<(Vector<i32>, ColorIndex)> for Volume {
+    fn extend<I: IntoIterator<Item = (Vector<i32>, ColorIndex)>>(&mut self, iter: I) {
+        self.voxels.extend(iter)
+    }
+}
+
+/// Cuts a volume into tiles of at most 256³ voxels. See the [module
+/// documentation](self).
+///
+/// The file has the default palette. Each tile's transform is named
+/// `tile X Y Z`, where `X`, `Y` and `Z` is the tile's position in the grid of
+/// tiles.
+pub fn tile(volume: &Volume) -> VoxData {
+    tile_with_size(volume, MAX_MODEL_SIZE).expect("maximum model size is a valid tile size")
+}
+
+/// Cuts a volume into tiles of at most `tile_size` voxels on each axis. See
+/// [`tile`].
+pub fn tile_with_size(volume: &Volume, tile_size: u32) -> Result<VoxData, TileError> {
+    if tile_size == 0 || tile_size > MAX_MODEL_SIZE {
+        return Err(TileError::InvalidTileSize(tile_size));
+    }
+    let tile_size = tile_size as i32;
+
+    let mut tiles = BTreeMap::<_, Vec<Voxel>>::new();
+    for (point, color_index) in volume.iter() {
+        let tile = Vector::new(
+            point.x.div_euclid(tile_size),
+            point.y.div_euclid(tile_size),
+            point.z.div_euclid(tile_size),
+        );
+        let local = Vector::new(
+            point.x.rem_euclid(tile_size) as u8 as i8,
+            point.y.rem_euclid(tile_size) as u8 as i8,
+            point.z.rem_euclid(tile_size) as u8 as i8,
+        );
+        tiles.entry(tile).or_default().push(Voxel {
+            point: local,
+            color_index,
+        });
+    }
+
+    let mut vox = VoxData::default();
+    let mut scene = Scene::new();
+    let root = scene.root();
+
+    for (tile, mut voxels) in tiles {
+        voxels.sort_by_key(|voxel| {
+            let point = unsigned(voxel.point);
+            (point.z, point.y, point.x)
+        });
+        let size = voxels.iter().fold(Vector::new(1, 1, 1), |size, voxel| {
+            let point = unsigned(voxel.point);
+            Vector::new(
+                size.x.max(point.x as u32 + 1),
+                size.y.max(point.y as u32 + 1),
+                size.z.max(point.z as u32 + 1),
+            )
+        });
+        let model = Model { size, voxels };
+
+        let origin = Vector::new(tile.x * tile_size, tile.y * tile_size, tile.z * tile_size);
+        let pivot = model.pivot();
+
+        let id = scene.add_shape(root, vox.models.len() as u32)?;
+        let object = scene.get_mut(id).expect("object was just added");
+        object.set_name(format!("tile {} {} {}", tile.x, tile.y, tile.z));
+        object.transform.frames[0].set_translation(Vector::new(
+            origin.x + pivot.x,
+            origin.y + pivot.y,
+            origin.z + pivot.z,
+        ));
+
+        vox.models.push(model);
+    }
+
+    vox.set_scene(&scene)?;
+
+    Ok(vox)
+}
+
+/// Reassembles a volume from all shapes in a file. Each shape's voxels are
+/// placed as shown by MagicaVoxel at frame 0. If shapes overlap, the voxels of
+/// the shape that comes later in the scene graph are kept.
+pub fn untile(vox: &VoxData) -> Result<Volume, TileError> {
+    let mut volume = Volume::new();
+    for_each_voxel(vox, 0, |_, point, color_index| {
+        volume.insert(point, color_index);
+    })?;
+    Ok(volume)
+}
+
+/// Returns the coordinates of a voxel in a model. These are stored as `i8`,
+/// but models can be up to 256 voxels large.
+fn unsigned(point: Vector<i8>) -> Vector<u8> {
+    Vector::new(point.x as u8, point.y as u8, point.z as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        tile,
+        tile_with_size,
+        untile,
+        TileError,
+        Volume,
+    };
+    use crate::{
+        from_slice,
+        to_vec,
+        types::{
+            ColorIndex,
+            Vector,
+        },
+    };
+
+    #[test]
+    fn it_round_trips_tiled_volumes() {
+        let volume = (-300i32..300)
+            .step_by(7)
+            .flat_map(|x| {
+                (0..3).map(move |y| {
+                    (
+                        Vector::new(x, y * 200, x / 3 - 1000),
+                        ColorIndex((x.rem_euclid(255) + 1) as u8),
+                    )
+                })
+            })
+            .collect::<Volume>();
+
+        let vox = tile(&volume);
+        assert!(vox
+            .models
+            .iter()
+            .all(|model| model.size.x <= 256 && model.size.y <= 256 && model.size.z <= 256));
+
+        // Read the file back, to make sure the coordinates survive.
+        let vox = from_slice(&to_vec(&vox).unwrap()).unwrap();
+        let reassembled = untile(&vox).unwrap();
+        assert_eq!(reassembled.len(), volume.len());
+        for (point, color_index) in volume.iter() {
+            assert_eq!(reassembled.get(point), Some(color_index), "{:?}", point);
+        }
+        assert_eq!(reassembled.bounds(), volume.bounds());
+    }
+
+    #[test]
+    fn it_tiles_with_smaller_tiles() {
+        let volume = (0..10)
+            .map(|x| (Vector::new(x, 0, 0), ColorIndex(1)))
+            .collect::<Volume>();
+        let vox = tile_with_size(&volume, 4).unwrap();
+        assert_eq!(vox.models.len(), 3);
+        assert_eq!(vox.models[2].size, Vector::new(2, 1, 1));
+        assert_eq!(untile(&vox).unwrap().len(), 10);
+
+        assert!(matches!(
+            tile_with_size(&volume, 257),
+            Err(TileError::InvalidTileSize(257))
+        ));
+    }
+
+    #[test]
+    fn it_untiles_scenes() {
+        let vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap();
+        let num_voxels = vox
+            .models
+            .iter()
+            .map(|model| model.voxels.len())
+            .sum::<usize>();
+        assert_eq!(untile(&vox).unwrap().len(), num_voxels);
+    }
+}
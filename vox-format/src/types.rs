@@ -11,7 +11,11 @@ use std::{
         Read,
         Write,
     },
-    ops::Index,
+    iter::FromIterator,
+    ops::{
+        Index,
+        Mul,
+    },
 };
 
 use byteorder::{
@@ -19,6 +23,7 @@ use byteorder::{
     WriteBytesExt,
     LE,
 };
+use indexmap::IndexMap;
 #[cfg(feature = "serialize")]
 use serde::{
     Deserialize,
@@ -27,8 +32,14 @@ use serde::{
 use thiserror::Error;
 
 use crate::{
+    chunk::ChunkId,
     default_palette::DEFAULT_PALETTE,
-    reader::Error as ReadError,
+    reader::{
+        Error as ReadError,
+        LimitKind,
+        Limits,
+        ResultExt,
+    },
     writer::Error as WriteError,
 };
 
@@ -43,15 +54,22 @@ use crate::{
 pub struct Version(pub u32);
 
 impl Version {
+    /// The legacy version, which stores materials in `MATT` chunks. This is
+    /// still written by Goxel and older MagicaVoxel builds.
+    pub const V150: Self = Self(150);
+
+    /// The current version.
+    pub const V200: Self = Self(200);
+
     /// Returns whether this version is supported.
     pub fn is_supported(&self) -> bool {
-        *self == Self::default()
+        matches!(*self, Self::V150 | Self::V200)
     }
 }
 
 impl Default for Version {
     fn default() -> Self {
-        Self(200)
+        Self::V200
     }
 }
 
@@ -97,6 +115,17 @@ impl Model {
     pub fn get_voxel(&self, point: Vector<i8>) -> Option<&Voxel> {
         self.voxels.iter().find(|voxel| voxel.point == point)
     }
+
+    /// Returns the model's pivot. This is the point that MagicaVoxel places
+    /// at the translation of the transform that displays the model, i.e. the
+    /// center of the model, rounded down.
+    pub fn pivot(&self) -> Vector<i32> {
+        Vector::new(
+            (self.size.x / 2) as i32,
+            (self.size.y / 2) as i32,
+            (self.size.z / 2) as i32,
+        )
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -261,7 +290,7 @@ impl Palette {
     ///     println!("{} -> {:?}", index, color);
     /// }
     /// ```
-    pub fn iter(&self) -> PaletteIter {
+    pub fn iter(&self) -> PaletteIter<'_> {
         PaletteIter {
             inner: self.colors.iter().enumerate(),
         }
@@ -272,7 +301,7 @@ impl Palette {
         let mut palette = Palette::default();
 
         for i in 0..255 {
-            palette.colors[i + 1] = Color::read(&mut reader)?;
+            palette.colors[i + 1] = Color::read(&mut reader).field("palette color")?;
         }
 
         Ok(palette)
@@ -359,11 +388,17 @@ impl MaterialPalette {
     ///
     /// This interface his likely to change in the future and is not fully
     /// implemented yet.
-    pub fn iter(&self) -> MaterialPaletteIter {
+    pub fn iter(&self) -> MaterialPaletteIter<'_> {
         MaterialPaletteIter {
             inner: self.materials.iter(),
         }
     }
+
+    /// Inserts a material. Returns the material that was previously stored
+    /// with this ID.
+    pub fn insert(&mut self, material_id: ColorIndex, material: Material) -> Option<Material> {
+        self.materials.insert(material_id, material)
+    }
 }
 
 /// An iterator over entries in a [`MaterialPalette`]. This is created with
@@ -537,31 +572,38 @@ pub struct Material {
 impl Material {
     /// Reads a material definition from a [`std::io::Read`].
     pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
-        let ty = MaterialType::read(&mut reader)?;
-        let weight = reader.read_f32::<LE>()?;
-        let flags = reader.read_u32::<LE>()?;
+        let ty = MaterialType::read(&mut reader).field("material type")?;
+        let weight = reader.read_f32::<LE>().field("material weight")?;
+        let flags = reader.read_u32::<LE>().field("material flags")?;
 
         let plastic = (flags & 1 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let roughness = (flags & 2 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let specular = (flags & 4 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let ior = (flags & 8 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let attenuation = (flags & 16 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let power = (flags & 32 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
         let glow = (flags & 64 != 0)
             .then(|| reader.read_f32::<LE>())
-            .transpose()?;
+            .transpose()
+            .field("material property")?;
 
         Ok(Material {
             ty,
@@ -576,6 +618,59 @@ impl Material {
             is_total_power: (flags & 128 != 0),
         })
     }
+
+    /// Writes the material definition to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        let properties = [
+            self.plastic,
+            self.roughness,
+            self.specular,
+            self.ior,
+            self.attenuation,
+            self.power,
+            self.glow,
+        ];
+
+        let mut flags = 0u32;
+        for (bit, property) in properties.iter().enumerate() {
+            if property.is_some() {
+                flags |= 1 << bit;
+            }
+        }
+        if self.is_total_power {
+            flags |= 128;
+        }
+
+        self.ty.write(&mut writer)?;
+        writer.write_f32::<LE>(self.weight)?;
+        writer.write_u32::<LE>(flags)?;
+        for value in properties.iter().flatten() {
+            writer.write_f32::<LE>(*value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a legacy `MATT` chunk, which contains the material ID followed by
+    /// the material definition.
+    pub fn read_matt<R: Read>(mut reader: R) -> Result<(ColorIndex, Self), ReadError> {
+        let material_id = reader.read_u32::<LE>().field("material ID")?;
+        let material_id = u8::try_from(material_id)
+            .ok()
+            .filter(|id| *id != 0)
+            .ok_or(ReadError::InvalidMaterialId { material_id })?;
+        Ok((ColorIndex(material_id), Self::read(reader)?))
+    }
+
+    /// Writes a legacy `MATT` chunk.
+    pub fn write_matt<W: Write>(
+        &self,
+        material_id: ColorIndex,
+        mut writer: W,
+    ) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(material_id.0.into())?;
+        self.write(writer)
+    }
 }
 
 /// A material type.
@@ -621,14 +716,15 @@ impl From<MaterialType> for u8 {
 impl MaterialType {
     /// Reads a material type from a [`std::io::Read`].
     pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
-        reader
-            .read_u8()?
-            .try_into()
-            .map_err(|e: MaterialTryFromError| ReadError::InvalidMaterial { material_type: e.0 })
+        let material_type = reader.read_u32::<LE>()?;
+        u8::try_from(material_type)
+            .ok()
+            .and_then(|ty| ty.try_into().ok())
+            .ok_or(ReadError::InvalidMaterial { material_type })
     }
 
     pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
-        writer.write_u8((*self).into())?;
+        writer.write_u32::<LE>(u8::from(*self).into())?;
         Ok(())
     }
 }
@@ -656,17 +752,24 @@ pub struct Transform {
 
 impl Transform {
     /// Reads a transform node from a [`std::io::Read`].
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
-        let node_id = reader.read_u32::<LE>()?;
-        let attributes = Attributes::read(&mut reader)?;
-        let child_node_id = reader.read_u32::<LE>()?;
-        let reserved_id = read_id_opt(&mut reader)?;
-        let layer_id = read_id_opt(&mut reader)?;
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads a transform node from a [`std::io::Read`], enforcing `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
+        let node_id = reader.read_u32::<LE>().field("node ID")?;
+        let attributes =
+            Attributes::read_with_limits(&mut reader, limits).field("node attributes")?;
+        let child_node_id = reader.read_u32::<LE>().field("child node ID")?;
+        let reserved_id = read_id_opt(&mut reader).field("reserved ID")?;
+        let layer_id = read_id_opt(&mut reader).field("layer ID")?;
 
-        let num_frames = reader.read_u32::<LE>()?;
+        let num_frames = reader.read_u32::<LE>().field("number of frames")?;
         let mut frames = vec![];
         for _ in 0..num_frames {
-            frames.push(Attributes::read(&mut reader)?);
+            frames
+                .push(Attributes::read_with_limits(&mut reader, limits).field("frame attributes")?);
         }
 
         Ok(Self {
@@ -679,13 +782,72 @@ impl Transform {
         })
     }
 
+    /// Writes the transform node to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.node_id)?;
+        self.attributes.write(&mut writer)?;
+        writer.write_u32::<LE>(self.child_node_id)?;
+        write_id_opt(&mut writer, self.reserved_id)?;
+        write_id_opt(&mut writer, self.layer_id)?;
+        writer.write_u32::<LE>(self.frames.len().try_into()?)?;
+        for frame in &self.frames {
+            frame.write(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the translation of the frame at index `frame`. Returns `None`,
+    /// if the frame doesn't exist, or its translation is missing or invalid.
     pub fn get_transform(&self, frame: usize) -> Option<Vector<i32>> {
-        let mut parts = self.frames.get(frame)?.get("_t")?.split_whitespace();
-        let x = parts.next()?.parse().ok()?;
-        let y = parts.next()?.parse().ok()?;
-        let z = parts.next()?.parse().ok()?;
+        self.frames.get(frame)?.translation().ok().flatten()
+    }
+}
 
-        parts.next().is_none().then(|| Vector::new(x, y, z))
+/// A node in the scene graph.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub enum Node {
+    Transform(Transform),
+    Group(Group),
+    Shape(Shape),
+}
+
+impl Node {
+    /// Returns the ID of the node.
+    pub fn node_id(&self) -> u32 {
+        match self {
+            Node::Transform(transform) => transform.node_id,
+            Node::Group(group) => group.node_id,
+            Node::Shape(shape) => shape.node_id,
+        }
+    }
+
+    /// Returns the node's attributes.
+    pub fn attributes(&self) -> &Attributes {
+        match self {
+            Node::Transform(transform) => &transform.attributes,
+            Node::Group(group) => &group.attributes,
+            Node::Shape(shape) => &shape.attributes,
+        }
+    }
+
+    /// Returns the ID of the chunk that stores this kind of node.
+    pub fn chunk_id(&self) -> ChunkId {
+        match self {
+            Node::Transform(_) => ChunkId::NTrn,
+            Node::Group(_) => ChunkId::NGrp,
+            Node::Shape(_) => ChunkId::NShp,
+        }
+    }
+
+    /// Writes the node to a [`std::io::Write`]. This writes only the chunk's
+    /// contents. Use [`Node::chunk_id`] to get the chunk ID.
+    pub fn write<W: Write>(&self, writer: W) -> Result<(), WriteError> {
+        match self {
+            Node::Transform(transform) => transform.write(writer),
+            Node::Group(group) => group.write(writer),
+            Node::Shape(shape) => shape.write(writer),
+        }
     }
 }
 
@@ -705,14 +867,22 @@ pub struct Group {
 
 impl Group {
     /// Reads a group from a [`std::io::Read`].
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
-        let node_id = reader.read_u32::<LE>()?;
-        let attributes = Attributes::read(&mut reader)?;
-        let num_children = reader.read_u32::<LE>()?;
-        let mut children = Vec::with_capacity(num_children as usize);
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads a group from a [`std::io::Read`], enforcing `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
+        let node_id = reader.read_u32::<LE>().field("node ID")?;
+        let attributes =
+            Attributes::read_with_limits(&mut reader, limits).field("node attributes")?;
+        let num_children = reader.read_u32::<LE>().field("number of children")?;
+
+        // Don't trust the number of children for allocating memory.
+        let mut children = Vec::with_capacity(num_children.min(1024) as usize);
 
         for _ in 0..num_children {
-            children.push(reader.read_u32::<LE>()?);
+            children.push(reader.read_u32::<LE>().field("child node ID")?);
         }
 
         Ok(Self {
@@ -721,6 +891,17 @@ impl Group {
             children,
         })
     }
+
+    /// Writes the group to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.node_id)?;
+        self.attributes.write(&mut writer)?;
+        writer.write_u32::<LE>(self.children.len().try_into()?)?;
+        for child in &self.children {
+            writer.write_u32::<LE>(*child)?;
+        }
+        Ok(())
+    }
 }
 
 /// A shape node.
@@ -734,24 +915,75 @@ impl Group {
 pub struct Shape {
     pub node_id: u32,
     pub attributes: Attributes,
+
+    /// The models this shape displays. Usually this is a single model, but
+    /// animated shapes have one for each keyframe.
+    pub models: Vec<ShapeModel>,
 }
 
 impl Shape {
     /// Reads a shape node from a [`std::io::Read`].
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads a shape node from a [`std::io::Read`], enforcing `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
+        let node_id = reader.read_u32::<LE>().field("node ID")?;
+        let attributes =
+            Attributes::read_with_limits(&mut reader, limits).field("node attributes")?;
+        let num_models = reader.read_u32::<LE>().field("number of models")?;
+
+        // Don't trust the number of models for allocating memory.
+        let mut models = Vec::with_capacity(num_models.min(1024) as usize);
+
+        for _ in 0..num_models {
+            let model_id = reader.read_u32::<LE>().field("model ID")?;
+            let attributes =
+                Attributes::read_with_limits(&mut reader, limits).field("model attributes")?;
+            models.push(ShapeModel {
+                model_id,
+                attributes,
+            });
+        }
+
         Ok(Self {
-            node_id: reader.read_u32::<LE>()?,
-            attributes: Attributes::read(reader)?,
+            node_id,
+            attributes,
+            models,
         })
     }
+
+    /// Writes the shape node to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.node_id)?;
+        self.attributes.write(&mut writer)?;
+        writer.write_u32::<LE>(self.models.len().try_into()?)?;
+        for model in &self.models {
+            writer.write_u32::<LE>(model.model_id)?;
+            model.attributes.write(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// A model that is displayed by a [`Shape`].
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct ShapeModel {
+    /// The index of the model in the file.
+    pub model_id: u32,
+
+    /// The model's attributes. For animations this contains the frame index
+    /// (`_f`).
+    pub attributes: Attributes,
 }
 
 /// A layer node.
 ///
-/// # Work-in-Progress
-///
-/// This interface his likely to change in the future and is not fully
-/// implemented yet.
+/// Transform nodes refer to the layer they are on by the layer's `node_id`.
+/// The layer's name, visibility and color are stored in its attributes. See
+/// [`crate::layers`] for operations on the layers of a file.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
 pub struct Layer {
@@ -761,40 +993,157 @@ pub struct Layer {
 }
 
 impl Layer {
+    /// Creates a layer with the given ID and no attributes.
+    pub fn new(node_id: u32) -> Self {
+        Self {
+            node_id,
+            attributes: Attributes::new(),
+            reserved_id: None,
+        }
+    }
+
+    /// Returns the layer's name (`_name`).
+    pub fn name(&self) -> Option<&str> {
+        self.attributes.name()
+    }
+
+    /// Sets the layer's name (`_name`).
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.attributes.set_name(name);
+    }
+
+    /// Returns whether the layer is hidden (`_hidden`). Layers without this
+    /// attribute are visible.
+    pub fn is_hidden(&self) -> Result<bool, HiddenParseError> {
+        Ok(self.attributes.hidden()?.unwrap_or_default())
+    }
+
+    /// Sets whether the layer is hidden (`_hidden`).
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.attributes.set_hidden(hidden);
+    }
+
+    /// Returns the color that MagicaVoxel shows for the layer (`_color`).
+    pub fn color(&self) -> Result<Option<Color>, ColorParseError> {
+        self.attributes.color()
+    }
+
+    /// Sets the color that MagicaVoxel shows for the layer (`_color`).
+    pub fn set_color(&mut self, color: Color) {
+        self.attributes.set_color(color);
+    }
+
     /// Reads a layer node from a [`std::io::Read`].
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads a layer node from a [`std::io::Read`], enforcing `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
         Ok(Self {
-            node_id: reader.read_u32::<LE>()?,
-            attributes: Attributes::read(&mut reader)?,
-            reserved_id: read_id_opt(reader)?,
+            node_id: reader.read_u32::<LE>().field("layer ID")?,
+            attributes: Attributes::read_with_limits(&mut reader, limits)
+                .field("layer attributes")?,
+            reserved_id: read_id_opt(reader).field("reserved ID")?,
         })
     }
+
+    /// Writes the layer to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.node_id)?;
+        self.attributes.write(&mut writer)?;
+        write_id_opt(writer, self.reserved_id)
+    }
+}
+
+/// The properties of a material from a `MATL` chunk. Newer files use these
+/// instead of legacy [`Material`]s. The material with ID `i` belongs to the
+/// palette entry `i`.
+///
+/// The properties are stored as attributes: `_type` is one of `_diffuse`,
+/// `_metal`, `_glass`, `_emit`, `_blend` and `_media`. Depending on the type,
+/// there are numeric properties, such as `_rough` (roughness), `_metal`
+/// (metalness), `_ior` (index of refraction minus one), `_trans`
+/// (transparency), `_emit` (emission) and `_flux` (power of the emission).
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serialize", derive(Serialize, Deserialize))]
+pub struct MaterialProperties {
+    pub material_id: u32,
+    pub attributes: Attributes,
+}
+
+impl MaterialProperties {
+    /// Reads material properties from a [`std::io::Read`].
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads material properties from a [`std::io::Read`], enforcing
+    /// `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
+        Ok(Self {
+            material_id: reader.read_u32::<LE>().field("material ID")?,
+            attributes: Attributes::read_with_limits(reader, limits)
+                .field("material properties")?,
+        })
+    }
+
+    /// Writes the material properties to a [`std::io::Write`].
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.material_id)?;
+        self.attributes.write(writer)
+    }
+
+    /// Returns the material type (`_type`), e.g. `_metal`.
+    pub fn material_type(&self) -> Option<&str> {
+        self.attributes.get("_type")
+    }
+
+    /// Returns a numeric property, e.g. `_rough`. Returns `None`, if the
+    /// property is missing or isn't a number.
+    pub fn get_f32(&self, key: &str) -> Option<f32> {
+        self.attributes.get(key)?.parse().ok()
+    }
 }
 
 /// Node attributes. These contain meta-data for nodes, such as [`Transform`] or
 /// [`Layer`].
-#[derive(Clone, Debug, Default)]
+///
+/// This is a dictionary of strings, which keeps the order in which the
+/// attributes were read or inserted. MagicaVoxel uses a few well-known keys,
+/// for which there are typed accessors, e.g. [`Attributes::name`] for `_name`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[cfg_attr(
     feature = "serialize",
     derive(Serialize, Deserialize),
     serde(transparent)
 )]
 pub struct Attributes {
-    inner: HashMap<String, String>,
+    inner: IndexMap<String, String>,
 }
 
 impl Attributes {
+    /// Creates an empty set of attributes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     /// Reads attributes from a [`std::io::Read`].
-    pub fn read<R: Read>(mut reader: R) -> Result<Self, ReadError> {
+    pub fn read<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_with_limits(reader, &Limits::default())
+    }
+
+    /// Reads attributes from a [`std::io::Read`], enforcing `limits`.
+    pub fn read_with_limits<R: Read>(mut reader: R, limits: &Limits) -> Result<Self, ReadError> {
         // An array of key value pairs, where key and value are strings prefixed with
         // length as u32
 
-        let mut inner = HashMap::new();
-        let num_items = reader.read_u32::<LE>()?;
+        let mut inner = IndexMap::new();
+        let num_items = reader.read_u32::<LE>().field("number of attributes")?;
         log::trace!("Attributes::read: num_items={}", num_items);
         for _ in 0..num_items {
-            let key = Self::read_string(&mut reader)?;
-            let value = Self::read_string(&mut reader)?;
+            let key = Self::read_string(&mut reader, limits).field("attribute key string")?;
+            let value = Self::read_string(&mut reader, limits).field("attribute value string")?;
             log::trace!("Attributes::read: key={}, value={}", key, value);
             inner.insert(key, value);
         }
@@ -802,34 +1151,215 @@ impl Attributes {
         Ok(Attributes { inner })
     }
 
-    fn read_string<R: Read>(mut reader: R) -> Result<String, ReadError> {
+    fn read_string<R: Read>(mut reader: R, limits: &Limits) -> Result<String, ReadError> {
         let len = reader.read_u32::<LE>()?;
         log::trace!("Attributes::read_string: len={}", len);
-        let mut buf = vec![0; len.try_into().expect("int overflow")];
-        reader.read_exact(&mut buf)?;
+        Limits::check(
+            LimitKind::StringLength,
+            len.into(),
+            limits.max_string_len.into(),
+        )?;
+
+        // Don't allocate the buffer upfront, since `len` comes from the file. This way
+        // we only allocate as much as there actually is to read.
+        let mut buf = vec![];
+        (&mut reader).take(len.into()).read_to_end(&mut buf)?;
+        if buf.len() as u64 != u64::from(len) {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
         log::trace!("Attributes::read_string: buf={:?}", buf);
         Ok(String::from_utf8(buf)?)
     }
 
+    /// Writes the attributes to a [`std::io::Write`] in their current order.
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(self.inner.len().try_into()?)?;
+        for (key, value) in &self.inner {
+            Self::write_string(&mut writer, key)?;
+            Self::write_string(&mut writer, value)?;
+        }
+        Ok(())
+    }
+
+    fn write_string<W: Write>(mut writer: W, s: &str) -> Result<(), WriteError> {
+        writer.write_u32::<LE>(s.len().try_into()?)?;
+        writer.write_all(s.as_bytes())?;
+        Ok(())
+    }
+
     /// Returns the attribute with the given key, or `None`, if no such
     /// attribute exists.
     pub fn get(&self, key: impl AsRef<str>) -> Option<&str> {
         Some(self.inner.get(key.as_ref())?.as_str())
     }
 
+    /// Returns `true`, if an attribute with the given key exists.
+    pub fn contains_key(&self, key: impl AsRef<str>) -> bool {
+        self.inner.contains_key(key.as_ref())
+    }
+
+    /// Sets an attribute and returns its previous value. An existing
+    /// attribute keeps its position, and a new one is added at the end.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.inner.insert(key.into(), value.into())
+    }
+
+    /// Removes an attribute and returns its value. The order of the remaining
+    /// attributes is preserved.
+    pub fn remove(&mut self, key: impl AsRef<str>) -> Option<String> {
+        self.inner.shift_remove(key.as_ref())
+    }
+
+    /// Returns the number of attributes.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns `true`, if there are no attributes.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Sorts the attributes by key.
+    pub fn sort_keys(&mut self) {
+        self.inner.sort_keys();
+    }
+
     /// Creates an iterator over the attributes. The iterator returns items
     /// `(&str, &str)`.
-    pub fn iter(&self) -> AttributesIter {
+    pub fn iter(&self) -> AttributesIter<'_> {
         AttributesIter {
             inner: self.inner.iter(),
         }
     }
+
+    /// Returns the name (`_name`).
+    pub fn name(&self) -> Option<&str> {
+        self.get("_name")
+    }
+
+    /// Sets the name (`_name`).
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.insert("_name", name);
+    }
+
+    /// Returns whether the node or layer is hidden (`_hidden`).
+    pub fn hidden(&self) -> Result<Option<bool>, HiddenParseError> {
+        self.get("_hidden")
+            .map(|value| parse_bool(value).ok_or_else(|| HiddenParseError(value.to_owned())))
+            .transpose()
+    }
+
+    /// Sets whether the node or layer is hidden (`_hidden`).
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.insert("_hidden", format_bool(hidden));
+    }
+
+    /// Returns the translation of a transform frame (`_t`).
+    pub fn translation(&self) -> Result<Option<Vector<i32>>, TranslationParseError> {
+        self.get("_t")
+            .map(|value| parse_vector(value).ok_or_else(|| TranslationParseError(value.to_owned())))
+            .transpose()
+    }
+
+    /// Sets the translation of a transform frame (`_t`).
+    pub fn set_translation(&mut self, translation: Vector<i32>) {
+        self.insert(
+            "_t",
+            format!("{} {} {}", translation.x, translation.y, translation.z),
+        );
+    }
+
+    /// Returns the rotation of a transform frame (`_r`).
+    pub fn rotation(&self) -> Result<Option<Rotation>, RotationParseError> {
+        self.get("_r")
+            .map(|value| {
+                value
+                    .parse()
+                    .ok()
+                    .and_then(Rotation::new)
+                    .ok_or_else(|| RotationParseError(value.to_owned()))
+            })
+            .transpose()
+    }
+
+    /// Sets the rotation of a transform frame (`_r`).
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.insert("_r", rotation.to_byte().to_string());
+    }
+
+    /// Returns the frame index of an animation frame (`_f`).
+    pub fn frame_index(&self) -> Result<Option<u32>, FrameIndexParseError> {
+        self.get("_f")
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| FrameIndexParseError(value.to_owned()))
+            })
+            .transpose()
+    }
+
+    /// Sets the frame index of an animation frame (`_f`).
+    pub fn set_frame_index(&mut self, frame_index: u32) {
+        self.insert("_f", frame_index.to_string());
+    }
+
+    /// Returns whether the animation of a node loops (`_loop`).
+    pub fn looping(&self) -> Result<Option<bool>, LoopParseError> {
+        self.get("_loop")
+            .map(|value| parse_bool(value).ok_or_else(|| LoopParseError(value.to_owned())))
+            .transpose()
+    }
+
+    /// Sets whether the animation of a node loops (`_loop`).
+    pub fn set_looping(&mut self, looping: bool) {
+        self.insert("_loop", format_bool(looping));
+    }
+
+    /// Returns the color of a layer (`_color`). The color is stored without
+    /// alpha, so it's always opaque.
+    pub fn color(&self) -> Result<Option<Color>, ColorParseError> {
+        self.get("_color")
+            .map(|value| {
+                let mut parts = value.split_whitespace().map(str::parse::<u8>);
+                match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(r)), Some(Ok(g)), Some(Ok(b)), None) => Ok(Color { r, g, b, a: 255 }),
+                    _ => Err(ColorParseError(value.to_owned())),
+                }
+            })
+            .transpose()
+    }
+
+    /// Sets the color of a layer (`_color`). The alpha channel is ignored.
+    pub fn set_color(&mut self, color: Color) {
+        self.insert("_color", format!("{} {} {}", color.r, color.g, color.b));
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Attributes {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            inner: iter
+                .into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Attributes {
+    type Item = (&'a str, &'a str);
+    type IntoIter = AttributesIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 /// An interator over attributes. Created with [`Attributes::iter`].
 #[derive(Debug)]
 pub struct AttributesIter<'a> {
-    inner: std::collections::hash_map::Iter<'a, String, String>,
+    inner: indexmap::map::Iter<'a, String, String>,
 }
 
 impl<'a> Iterator for AttributesIter<'a> {
@@ -841,6 +1371,262 @@ impl<'a> Iterator for AttributesIter<'a> {
     }
 }
 
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+fn format_bool(value: bool) -> &'static str {
+    if value {
+        "1"
+    }
+    else {
+        "0"
+    }
+}
+
+fn parse_vector(value: &str) -> Option<Vector<i32>> {
+    let mut parts = value.split_whitespace();
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+
+    parts.next().is_none().then(|| Vector::new(x, y, z))
+}
+
+/// The `_hidden` attribute isn't `0` or `1`.
+#[derive(Debug, Error)]
+#[error("Invalid value for _hidden: {0:?}")]
+pub struct HiddenParseError(pub String);
+
+/// The `_t` attribute isn't three integers separated by spaces.
+#[derive(Debug, Error)]
+#[error("Invalid value for _t: {0:?}")]
+pub struct TranslationParseError(pub String);
+
+/// The `_r` attribute isn't a valid [`Rotation`].
+#[derive(Debug, Error)]
+#[error("Invalid value for _r: {0:?}")]
+pub struct RotationParseError(pub String);
+
+/// The `_f` attribute isn't a frame index.
+#[derive(Debug, Error)]
+#[error("Invalid value for _f: {0:?}")]
+pub struct FrameIndexParseError(pub String);
+
+/// The `_loop` attribute isn't `0` or `1`.
+#[derive(Debug, Error)]
+#[error("Invalid value for _loop: {0:?}")]
+pub struct LoopParseError(pub String);
+
+/// The `_color` attribute isn't three 8-bit integers separated by spaces.
+#[derive(Debug, Error)]
+#[error("Invalid value for _color: {0:?}")]
+pub struct ColorParseError(pub String);
+
+/// A rotation, which is stored in the `_r` attribute of transform frames.
+///
+/// MagicaVoxel only supports rotations and reflections that map axes to axes.
+/// These are stored as a byte: Bits 0-1 and 2-3 contain the column of the
+/// non-zero entry in the first and second row of the rotation matrix, and bits
+/// 4-6 contain the signs of the non-zero entries in the three rows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serialize",
+    derive(Serialize, Deserialize),
+    serde(transparent)
+)]
+pub struct Rotation(u8);
+
+impl Rotation {
+    /// The rotation that doesn't rotate at all.
+    pub const IDENTITY: Self = Self(0b0000_0100);
+
+    /// Creates a rotation from its byte representation. Returns `None`, if
+    /// the byte doesn't encode a valid rotation.
+    pub fn new(byte: u8) -> Option<Self> {
+        let first = byte & 3;
+        let second = (byte >> 2) & 3;
+        (first < 3 && second < 3 && first != second && byte & 0x80 == 0).then_some(Self(byte))
+    }
+
+    /// Creates a rotation from a matrix. Returns `None`, if each row and column
+    /// doesn't contain exactly one entry that is `1` or `-1`.
+    pub fn from_matrix(matrix: [[i8; 3]; 3]) -> Option<Self> {
+        let mut byte = 0;
+        let mut columns = [false; 3];
+        for (i, row) in matrix.iter().enumerate() {
+            let mut non_zero = row.iter().enumerate().filter(|(_, x)| **x != 0);
+            let (column, value) = non_zero.next()?;
+            if non_zero.next().is_some() || !matches!(value, 1 | -1) || columns[column] {
+                return None;
+            }
+            columns[column] = true;
+            if i < 2 {
+                byte |= (column as u8) << (2 * i);
+            }
+            if *value < 0 {
+                byte |= 1 << (4 + i);
+            }
+        }
+        Some(Self(byte))
+    }
+
+    /// Returns the byte representation.
+    pub fn to_byte(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the rotation matrix.
+    pub fn to_matrix(self) -> [[i8; 3]; 3] {
+        let first = usize::from(self.0 & 3);
+        let second = usize::from((self.0 >> 2) & 3);
+        let columns = [first, second, 3 - first - second];
+
+        let mut matrix = [[0; 3]; 3];
+        for (i, column) in columns.iter().enumerate() {
+            matrix[i][*column] = if self.0 & (1 << (4 + i)) != 0 { -1 } else { 1 };
+        }
+        matrix
+    }
+
+    /// Rotates a vector.
+    pub fn apply(self, vector: Vector<i32>) -> Vector<i32> {
+        let v = [vector.x, vector.y, vector.z];
+        let m = self.to_matrix();
+        let row = |r: [i8; 3]| (0..3).map(|i| i32::from(r[i]) * v[i]).sum();
+        Vector::new(row(m[0]), row(m[1]), row(m[2]))
+    }
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mul for Rotation {
+    type Output = Rotation;
+
+    /// Composes two rotations. The result first applies `rhs` and then `self`.
+    fn mul(self, rhs: Rotation) -> Rotation {
+        let (a, b) = (self.to_matrix(), rhs.to_matrix());
+        let mut matrix = [[0; 3]; 3];
+        for (i, row) in matrix.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        Rotation::from_matrix(matrix).expect("product of rotations is a rotation")
+    }
+}
+
 fn read_id_opt<R: Read>(mut reader: R) -> Result<Option<u32>, ReadError> {
     Ok(reader.read_i32::<LE>()?.try_into().ok())
 }
+
+fn write_id_opt<W: Write>(mut writer: W, id: Option<u32>) -> Result<(), WriteError> {
+    match id {
+        Some(id) => writer.write_i32::<LE>(id.try_into()?)?,
+        None => writer.write_i32::<LE>(-1)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Attributes,
+        Color,
+        Rotation,
+        Vector,
+    };
+
+    #[test]
+    fn attributes_keep_their_order() {
+        let mut attributes = Attributes::new();
+        attributes.insert("b", "1");
+        attributes.insert("a", "2");
+        attributes.insert("c", "3");
+        attributes.insert("b", "4");
+        assert_eq!(attributes.remove("a").as_deref(), Some("2"));
+
+        let mut data = vec![];
+        attributes.write(&mut data).unwrap();
+        let attributes = Attributes::read(&data[..]).unwrap();
+        assert_eq!(
+            attributes.iter().collect::<Vec<_>>(),
+            [("b", "4"), ("c", "3")]
+        );
+    }
+
+    #[test]
+    fn typed_attributes() {
+        let mut attributes = Attributes::new();
+        attributes.set_name("tree");
+        attributes.set_hidden(true);
+        attributes.set_translation(Vector::new(1, -2, 3));
+        attributes.set_frame_index(7);
+        attributes.set_looping(false);
+        attributes.set_color(Color {
+            r: 1,
+            g: 2,
+            b: 3,
+            a: 0,
+        });
+
+        assert_eq!(attributes.name(), Some("tree"));
+        assert_eq!(attributes.hidden().unwrap(), Some(true));
+        assert_eq!(
+            attributes.translation().unwrap(),
+            Some(Vector::new(1, -2, 3))
+        );
+        assert_eq!(attributes.rotation().unwrap(), None);
+        assert_eq!(attributes.frame_index().unwrap(), Some(7));
+        assert_eq!(attributes.looping().unwrap(), Some(false));
+        assert_eq!(
+            attributes.color().unwrap(),
+            Some(Color {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 255
+            })
+        );
+
+        attributes.insert("_hidden", "yes");
+        attributes.insert("_t", "1 2");
+        attributes.insert("_r", "3");
+        attributes.insert("_f", "-1");
+        attributes.insert("_color", "1 2 256");
+        assert_eq!(attributes.hidden().unwrap_err().0, "yes");
+        assert_eq!(attributes.translation().unwrap_err().0, "1 2");
+        assert_eq!(attributes.rotation().unwrap_err().0, "3");
+        assert_eq!(attributes.frame_index().unwrap_err().0, "-1");
+        assert_eq!(attributes.color().unwrap_err().0, "1 2 256");
+    }
+
+    #[test]
+    fn rotations() {
+        assert_eq!(
+            Rotation::IDENTITY.to_matrix(),
+            [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
+        );
+
+        // 90 degrees around the z axis.
+        let matrix = [[0, -1, 0], [1, 0, 0], [0, 0, 1]];
+        let rotation = Rotation::from_matrix(matrix).unwrap();
+        assert_eq!(Rotation::new(rotation.to_byte()), Some(rotation));
+        assert_eq!(rotation.to_matrix(), matrix);
+        assert_eq!(rotation.apply(Vector::new(1, 2, 3)), Vector::new(-2, 1, 3));
+
+        assert_eq!(
+            Rotation::from_matrix([[1, 0, 0], [1, 0, 0], [0, 0, 1]]),
+            None
+        );
+        assert_eq!(Rotation::new(0b0000_0000), None);
+    }
+}
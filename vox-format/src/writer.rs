@@ -2,7 +2,8 @@
 
 use std::{
     convert::TryInto,
-    fs::OpenOptions,
+    fmt,
+    fs::File,
     io::{
         Cursor,
         Seek,
@@ -21,10 +22,23 @@ use crate::{
     chunk::{
         chunk_writer,
         ChunkId,
+        ChunkPath,
         ChunkWriter,
     },
+    custom::CustomChunk,
     data::VoxData,
-    types::Version,
+    types::{
+        Attributes,
+        Group,
+        Layer,
+        MaterialProperties,
+        Model,
+        Node,
+        Shape,
+        ShapeModel,
+        Transform,
+        Version,
+    },
 };
 
 /// Error type returned when writing fails.
@@ -41,6 +55,103 @@ pub enum Error {
     /// chunk-writer closure.
     #[error("Reader error")]
     Reader(#[from] crate::reader::Error),
+
+    /// The value of a custom chunk doesn't have the type its codec expects.
+    #[error("Value of custom chunk {chunk_id} has the wrong type for its codec.")]
+    InvalidCustomChunk { chunk_id: ChunkId },
+
+    /// A shape node refers to a model that isn't written.
+    #[error(
+        "Shape node {node_id} refers to model {model_id}, but there are only {num_models} models."
+    )]
+    InvalidModelId {
+        node_id: u32,
+        model_id: u32,
+        num_models: usize,
+    },
+
+    /// There are too many models to assign IDs to their scene graph nodes.
+    #[error("Node IDs for the scene graph overflowed")]
+    NodeIdOverflow,
+
+    /// The file would be written with a version that this crate can't write.
+    #[error("Unsupported file version: {version}")]
+    UnsupportedFileVersion { version: Version },
+
+    /// An error with information about what was being written. Use
+    /// [`Error::root`] to get the underlying error.
+    #[error("{source} ({context})")]
+    Context {
+        context: WriteContext,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Returns the underlying error without any [`Error::Context`].
+    pub fn root(&self) -> &Error {
+        let mut error = self;
+        while let Error::Context { source, .. } = error {
+            error = source;
+        }
+        error
+    }
+
+    /// Returns the context of this error, if it has any.
+    pub fn context(&self) -> Option<&WriteContext> {
+        match self {
+            Error::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    fn into_context(self) -> (WriteContext, Box<Error>) {
+        match self {
+            Error::Context { context, source } => (context, source),
+            error => (WriteContext::default(), Box::new(error)),
+        }
+    }
+
+    /// Adds a chunk to the start of the chunk path. This is called for each
+    /// chunk while the error propagates up the chunk tree.
+    pub(crate) fn in_chunk(self, chunk_id: ChunkId, index: usize) -> Self {
+        let (mut context, source) = self.into_context();
+        context.chunk_path.prepend(chunk_id, index);
+        Error::Context { context, source }
+    }
+
+    /// Adds the index of the model that was being written.
+    pub(crate) fn in_model(self, model_index: usize) -> Self {
+        let (mut context, source) = self.into_context();
+        context.model_index.get_or_insert(model_index);
+        Error::Context { context, source }
+    }
+}
+
+/// Information about what was being written when an error occured. See
+/// [`Error::Context`].
+#[derive(Clone, Debug, Default)]
+pub struct WriteContext {
+    /// Path of the chunk that was being written, e.g. `MAIN/XYZI#3`.
+    pub chunk_path: ChunkPath,
+
+    /// Index of the model that was being written.
+    pub model_index: Option<usize>,
+}
+
+impl fmt::Display for WriteContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        if !self.chunk_path.is_empty() {
+            write!(f, "in chunk {}", self.chunk_path)?;
+            separator = ", ";
+        }
+        if let Some(model_index) = self.model_index {
+            write!(f, "{}while writing model {}", separator, model_index)?;
+        }
+        Ok(())
+    }
 }
 
 /// Writes the file header for a VOX file.
@@ -62,12 +173,76 @@ pub fn main_chunk_writer<W: Write + Seek, F: FnMut(&mut ChunkWriter<W>) -> Resul
     chunk_writer(writer, ChunkId::Main, f)
 }
 
+/// Options that control the layout of written files.
+#[derive(Clone, Debug, Default)]
+pub struct WriterOptions {
+    /// The file version. If this is `None`, [`VoxData::version`] is used.
+    /// The scene graph and layers are written for every version, since
+    /// MagicaVoxel also writes them to version 150 files.
+    pub version: Option<Version>,
+
+    /// Write a `PACK` chunk with the number of models. MagicaVoxel doesn't
+    /// use it anymore, but some older tools expect it.
+    pub write_pack: bool,
+
+    /// Write the `RGBA` chunk, even if the palette is the default palette.
+    pub always_write_palette: bool,
+
+    /// Write a default scene graph, if [`VoxData::nodes`] is empty: A root
+    /// transform and group, and a transform and shape for each model. All
+    /// transforms are on layer 0, which is written as well, if
+    /// [`VoxData::layers`] is empty.
+    pub write_default_scene_graph: bool,
+
+    /// Write chunks in the order MagicaVoxel writes them: Models, scene graph,
+    /// layers, palette and materials. Custom chunks come last and are sorted
+    /// by chunk ID. Nodes and layers are sorted by ID and their attributes by
+    /// key. Otherwise the palette and materials follow the models, and
+    /// everything else is written in the order of [`VoxData`]'s fields.
+    pub canonical_order: bool,
+
+    /// Sort the voxels of each model by position, with the x coordinate
+    /// changing fastest.
+    pub sort_voxels: bool,
+}
+
+impl WriterOptions {
+    /// Options for canonical output, which is the same for the same content:
+    /// Voxels are sorted, chunks are written in canonical order, and the
+    /// palette is always written. Use [`VoxData::canonical_hash`] to compare
+    /// content without writing it.
+    pub fn canonical() -> Self {
+        Self {
+            always_write_palette: true,
+            canonical_order: true,
+            sort_voxels: true,
+            ..Self::default()
+        }
+    }
+}
+
 /// Writes [`crate::data::VoxData`] to a [`std::io::Write`].
+///
+/// The file is written with the version in [`VoxData::version`]. See
+/// [`WriterOptions::version`].
 pub fn to_writer<W: Write + Seek>(writer: W, vox: &VoxData) -> Result<(), Error> {
-    main_chunk_writer(writer, Version::default(), |chunk_writer| {
-        // Write PACK, if there is more than 1 model.
-        // FIXME: Apparently PACK is not used anymore.
-        if vox.models.len() > 1 {
+    to_writer_with_options(writer, vox, &WriterOptions::default())
+}
+
+/// Writes [`crate::data::VoxData`] to a [`std::io::Write`], with the layout
+/// specified by `options`.
+pub fn to_writer_with_options<W: Write + Seek>(
+    writer: W,
+    vox: &VoxData,
+    options: &WriterOptions,
+) -> Result<(), Error> {
+    let version = options.version.unwrap_or(vox.version);
+    if !version.is_supported() {
+        return Err(Error::UnsupportedFileVersion { version });
+    }
+
+    main_chunk_writer(writer, version, |chunk_writer| {
+        if options.write_pack {
             chunk_writer.child_content_writer(ChunkId::Pack, |writer| {
                 writer.write_u32::<LE>(vox.models.len().try_into()?)?;
                 Ok(())
@@ -75,45 +250,545 @@ pub fn to_writer<W: Write + Seek>(writer: W, vox: &VoxData) -> Result<(), Error>
         }
 
         // Write models
-        for model in &vox.models {
-            // Write SIZE chunk
-            chunk_writer.child_content_writer(ChunkId::Size, |writer| {
-                model.size.write(writer)?;
-                Ok(())
-            })?;
+        for (model_index, model) in vox.models.iter().enumerate() {
+            write_model(chunk_writer, model, options.sort_voxels)
+                .map_err(|e| e.in_model(model_index))?;
+        }
 
-            // Write XYZI chunk
-            chunk_writer.child_content_writer(ChunkId::Xyzi, |mut writer| {
-                writer.write_u32::<LE>(model.voxels.len().try_into()?)?;
-                for voxel in &model.voxels {
-                    voxel.write(&mut writer)?;
-                }
-                Ok(())
-            })?;
+        if options.canonical_order {
+            write_scene_graph(chunk_writer, vox, options)?;
+            write_palette_and_materials(chunk_writer, vox, options)?;
+
+            // The sort is stable, so chunks with the same ID keep their order.
+            let mut custom_chunks = vox.custom_chunks.iter().collect::<Vec<_>>();
+            custom_chunks.sort_by_key(|custom_chunk| <[u8; 4]>::from(custom_chunk.id()));
+            write_custom_chunks(chunk_writer, custom_chunks)?;
+        }
+        else {
+            write_palette_and_materials(chunk_writer, vox, options)?;
+            write_scene_graph(chunk_writer, vox, options)?;
+            write_custom_chunks(chunk_writer, &vox.custom_chunks)?;
         }
 
-        // Write palette
-        if !vox.palette.is_default() {
-            chunk_writer.child_content_writer(ChunkId::Rgba, |writer| {
-                vox.palette.write(writer)?;
-                Ok(())
-            })?;
+        Ok(())
+    })
+}
+
+/// Writes the `RGBA` chunk, if needed, and the `MATT` and `MATL` chunks.
+fn write_palette_and_materials<W: Write + Seek>(
+    chunk_writer: &mut ChunkWriter<W>,
+    vox: &VoxData,
+    options: &WriterOptions,
+) -> Result<(), Error> {
+    if options.always_write_palette || !vox.palette.is_default() {
+        chunk_writer.child_content_writer(ChunkId::Rgba, |writer| {
+            vox.palette.write(writer)?;
+            Ok(())
+        })?;
+    }
+
+    // Write legacy materials, sorted by ID, so the output doesn't depend on the
+    // hash map's order.
+    let mut materials = vox.materials.iter().collect::<Vec<_>>();
+    materials.sort_by_key(|(material_id, _)| *material_id);
+    for (material_id, material) in materials {
+        chunk_writer.child_content_writer(ChunkId::Matt, |writer| {
+            material.write_matt(material_id, writer)?;
+            Ok(())
+        })?;
+    }
+
+    if options.canonical_order {
+        let mut material_properties = vox.material_properties.clone();
+        material_properties.sort_by_key(|properties| properties.material_id);
+        for properties in &mut material_properties {
+            properties.attributes.sort_keys();
         }
+        write_material_properties(chunk_writer, &material_properties)?;
+    }
+    else {
+        write_material_properties(chunk_writer, &vox.material_properties)?;
+    }
+
+    Ok(())
+}
+
+fn write_material_properties<W: Write + Seek>(
+    chunk_writer: &mut ChunkWriter<W>,
+    material_properties: &[MaterialProperties],
+) -> Result<(), Error> {
+    for properties in material_properties {
+        chunk_writer.child_content_writer(ChunkId::Matl, |writer| properties.write(writer))?;
+    }
+    Ok(())
+}
+
+fn write_custom_chunks<'a, W: Write + Seek>(
+    chunk_writer: &mut ChunkWriter<W>,
+    custom_chunks: impl IntoIterator<Item = &'a CustomChunk>,
+) -> Result<(), Error> {
+    for custom_chunk in custom_chunks {
+        chunk_writer.child_content_writer(custom_chunk.id(), |writer| {
+            writer.write_all(&custom_chunk.encode()?)?;
+            Ok(())
+        })?;
+    }
+    Ok(())
+}
+
+/// Writes the `SIZE` and `XYZI` chunks for a model.
+fn write_model<W: Write + Seek>(
+    chunk_writer: &mut ChunkWriter<W>,
+    model: &Model,
+    sort_voxels: bool,
+) -> Result<(), Error> {
+    // Write SIZE chunk
+    chunk_writer.child_content_writer(ChunkId::Size, |writer| {
+        model.size.write(writer)?;
+        Ok(())
+    })?;
 
+    let mut voxels = model.voxels.iter().collect::<Vec<_>>();
+    if sort_voxels {
+        voxels.sort_by_key(|voxel| {
+            let point = voxel.point;
+            (
+                point.z as u8,
+                point.y as u8,
+                point.x as u8,
+                voxel.color_index,
+            )
+        });
+    }
+
+    // Write XYZI chunk
+    chunk_writer.child_content_writer(ChunkId::Xyzi, |mut writer| {
+        writer.write_u32::<LE>(voxels.len().try_into()?)?;
+        for voxel in &voxels {
+            voxel.write(&mut writer)?;
+        }
         Ok(())
     })
 }
 
+/// Writes the nodes and layers, or the default scene graph, if enabled.
+fn write_scene_graph<W: Write + Seek>(
+    chunk_writer: &mut ChunkWriter<W>,
+    vox: &VoxData,
+    options: &WriterOptions,
+) -> Result<(), Error> {
+    let (default_nodes, default_layer);
+    let (mut nodes, mut layers) = (
+        vox.nodes.iter().collect::<Vec<_>>(),
+        vox.layers.iter().collect::<Vec<_>>(),
+    );
+    if nodes.is_empty() && options.write_default_scene_graph {
+        let (scene_graph, layer) = default_scene_graph(vox.models.len())?;
+        default_nodes = scene_graph;
+        nodes = default_nodes.iter().collect();
+        if layers.is_empty() {
+            default_layer = layer;
+            layers.push(&default_layer);
+        }
+    }
+
+    if options.canonical_order {
+        nodes.sort_by_key(|node| node.node_id());
+        layers.sort_by_key(|layer| layer.node_id);
+    }
+
+    for node in nodes {
+        if let Node::Shape(shape) = node {
+            let num_models = vox.models.len();
+            if let Some(model) = shape
+                .models
+                .iter()
+                .find(|model| model.model_id as usize >= num_models)
+            {
+                return Err(Error::InvalidModelId {
+                    node_id: shape.node_id,
+                    model_id: model.model_id,
+                    num_models,
+                });
+            }
+        }
+
+        chunk_writer.child_content_writer(node.chunk_id(), |writer| {
+            if options.canonical_order {
+                canonical_node(node).write(writer)?;
+            }
+            else {
+                node.write(writer)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    for layer in layers {
+        chunk_writer.child_content_writer(ChunkId::Layr, |writer| {
+            if options.canonical_order {
+                let mut layer = layer.clone();
+                layer.attributes.sort_keys();
+                layer.write(writer)?;
+            }
+            else {
+                layer.write(writer)?;
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Returns a copy of `node` with all attributes sorted by key.
+fn canonical_node(node: &Node) -> Node {
+    let mut node = node.clone();
+    match &mut node {
+        Node::Transform(transform) => {
+            transform.attributes.sort_keys();
+            transform.frames.iter_mut().for_each(Attributes::sort_keys);
+        }
+        Node::Group(group) => group.attributes.sort_keys(),
+        Node::Shape(shape) => {
+            shape.attributes.sort_keys();
+            for model in &mut shape.models {
+                model.attributes.sort_keys();
+            }
+        }
+    }
+    node
+}
+
+/// Creates a scene graph, in which each model is placed at the origin by its
+/// own transform, and a layer that all transforms are on.
+///
+/// Node IDs are assigned like MagicaVoxel does: The root transform is 0, the
+/// root group 1, and the transform and shape of model `i` are `2 + 2 * i` and
+/// `3 + 2 * i`.
+pub(crate) fn default_scene_graph(num_models: usize) -> Result<(Vec<Node>, Layer), Error> {
+    let mut nodes = vec![];
+    let mut children = vec![];
+
+    for model_index in 0..num_models {
+        let model_id: u32 = model_index.try_into()?;
+        // Check that the shape's ID, which is one more, fits as well.
+        let shape_id = model_id
+            .checked_mul(2)
+            .and_then(|id| id.checked_add(3))
+            .ok_or(Error::NodeIdOverflow)?;
+        children.push(shape_id - 1);
+
+        nodes.push(Node::Transform(default_transform(
+            shape_id - 1,
+            shape_id,
+            Some(0),
+        )));
+        nodes.push(Node::Shape(Shape {
+            node_id: shape_id,
+            attributes: Attributes::new(),
+            models: vec![ShapeModel {
+                model_id,
+                attributes: Attributes::new(),
+            }],
+        }));
+    }
+
+    let root = [
+        Node::Transform(default_transform(0, 1, None)),
+        Node::Group(Group {
+            node_id: 1,
+            attributes: Attributes::new(),
+            children,
+        }),
+    ];
+    nodes.splice(0..0, root);
+
+    let layer = Layer {
+        node_id: 0,
+        attributes: Attributes::new(),
+        reserved_id: None,
+    };
+
+    Ok((nodes, layer))
+}
+
+/// Creates a transform node with a single frame without attributes.
+fn default_transform(node_id: u32, child_node_id: u32, layer_id: Option<u32>) -> Transform {
+    Transform {
+        node_id,
+        attributes: Attributes::new(),
+        child_node_id,
+        reserved_id: None,
+        layer_id,
+        frames: vec![Attributes::new()],
+    }
+}
+
 /// Encode [`VoxData`] and return bytes as `Vec<u8>`.
 pub fn to_vec(vox: &VoxData) -> Result<Vec<u8>, Error> {
+    to_vec_with_options(vox, &WriterOptions::default())
+}
+
+/// Encode [`VoxData`] with the layout specified by `options` and return bytes
+/// as `Vec<u8>`.
+pub fn to_vec_with_options(vox: &VoxData, options: &WriterOptions) -> Result<Vec<u8>, Error> {
     //let mut buf = Vec::with_capacity(vox.size_hint());
     let mut buf = Vec::with_capacity(1024);
-    to_writer(Cursor::new(&mut buf), vox)?;
+    to_writer_with_options(Cursor::new(&mut buf), vox, options)?;
     buf.shrink_to_fit();
     Ok(buf)
 }
 
 /// Writes VOX data to the specified path.
 pub fn to_file<P: AsRef<Path>>(path: P, vox: &VoxData) -> Result<(), Error> {
-    to_writer(OpenOptions::new().create(true).write(true).open(path)?, vox)
+    to_writer(File::create(path)?, vox)
+}
+
+/// Writes VOX data with the layout specified by `options` to the specified
+/// path.
+pub fn to_file_with_options<P: AsRef<Path>>(
+    path: P,
+    vox: &VoxData,
+    options: &WriterOptions,
+) -> Result<(), Error> {
+    to_writer_with_options(File::create(path)?, vox, options)
+}
+
+/// Writes VOX data compressed with `compression` to the specified path.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub fn to_file_compressed<P: AsRef<Path>>(
+    path: P,
+    vox: &VoxData,
+    compression: crate::compression::Compression,
+) -> Result<(), Error> {
+    let data = to_vec(vox)?;
+    let mut writer = std::io::BufWriter::new(File::create(path)?);
+    compression.compress(&data, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{
+        Cursor,
+        ErrorKind,
+    };
+
+    use super::{
+        main_chunk_writer,
+        to_vec,
+        to_vec_with_options,
+        Error,
+        WriterOptions,
+    };
+    use crate::{
+        chunk::{
+            ChunkId,
+            ChunkTree,
+        },
+        data::VoxData,
+        from_slice,
+        types::{
+            Attributes,
+            Model,
+            Node,
+            Shape,
+            ShapeModel,
+            Vector,
+            Version,
+            Voxel,
+        },
+    };
+
+    #[test]
+    fn errors_contain_the_chunk_path() {
+        let mut buf = vec![];
+        let error = main_chunk_writer(Cursor::new(&mut buf), Version::default(), |chunk_writer| {
+            chunk_writer.child_content_writer(ChunkId::NTrn, |_| Ok(()))?;
+            chunk_writer.child_content_writer(ChunkId::NGrp, |_| Ok(()))?;
+            chunk_writer.child_content_writer(ChunkId::NTrn, |_| {
+                Err(std::io::Error::from(ErrorKind::Other).into())
+            })
+        })
+        .unwrap_err();
+
+        assert!(matches!(error.root(), Error::Io(_)));
+        assert_eq!(
+            error.context().unwrap().chunk_path.to_string(),
+            "MAIN/nTRN#1"
+        );
+    }
+
+    #[test]
+    fn it_writes_the_chosen_version() {
+        let mut vox = VoxData {
+            version: Version::V150,
+            ..VoxData::default()
+        };
+        assert_eq!(to_vec(&vox).unwrap()[4..8], 150u32.to_le_bytes());
+
+        vox.version = Version(201);
+        assert!(matches!(
+            to_vec(&vox).unwrap_err(),
+            Error::UnsupportedFileVersion { .. }
+        ));
+    }
+
+    fn two_models() -> VoxData {
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(2, 2, 2),
+            voxels: vec![Voxel::new([1, 1, 1], 1), Voxel::new([0, 0, 1], 2)],
+        });
+        vox.models.push(Model {
+            size: Vector::new(1, 1, 1),
+            voxels: vec![Voxel::new([0, 0, 0], 3)],
+        });
+        vox
+    }
+
+    fn chunk_ids(data: &[u8]) -> Vec<ChunkId> {
+        let tree = ChunkTree::from_slice(data).unwrap();
+        tree.root.children.iter().map(|node| node.id).collect()
+    }
+
+    #[test]
+    fn it_writes_the_chunks_selected_by_the_options() {
+        let vox = two_models();
+        assert_eq!(
+            chunk_ids(&to_vec(&vox).unwrap()),
+            [ChunkId::Size, ChunkId::Xyzi, ChunkId::Size, ChunkId::Xyzi]
+        );
+
+        let options = WriterOptions {
+            write_pack: true,
+            always_write_palette: true,
+            write_default_scene_graph: true,
+            canonical_order: true,
+            ..WriterOptions::default()
+        };
+        let data = to_vec_with_options(&vox, &options).unwrap();
+        assert_eq!(
+            chunk_ids(&data),
+            [
+                ChunkId::Pack,
+                ChunkId::Size,
+                ChunkId::Xyzi,
+                ChunkId::Size,
+                ChunkId::Xyzi,
+                ChunkId::NTrn,
+                ChunkId::NGrp,
+                ChunkId::NTrn,
+                ChunkId::NShp,
+                ChunkId::NTrn,
+                ChunkId::NShp,
+                ChunkId::Layr,
+                ChunkId::Rgba,
+            ]
+        );
+
+        let read = from_slice(&data).unwrap();
+        assert_eq!(read.nodes.len(), 6);
+        assert_eq!(read.layers.len(), 1);
+        match read.node(1).unwrap() {
+            Node::Group(group) => assert_eq!(group.children, [2, 4]),
+            node => panic!("Expected group, but got: {:?}", node),
+        }
+        match read.node(4).unwrap() {
+            Node::Transform(transform) => {
+                assert_eq!(transform.child_node_id, 5);
+                assert_eq!(transform.layer_id, Some(0));
+            }
+            node => panic!("Expected transform, but got: {:?}", node),
+        }
+    }
+
+    #[test]
+    fn it_keeps_the_scene_graph_of_version_150_files() {
+        let vox = from_slice(include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        )))
+        .unwrap();
+        assert_eq!(vox.version, Version::V150);
+
+        let read = from_slice(&to_vec(&vox).unwrap()).unwrap();
+        assert_eq!(read.version, Version::V150);
+        assert_eq!(read.nodes.len(), vox.nodes.len());
+        assert_eq!(read.layers.len(), vox.layers.len());
+        assert_eq!(read.models.len(), vox.models.len());
+    }
+
+    #[test]
+    fn it_sorts_voxels() {
+        let options = WriterOptions {
+            sort_voxels: true,
+            ..WriterOptions::default()
+        };
+        let vox = from_slice(&to_vec_with_options(&two_models(), &options).unwrap()).unwrap();
+        assert_eq!(
+            vox.models[0].voxels,
+            [Voxel::new([0, 0, 1], 2), Voxel::new([1, 1, 1], 1)]
+        );
+
+        // Coordinates are unsigned, so 200 comes after 1, although it's negative as
+        // an `i8`.
+        let mut vox = VoxData::default();
+        vox.models.push(Model {
+            size: Vector::new(256, 1, 1),
+            voxels: vec![Voxel::new([200u8 as i8, 0, 0], 1), Voxel::new([1, 0, 0], 2)],
+        });
+        let vox = from_slice(&to_vec_with_options(&vox, &options).unwrap()).unwrap();
+        assert_eq!(
+            vox.models[0].voxels,
+            [Voxel::new([1, 0, 0], 2), Voxel::new([200u8 as i8, 0, 0], 1)]
+        );
+    }
+
+    #[test]
+    fn it_round_trips_the_scene_graph() {
+        let data = include_bytes!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../test_files/test_multiple_models.vox"
+        ));
+        let scene_graph = |data: &[u8]| {
+            let tree = ChunkTree::from_slice(data).unwrap();
+            tree.root
+                .children
+                .into_iter()
+                .filter(|node| {
+                    matches!(
+                        node.id,
+                        ChunkId::NTrn | ChunkId::NGrp | ChunkId::NShp | ChunkId::Layr
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let written = to_vec(&from_slice(data).unwrap()).unwrap();
+        assert_eq!(scene_graph(&written), scene_graph(data));
+    }
+
+    #[test]
+    fn it_rejects_shapes_with_invalid_model_ids() {
+        let mut vox = two_models();
+        vox.nodes.push(Node::Shape(Shape {
+            node_id: 3,
+            attributes: Attributes::new(),
+            models: vec![ShapeModel {
+                model_id: 2,
+                attributes: Attributes::new(),
+            }],
+        }));
+        assert!(matches!(
+            to_vec(&vox).unwrap_err().root(),
+            Error::InvalidModelId {
+                node_id: 3,
+                model_id: 2,
+                num_models: 2
+            }
+        ));
+    }
 }
@@ -1,12 +1,12 @@
 use std::{
     collections::HashSet,
-    convert::TryInto,
     fs::{
+        self,
         File,
-        OpenOptions,
     },
     io::{
-        Read,
+        BufWriter,
+        Cursor,
         Write,
     },
     path::{
@@ -17,25 +17,50 @@ use std::{
 
 use color_eyre::eyre::{
     bail,
+    eyre,
     Error,
 };
 use image::io::Reader as ImageReader;
 use structopt::StructOpt;
 use vox_format::{
+    bake::{
+        bake,
+        OverlapPolicy,
+    },
     chunk::{
-        read_main_chunk,
+        transform_chunks,
         Chunk,
+        ChunkAction,
         ChunkId,
-        ChunkWriter,
+    },
+    compression::{
+        decompress_if_compressed,
+        Compression,
     },
     default_palette::DEFAULT_PALETTE,
     from_file,
+    gltf::{
+        export,
+        GltfFormat,
+        GltfOptions,
+    },
+    merge::{
+        merge,
+        MergeInput,
+    },
+    reader::Limits,
+    split::{
+        split,
+        SplitMode,
+    },
+    to_vec,
     types::{
         ColorIndex,
         Model,
         Palette,
+        Vector,
     },
-    writer::main_chunk_writer,
+    VoxData,
 };
 
 /// Tools for inspection and manipulation of MagicaVoxel VOX files.
@@ -58,6 +83,10 @@ enum Args {
         #[structopt(short = "o", long = "output")]
         output: Option<PathBuf>,
 
+        /// Compress the output file with `gzip` or `zstd`.
+        #[structopt(long = "compress")]
+        compress: Option<Compression>,
+
         /// The input file from which the chunks will be stripped.
         input: PathBuf,
     },
@@ -120,6 +149,109 @@ enum Args {
         /// the file extension.
         #[structopt(short = "o", long = "output")]
         output: Option<PathBuf>,
+
+        /// Compress the output file with `gzip` or `zstd`.
+        #[structopt(long = "compress")]
+        compress: Option<Compression>,
+    },
+    /// Merges several VOX files into one.
+    ///
+    /// Each input is placed in its own group, which is named after the input
+    /// file. The palettes of the inputs are merged into one palette. If the
+    /// inputs use more than 255 colors, similar colors are merged.
+    Merge {
+        /// The input files.
+        #[structopt(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// The offset of an input, as `X,Y,Z`. If specified, there must be
+        /// one offset for each input.
+        #[structopt(short = "t", long = "offset", parse(try_from_str = parse_vector), number_of_values = 1)]
+        offsets: Vec<Vector<i32>>,
+
+        /// The path for the output file.
+        #[structopt(short = "o", long = "output")]
+        output: PathBuf,
+
+        /// Compress the output file with `gzip` or `zstd`.
+        #[structopt(long = "compress")]
+        compress: Option<Compression>,
+    },
+    /// Splits a VOX file into one file per object.
+    ///
+    /// By default each shape is written to its own file. The files are named
+    /// after the objects (`_name`), and keep the palette and materials of the
+    /// input file. Each object is moved to the origin. If names are the same
+    /// after replacing characters that can't be used in file names, `-N` is
+    /// appended.
+    Split {
+        /// The input file that will be split.
+        input: PathBuf,
+
+        /// The directory for the output files. Defaults to the directory of
+        /// the input file.
+        #[structopt(short = "o", long = "output")]
+        output: Option<PathBuf>,
+
+        /// Writes each object directly below the root to its own file, instead
+        /// of each shape. Groups are kept together.
+        #[structopt(short = "g", long = "top-level")]
+        top_level: bool,
+
+        /// Compress the output files with `gzip` or `zstd`.
+        #[structopt(long = "compress")]
+        compress: Option<Compression>,
+    },
+    /// Bakes all objects of a VOX file into a single model.
+    ///
+    /// The translations and rotations of all objects are applied to their
+    /// voxels. The resulting model must fit into 256x256x256 voxels. If
+    /// objects overlap, the voxel of the last object is kept, unless
+    /// `--first-wins` or `--layer-priority` is specified.
+    Bake {
+        /// The input file that will be baked.
+        input: PathBuf,
+
+        /// Keep the voxel of the first object, if objects overlap.
+        #[structopt(long = "first-wins")]
+        first_wins: bool,
+
+        /// Layers by priority, as names or IDs. If objects overlap, the voxel
+        /// of the object whose layer comes first is kept. Objects on other
+        /// layers have the lowest priority.
+        #[structopt(short = "l", long = "layer-priority", number_of_values = 1)]
+        layer_priority: Vec<String>,
+
+        /// Output file. Defaults to `INPUT.baked.vox` where `INPUT` is the
+        /// input file path without `.vox` file extension.
+        #[structopt(short = "o", long = "output")]
+        output: Option<PathBuf>,
+
+        /// Compress the output file with `gzip` or `zstd`.
+        #[structopt(long = "compress")]
+        compress: Option<Compression>,
+    },
+    /// Exports a VOX file as glTF 2.0 scene.
+    ///
+    /// Each model is converted to a triangle mesh, and the scene graph is
+    /// exported as node hierarchy. The palette is embedded as texture, and
+    /// metal, glass and emissive materials are converted to PBR materials.
+    Export {
+        /// The input file that will be exported.
+        input: PathBuf,
+
+        /// The output format: `glb` or `gltf`.
+        #[structopt(short = "f", long = "format", default_value = "glb")]
+        format: GltfFormat,
+
+        /// Store ambient occlusion as vertex colors.
+        #[structopt(long = "ambient-occlusion")]
+        ambient_occlusion: bool,
+
+        /// Output file. Defaults to the input file path with the extension of
+        /// the output format.
+        #[structopt(short = "o", long = "output")]
+        output: Option<PathBuf>,
     },
 }
 
@@ -159,6 +291,7 @@ impl Args {
                 keep,
                 input,
                 output,
+                compress,
             } => {
                 let strip = if !strip.is_empty() && !keep.is_empty() {
                     bail!("The options `--strip` and `--keep` are exlusive");
@@ -177,13 +310,13 @@ impl Args {
 
                 let output = output.unwrap_or_else(|| default_output_path(&input, "stripped"));
 
-                copy_map_chunks(&input, &output, |_reader, chunk, _writer| {
+                transform_file(&input, &output, compress, |_reader, chunk| {
                     if !strip.strip(chunk.id()) {
-                        Ok(true)
+                        Ok(ChunkAction::Keep)
                     }
                     else {
                         log::trace!("Stripping chunk: {:?}", chunk.id());
-                        Ok(false)
+                        Ok(ChunkAction::Remove)
                     }
                 })?;
             }
@@ -241,6 +374,7 @@ impl Args {
                 input,
                 palette,
                 output,
+                compress,
             } => {
                 let palette = if let Some(palette) = palette {
                     log::debug!("Loading palette: {}", palette.display());
@@ -260,22 +394,149 @@ impl Args {
 
                 let output = output.unwrap_or_else(|| default_output_path(&input, "new-palette"));
 
-                copy_map_chunks(&input, &output, |_reader, chunk, writer| {
+                transform_file(&input, &output, compress, |_reader, chunk| {
                     if matches!(chunk.id(), ChunkId::Rgba) {
                         log::debug!("Replacing RGBA chunk");
                         log::debug!("{:?}", palette.get(ColorIndex::from(69)));
 
                         // Replace RGBA chunk
-                        writer
-                            .child_content_writer(ChunkId::Rgba, |writer| palette.write(writer))?;
+                        let mut content = vec![];
+                        palette.write(&mut content)?;
 
-                        Ok(false)
+                        Ok(ChunkAction::ReplaceContent(content))
                     }
                     else {
-                        Ok(true)
+                        Ok(ChunkAction::Keep)
                     }
                 })?;
             }
+            Self::Merge {
+                inputs,
+                offsets,
+                output,
+                compress,
+            } => {
+                if !offsets.is_empty() && offsets.len() != inputs.len() {
+                    bail!(
+                        "Expected {} offsets, but {} were specified",
+                        inputs.len(),
+                        offsets.len()
+                    );
+                }
+
+                let files = inputs
+                    .iter()
+                    .map(|input| {
+                        log::debug!("Reading input: {}", input.display());
+                        from_file(input)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let merge_inputs = files
+                    .iter()
+                    .zip(&inputs)
+                    .enumerate()
+                    .map(|(i, (vox, input))| {
+                        let mut merge_input =
+                            MergeInput::new(vox, offsets.get(i).copied().unwrap_or_default());
+                        merge_input.name = input
+                            .file_stem()
+                            .map(|name| name.to_string_lossy().into_owned());
+                        merge_input
+                    })
+                    .collect::<Vec<_>>();
+
+                let vox = merge(&merge_inputs)?;
+
+                log::debug!("Writing output: {}", output.display());
+                write_output(&output, &to_vec(&vox)?, compress)?;
+            }
+            Self::Split {
+                input,
+                output,
+                top_level,
+                compress,
+            } => {
+                let output = output
+                    .unwrap_or_else(|| input.parent().map(ToOwned::to_owned).unwrap_or_default());
+                fs::create_dir_all(&output)?;
+
+                let vox = from_file(&input)?;
+                let mode = if top_level {
+                    SplitMode::TopLevel
+                }
+                else {
+                    SplitMode::Shapes
+                };
+
+                let mut file_names = HashSet::new();
+                for part in split(&vox, mode)? {
+                    let file_name = unique_file_name(&mut file_names, &part.name);
+                    let path = output.join(format!("{}.vox", file_name));
+                    log::debug!("Writing output: {}", path.display());
+                    write_output(&path, &to_vec(&part.vox)?, compress)?;
+                }
+            }
+            Self::Bake {
+                input,
+                first_wins,
+                layer_priority,
+                output,
+                compress,
+            } => {
+                let vox = from_file(&input)?;
+
+                let policy = if !layer_priority.is_empty() {
+                    if first_wins {
+                        bail!("The options `--first-wins` and `--layer-priority` are exclusive");
+                    }
+                    let layers = layer_priority
+                        .iter()
+                        .map(|layer| {
+                            match vox.layer_by_name(layer) {
+                                Some(layer) => Ok(layer.node_id),
+                                None => {
+                                    layer
+                                        .parse()
+                                        .map_err(|_| eyre!("Layer not found: {}", layer))
+                                }
+                            }
+                        })
+                        .collect::<Result<_, Error>>()?;
+                    OverlapPolicy::LayerPriority(layers)
+                }
+                else if first_wins {
+                    OverlapPolicy::FirstWins
+                }
+                else {
+                    OverlapPolicy::LastWins
+                };
+
+                let model = bake(&vox, &policy)?;
+
+                let mut baked = VoxData::default();
+                baked.models.push(model);
+                baked.palette = vox.palette;
+                baked.materials = vox.materials;
+                baked.material_properties = vox.material_properties;
+
+                let output = output.unwrap_or_else(|| default_output_path(&input, "baked"));
+                log::debug!("Writing output: {}", output.display());
+                write_output(&output, &to_vec(&baked)?, compress)?;
+            }
+            Self::Export {
+                input,
+                format,
+                ambient_occlusion,
+                output,
+            } => {
+                let vox = from_file(&input)?;
+                let data = export(&vox, format, &GltfOptions { ambient_occlusion })?;
+
+                let output = output.unwrap_or_else(|| input.with_extension(format.extension()));
+                log::debug!("Writing output: {}", output.display());
+                fs::write(&output, data)?;
+            }
         }
 
         Ok(())
@@ -290,70 +551,87 @@ fn print_model(i: usize, model: &Model) {
     }
 }
 
+fn parse_vector(s: &str) -> Result<Vector<i32>, Error> {
+    let components = s
+        .split(',')
+        .map(|c| c.trim().parse())
+        .collect::<Result<Vec<i32>, _>>()?;
+
+    match components[..] {
+        [x, y, z] => Ok(Vector::new(x, y, z)),
+        _ => bail!("Expected 3 components separated by `,`: {}", s),
+    }
+}
+
+/// Replaces characters that shouldn't be used in file names.
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_. ".contains(c) {
+                c
+            }
+            else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Like [`file_name`], but appends `-N`, if the file name is already in
+/// `names`. Names are compared case-insensitively, since the file system might
+/// be case-insensitive.
+fn unique_file_name(names: &mut HashSet<String>, name: &str) -> String {
+    let name = file_name(name);
+    let mut unique = name.clone();
+    let mut n = 2;
+    while !names.insert(unique.to_lowercase()) {
+        unique = format!("{}-{}", name, n);
+        n += 1;
+    }
+    unique
+}
+
 fn default_output_path<P: AsRef<Path>>(input: P, postfix: &str) -> PathBuf {
     let input = input.as_ref();
     let ext = input.extension().and_then(|s| s.to_str()).unwrap_or("vox");
     input.with_extension(format!("{}.{}", postfix, ext))
 }
 
-fn copy_map_chunks<
+fn transform_file<
     P: AsRef<Path>,
     Q: AsRef<Path>,
-    F: FnMut(&mut File, &Chunk, &mut ChunkWriter<File>) -> Result<bool, vox_format::writer::Error>,
+    F: FnMut(&mut Cursor<Vec<u8>>, &Chunk) -> Result<ChunkAction, vox_format::writer::Error>,
 >(
     input: P,
     output: Q,
-    mut f: F,
+    compress: Option<Compression>,
+    f: F,
 ) -> Result<(), Error> {
     let input = input.as_ref();
     let output = output.as_ref();
 
     log::debug!("Reading input: {}", input.display());
-    let mut reader = File::open(&input)?;
-    let (main_chunk, version) = read_main_chunk(&mut reader)?;
+    let data = fs::read(input)?;
+    let data = decompress_if_compressed(Cursor::new(&data), &Limits::default())?.unwrap_or(data);
+
+    let mut buf = vec![];
+    transform_chunks(Cursor::new(data), Cursor::new(&mut buf), f)?;
 
     log::debug!("Writing output: {}", output.display());
-    let writer = OpenOptions::new().create(true).write(true).open(output)?;
+    write_output(output, &buf, compress)?;
 
-    let mut chunks = vec![];
+    Ok(())
+}
 
-    for r in main_chunk.children(&mut reader) {
-        let chunk = r?;
-        chunks.push(chunk);
+fn write_output(output: &Path, data: &[u8], compress: Option<Compression>) -> Result<(), Error> {
+    let mut writer = BufWriter::new(File::create(output)?);
+    if let Some(compression) = compress {
+        compression.compress(data, &mut writer)?;
     }
-
-    main_chunk_writer(writer, version, |chunk_writer| {
-        let mut buf = vec![];
-
-        for chunk in &chunks {
-            if f(&mut reader, chunk, chunk_writer)? {
-                // Copy chunk
-                log::trace!("Copying chunk: {:?}", chunk.id());
-
-                buf.clear();
-                buf.reserve(chunk.content_len().try_into()?);
-
-                chunk.content(&mut reader)?.read_to_end(&mut buf)?;
-
-                chunk_writer.child_content_writer(chunk.id(), |writer| {
-                    writer.write_all(&buf)?;
-                    Ok(())
-                })?;
-
-                // TODO: If we move the copy function into `vox-format`, we can make use of the
-                // fact that we can read/write the children as a blob.
-                if chunk.children_len() != 0 {
-                    //todo!("TODO: Copy children. This is not implemented, because at this point
-                    // all supported chunk types (except `MAIN`) have no children. Please open an
-                    // issue, if you need this feature.");
-                    log::warn!("Unexpected chunk with children. Ignoring children");
-                }
-            }
-        }
-
-        Ok(())
-    })?;
-
+    else {
+        writer.write_all(data)?;
+    }
+    writer.flush()?;
     Ok(())
 }
 